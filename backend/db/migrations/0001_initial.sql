@@ -0,0 +1,48 @@
+-- The schema the backend shipped with, before migrations were versioned
+
+CREATE TABLE "voice" (
+    "id"            TEXT NOT NULL UNIQUE,
+    "name"          TEXT NOT NULL,
+    "description"   TEXT NOT NULL,
+    "prefix"        TEXT NOT NULL,
+    "created_at"    INTEGER NOT NULL,
+    "deleted_at"    INTEGER,
+    PRIMARY KEY("id")
+);
+
+CREATE INDEX "enabled_voices" ON "voice" (
+    "deleted_at" ASC,
+    "id" ASC
+);
+
+CREATE TABLE "conversation" (
+    "id"            TEXT NOT NULL UNIQUE,
+    "user_id"       TEXT NOT NULL,
+    "name"          TEXT NOT NULL,
+    "voice_id"      TEXT NOT NULL,
+    "created_at"    INTEGER NOT NULL,
+    "deleted_at"    INTEGER,
+    FOREIGN KEY("voice_id") REFERENCES "voice"("id"),
+    PRIMARY KEY("id")
+);
+
+CREATE INDEX "enabled_user_conversations" ON "conversation" (
+    "deleted_at" ASC,
+    "user_id" ASC
+);
+
+CREATE TABLE "message" (
+    "id"              TEXT NOT NULL UNIQUE,
+    "conversation_id" TEXT NOT NULL,
+    "author"          TEXT NOT NULL,
+    "content"         TEXT NOT NULL,
+    "created_at"      INTEGER NOT NULL,
+    "deleted_at"      INTEGER,
+    FOREIGN KEY("conversation_id") REFERENCES "conversation"("id"),
+    PRIMARY KEY("id")
+);
+
+CREATE INDEX "enabled_messages_by_conversations" ON "message" (
+    "deleted_at" ASC,
+    "conversation_id" ASC
+);
@@ -0,0 +1,3 @@
+-- The maximum number of prompt tokens for a voice
+
+ALTER TABLE "voice" ADD COLUMN "context_budget" INTEGER;
@@ -0,0 +1,8 @@
+-- How the replies of a voice are sampled
+
+ALTER TABLE "voice" ADD COLUMN "temperature" REAL;
+ALTER TABLE "voice" ADD COLUMN "top_k" INTEGER;
+ALTER TABLE "voice" ADD COLUMN "top_p" REAL;
+ALTER TABLE "voice" ADD COLUMN "repeat_penalty" REAL;
+ALTER TABLE "voice" ADD COLUMN "max_tokens" INTEGER;
+ALTER TABLE "voice" ADD COLUMN "stop_sequences" TEXT;
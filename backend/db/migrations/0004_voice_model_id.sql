@@ -0,0 +1,3 @@
+-- The model in the model registry that generates the replies of a voice
+
+ALTER TABLE "voice" ADD COLUMN "model_id" TEXT;
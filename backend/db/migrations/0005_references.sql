@@ -0,0 +1,40 @@
+-- SQLite does not say which column a foreign key violation is about,
+-- these checks raise errors that name it, in the format of the other constraint errors
+
+CREATE TRIGGER "conversation_voice_exists"
+BEFORE INSERT ON "conversation"
+WHEN NOT EXISTS (SELECT 1 FROM "voice" WHERE "id" = NEW."voice_id")
+BEGIN
+    SELECT RAISE(ABORT, 'FOREIGN KEY constraint failed: conversation.voice_id');
+END;
+
+CREATE TRIGGER "conversation_voice_exists_on_update"
+BEFORE UPDATE OF "voice_id" ON "conversation"
+WHEN NOT EXISTS (SELECT 1 FROM "voice" WHERE "id" = NEW."voice_id")
+BEGIN
+    SELECT RAISE(ABORT, 'FOREIGN KEY constraint failed: conversation.voice_id');
+END;
+
+CREATE TRIGGER "message_conversation_exists"
+BEFORE INSERT ON "message"
+WHEN NOT EXISTS (SELECT 1 FROM "conversation" WHERE "id" = NEW."conversation_id")
+BEGIN
+    SELECT RAISE(ABORT, 'FOREIGN KEY constraint failed: message.conversation_id');
+END;
+
+CREATE TRIGGER "message_conversation_exists_on_update"
+BEFORE UPDATE OF "conversation_id" ON "message"
+WHEN NOT EXISTS (SELECT 1 FROM "conversation" WHERE "id" = NEW."conversation_id")
+BEGIN
+    SELECT RAISE(ABORT, 'FOREIGN KEY constraint failed: message.conversation_id');
+END;
+
+-- Deleting a conversation deletes its messages
+CREATE TRIGGER "conversation_soft_delete"
+AFTER UPDATE OF "deleted_at" ON "conversation"
+WHEN OLD."deleted_at" IS NULL AND NEW."deleted_at" IS NOT NULL
+BEGIN
+    UPDATE "message"
+    SET "deleted_at" = NEW."deleted_at"
+    WHERE "conversation_id" = NEW."id" AND "deleted_at" IS NULL;
+END;
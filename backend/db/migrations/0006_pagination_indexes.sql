@@ -0,0 +1,13 @@
+-- Lists are paginated in order of creation, these indexes keep every page fast
+
+CREATE INDEX "user_conversations_by_creation" ON "conversation" (
+    "user_id" ASC,
+    "created_at" ASC,
+    "id" ASC
+);
+
+CREATE INDEX "conversation_messages_by_creation" ON "message" (
+    "conversation_id" ASC,
+    "created_at" ASC,
+    "id" ASC
+);
@@ -0,0 +1,30 @@
+-- A full-text index of the content of messages, kept in sync with the message table
+
+CREATE VIRTUAL TABLE "message_search" USING fts5(
+    "content",
+    content = 'message',
+    content_rowid = 'rowid'
+);
+
+INSERT INTO "message_search" ("message_search") VALUES ('rebuild');
+
+CREATE TRIGGER "message_search_insert"
+AFTER INSERT ON "message"
+BEGIN
+    INSERT INTO "message_search" ("rowid", "content") VALUES (NEW."rowid", NEW."content");
+END;
+
+CREATE TRIGGER "message_search_update"
+AFTER UPDATE OF "content" ON "message"
+BEGIN
+    INSERT INTO "message_search" ("message_search", "rowid", "content")
+    VALUES ('delete', OLD."rowid", OLD."content");
+    INSERT INTO "message_search" ("rowid", "content") VALUES (NEW."rowid", NEW."content");
+END;
+
+CREATE TRIGGER "message_search_delete"
+AFTER DELETE ON "message"
+BEGIN
+    INSERT INTO "message_search" ("message_search", "rowid", "content")
+    VALUES ('delete', OLD."rowid", OLD."content");
+END;
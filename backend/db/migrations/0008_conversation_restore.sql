@@ -0,0 +1,9 @@
+-- Restoring a conversation restores the messages that were deleted along with it
+CREATE TRIGGER "conversation_restore"
+AFTER UPDATE OF "deleted_at" ON "conversation"
+WHEN OLD."deleted_at" IS NOT NULL AND NEW."deleted_at" IS NULL
+BEGIN
+    UPDATE "message"
+    SET "deleted_at" = NULL
+    WHERE "conversation_id" = NEW."id" AND "deleted_at" = OLD."deleted_at";
+END;
@@ -0,0 +1,23 @@
+-- Registered users, and the sessions they are logged in with
+
+CREATE TABLE "user" (
+    "id"            TEXT NOT NULL UNIQUE,
+    "username"      TEXT NOT NULL UNIQUE,
+    "password_hash" TEXT NOT NULL,
+    "created_at"    INTEGER NOT NULL,
+    PRIMARY KEY("id")
+);
+
+-- The id is a hash of the token in the session cookie, so a leaked table can't log anyone in
+CREATE TABLE "session" (
+    "id"            TEXT NOT NULL UNIQUE,
+    "user_id"       TEXT NOT NULL,
+    "created_at"    INTEGER NOT NULL,
+    "expires_at"    INTEGER NOT NULL,
+    FOREIGN KEY("user_id") REFERENCES "user"("id"),
+    PRIMARY KEY("id")
+);
+
+CREATE INDEX "user_sessions" ON "session" (
+    "user_id" ASC
+);
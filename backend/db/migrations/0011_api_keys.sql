@@ -0,0 +1,18 @@
+-- Keys that scripts use instead of logging in, only a hash of each key is stored
+
+CREATE TABLE "api_key" (
+    "id"            TEXT NOT NULL UNIQUE,
+    "user_id"       TEXT NOT NULL,
+    "name"          TEXT NOT NULL,
+    "scope"         TEXT NOT NULL CHECK ("scope" IN ('read', 'chat')),
+    "key_hash"      TEXT NOT NULL UNIQUE,
+    "created_at"    INTEGER NOT NULL,
+    "last_used_at"  INTEGER,
+    FOREIGN KEY("user_id") REFERENCES "user"("id"),
+    PRIMARY KEY("id")
+);
+
+CREATE INDEX "user_api_keys" ON "api_key" (
+    "user_id" ASC,
+    "created_at" ASC
+);
@@ -0,0 +1,3 @@
+-- Replies that were cancelled keep what was generated so far, marked as truncated
+
+ALTER TABLE "message" ADD COLUMN "truncated" BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,34 @@
+-- Rows created in the same second are listed in the order they were inserted,
+-- "seq" is the rowid they were inserted with, so it survives a VACUUM
+
+ALTER TABLE "conversation" ADD COLUMN "seq" INTEGER NOT NULL DEFAULT 0;
+UPDATE "conversation" SET "seq" = "rowid";
+
+CREATE TRIGGER "conversation_seq"
+AFTER INSERT ON "conversation"
+BEGIN
+    UPDATE "conversation" SET "seq" = NEW."rowid" WHERE "rowid" = NEW."rowid";
+END;
+
+ALTER TABLE "message" ADD COLUMN "seq" INTEGER NOT NULL DEFAULT 0;
+UPDATE "message" SET "seq" = "rowid";
+
+CREATE TRIGGER "message_seq"
+AFTER INSERT ON "message"
+BEGIN
+    UPDATE "message" SET "seq" = NEW."rowid" WHERE "rowid" = NEW."rowid";
+END;
+
+DROP INDEX "user_conversations_by_creation";
+CREATE INDEX "user_conversations_by_creation" ON "conversation" (
+    "user_id" ASC,
+    "created_at" ASC,
+    "seq" ASC
+);
+
+DROP INDEX "conversation_messages_by_creation";
+CREATE INDEX "conversation_messages_by_creation" ON "message" (
+    "conversation_id" ASC,
+    "created_at" ASC,
+    "seq" ASC
+);
@@ -0,0 +1,86 @@
+-- The schema of SQLite databases after migration 0007, for PostgreSQL
+-- Foreign keys are named after their column, so errors can tell which field is wrong
+
+CREATE TABLE "voice" (
+    "id"                TEXT NOT NULL PRIMARY KEY,
+    "name"              TEXT NOT NULL,
+    "description"       TEXT NOT NULL,
+    "prefix"            TEXT NOT NULL,
+    "context_budget"    BIGINT,
+    "temperature"       REAL,
+    "top_k"             BIGINT,
+    "top_p"             REAL,
+    "repeat_penalty"    REAL,
+    "max_tokens"        BIGINT,
+    "stop_sequences"    TEXT,
+    "model_id"          TEXT,
+    "created_at"        BIGINT NOT NULL,
+    "deleted_at"        BIGINT
+);
+
+CREATE INDEX "enabled_voices" ON "voice" (
+    "deleted_at" ASC,
+    "id" ASC
+);
+
+CREATE TABLE "conversation" (
+    "id"            TEXT NOT NULL PRIMARY KEY,
+    "user_id"       TEXT NOT NULL,
+    "name"          TEXT NOT NULL,
+    "voice_id"      TEXT NOT NULL,
+    "created_at"    BIGINT NOT NULL,
+    "deleted_at"    BIGINT,
+    CONSTRAINT "conversation.voice_id" FOREIGN KEY ("voice_id") REFERENCES "voice" ("id")
+);
+
+CREATE INDEX "enabled_user_conversations" ON "conversation" (
+    "deleted_at" ASC,
+    "user_id" ASC
+);
+
+CREATE INDEX "user_conversations_by_creation" ON "conversation" (
+    "user_id" ASC,
+    "created_at" ASC,
+    "id" ASC
+);
+
+CREATE TABLE "message" (
+    "id"                TEXT NOT NULL PRIMARY KEY,
+    "conversation_id"   TEXT NOT NULL,
+    "author"            TEXT NOT NULL,
+    "content"           TEXT NOT NULL,
+    "created_at"        BIGINT NOT NULL,
+    "deleted_at"        BIGINT,
+    CONSTRAINT "message.conversation_id"
+        FOREIGN KEY ("conversation_id") REFERENCES "conversation" ("id")
+);
+
+CREATE INDEX "enabled_messages_by_conversations" ON "message" (
+    "deleted_at" ASC,
+    "conversation_id" ASC
+);
+
+CREATE INDEX "conversation_messages_by_creation" ON "message" (
+    "conversation_id" ASC,
+    "created_at" ASC,
+    "id" ASC
+);
+
+-- The full-text index of the content of messages
+CREATE INDEX "message_search" ON "message" USING GIN (to_tsvector('simple', "content"));
+
+-- Deleting a conversation deletes its messages
+CREATE FUNCTION "conversation_soft_delete"() RETURNS trigger AS $$
+BEGIN
+    UPDATE "message"
+    SET "deleted_at" = NEW."deleted_at"
+    WHERE "conversation_id" = NEW."id" AND "deleted_at" IS NULL;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER "conversation_soft_delete"
+AFTER UPDATE OF "deleted_at" ON "conversation"
+FOR EACH ROW
+WHEN (OLD."deleted_at" IS NULL AND NEW."deleted_at" IS NOT NULL)
+EXECUTE FUNCTION "conversation_soft_delete"();
@@ -0,0 +1,15 @@
+-- Restoring a conversation restores the messages that were deleted along with it
+CREATE FUNCTION "conversation_restore"() RETURNS trigger AS $$
+BEGIN
+    UPDATE "message"
+    SET "deleted_at" = NULL
+    WHERE "conversation_id" = NEW."id" AND "deleted_at" = OLD."deleted_at";
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER "conversation_restore"
+AFTER UPDATE OF "deleted_at" ON "conversation"
+FOR EACH ROW
+WHEN (OLD."deleted_at" IS NOT NULL AND NEW."deleted_at" IS NULL)
+EXECUTE FUNCTION "conversation_restore"();
@@ -0,0 +1,19 @@
+-- Registered users, and the sessions they are logged in with
+
+CREATE TABLE "user" (
+    "id"            TEXT NOT NULL PRIMARY KEY,
+    "username"      TEXT NOT NULL UNIQUE,
+    "password_hash" TEXT NOT NULL,
+    "created_at"    BIGINT NOT NULL
+);
+
+-- The id is a hash of the token in the session cookie, so a leaked table can't log anyone in
+CREATE TABLE "session" (
+    "id"            TEXT NOT NULL PRIMARY KEY,
+    "user_id"       TEXT NOT NULL,
+    "created_at"    BIGINT NOT NULL,
+    "expires_at"    BIGINT NOT NULL,
+    CONSTRAINT "session.user_id" FOREIGN KEY ("user_id") REFERENCES "user" ("id")
+);
+
+CREATE INDEX "user_sessions" ON "session" ("user_id");
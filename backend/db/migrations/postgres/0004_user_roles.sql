@@ -0,0 +1,4 @@
+-- What a user is allowed to do, admins also manage the voices
+
+ALTER TABLE "user" ADD COLUMN "role" TEXT NOT NULL DEFAULT 'user' CHECK ("role" IN ('user', 'admin'));
+
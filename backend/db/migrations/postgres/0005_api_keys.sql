@@ -0,0 +1,14 @@
+-- Keys that scripts use instead of logging in, only a hash of each key is stored
+
+CREATE TABLE "api_key" (
+    "id"            TEXT NOT NULL PRIMARY KEY,
+    "user_id"       TEXT NOT NULL,
+    "name"          TEXT NOT NULL,
+    "scope"         TEXT NOT NULL CHECK ("scope" IN ('read', 'chat')),
+    "key_hash"      TEXT NOT NULL UNIQUE,
+    "created_at"    BIGINT NOT NULL,
+    "last_used_at"  BIGINT,
+    CONSTRAINT "api_key.user_id" FOREIGN KEY ("user_id") REFERENCES "user" ("id")
+);
+
+CREATE INDEX "user_api_keys" ON "api_key" ("user_id", "created_at");
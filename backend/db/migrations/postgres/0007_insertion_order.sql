@@ -0,0 +1,31 @@
+-- Rows created in the same second are listed in the order they were inserted
+
+ALTER TABLE "conversation" ADD COLUMN "seq" BIGSERIAL NOT NULL;
+UPDATE "conversation" SET "seq" = "ordered"."seq"
+FROM (
+    SELECT "id", ROW_NUMBER() OVER (ORDER BY "created_at" ASC, "id" ASC) AS "seq"
+    FROM "conversation"
+) AS "ordered"
+WHERE "conversation"."id" = "ordered"."id";
+
+ALTER TABLE "message" ADD COLUMN "seq" BIGSERIAL NOT NULL;
+UPDATE "message" SET "seq" = "ordered"."seq"
+FROM (
+    SELECT "id", ROW_NUMBER() OVER (ORDER BY "created_at" ASC, "id" ASC) AS "seq"
+    FROM "message"
+) AS "ordered"
+WHERE "message"."id" = "ordered"."id";
+
+DROP INDEX "user_conversations_by_creation";
+CREATE INDEX "user_conversations_by_creation" ON "conversation" (
+    "user_id" ASC,
+    "created_at" ASC,
+    "seq" ASC
+);
+
+DROP INDEX "conversation_messages_by_creation";
+CREATE INDEX "conversation_messages_by_creation" ON "message" (
+    "conversation_id" ASC,
+    "created_at" ASC,
+    "seq" ASC
+);
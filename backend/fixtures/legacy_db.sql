@@ -0,0 +1,57 @@
+-- A database created by the schema the backend shipped with, before migrations were versioned
+
+CREATE TABLE IF NOT EXISTS "voice" (
+    "id"            TEXT NOT NULL UNIQUE,
+    "name"          TEXT NOT NULL,
+    "description"   TEXT NOT NULL,
+    "prefix"        TEXT NOT NULL,
+    "created_at"    INTEGER NOT NULL,
+    "deleted_at"    INTEGER,
+    PRIMARY KEY("id")
+);
+
+CREATE INDEX IF NOT EXISTS "enabled_voices" ON "voice" (
+    "deleted_at" ASC,
+    "id" ASC
+);
+
+CREATE TABLE IF NOT EXISTS "conversation" (
+    "id"            TEXT NOT NULL UNIQUE,
+    "user_id"       TEXT NOT NULL,
+    "name"          TEXT NOT NULL,
+    "voice_id"      TEXT NOT NULL,
+    "created_at"    INTEGER NOT NULL,
+    "deleted_at"    INTEGER,
+    FOREIGN KEY("voice_id") REFERENCES "voice"("id"),
+    PRIMARY KEY("id")
+);
+
+CREATE INDEX IF NOT EXISTS "enabled_user_conversations" ON "conversation" (
+    "deleted_at" ASC,
+    "user_id" ASC
+);
+
+CREATE TABLE IF NOT EXISTS "message" (
+    "id"              TEXT NOT NULL UNIQUE,
+    "conversation_id" TEXT NOT NULL,
+    "author"          TEXT NOT NULL,
+    "content"         TEXT NOT NULL,
+    "created_at"      INTEGER NOT NULL,
+    "deleted_at"      INTEGER,
+    FOREIGN KEY("conversation_id") REFERENCES "conversation"("id"),
+    PRIMARY KEY("id")
+);
+
+CREATE INDEX IF NOT EXISTS "enabled_messages_by_conversations" ON "message" (
+    "deleted_at" ASC,
+    "conversation_id" ASC
+);
+
+INSERT INTO "voice" (id, name, description, prefix, created_at)
+VALUES ('legacy-voice', 'Legacy', 'From before migrations', 'An old voice;', 1699500000);
+
+INSERT INTO "conversation" (id, user_id, name, voice_id, created_at)
+VALUES ('legacy-conversation', 'legacy-user', 'Old times', 'legacy-voice', 1699500001);
+
+INSERT INTO "message" (id, conversation_id, author, content, created_at)
+VALUES ('legacy-message', 'legacy-conversation', 'user', 'Hello from the past', 1699500002);
@@ -0,0 +1,350 @@
+use actix_web::{
+    body::{EitherBody, MessageBody},
+    cookie::{time::Duration, Cookie, SameSite},
+    dev::{Payload, ServiceRequest, ServiceResponse},
+    http::header,
+    middleware::Next,
+    web, Error, FromRequest, HttpMessage, HttpRequest,
+};
+use argon2::{
+    password_hash::{
+        rand_core::{OsRng, RngCore},
+        PasswordHash, PasswordHasher, PasswordVerifier, SaltString,
+    },
+    Argon2,
+};
+use chrono::Utc;
+use futures_util::future::{ready, Ready};
+use models::{ApiKey, Conversation, Message, Role, Scope, User};
+use sha2::{Digest, Sha256};
+use sqlx::Error as SqlxError;
+
+use crate::api::error::HttpError;
+use crate::db::{Session, DB};
+
+/// The name of the cookie that holds the session token
+pub const SESSION_COOKIE: &str = "session";
+
+/// How long a login lasts
+pub const SESSION_DAYS: i64 = 30;
+
+/// Hash a password to store it, with a random salt
+///
+/// Hashing is slow on purpose, call it from a blocking thread
+///
+/// Arguments:
+/// - password: The password in plain text
+pub fn hash_password(password: &str) -> Result<String, HttpError> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|err| HttpError::new(500, format!("Could not hash password: {}", err)))
+}
+
+/// Whether a password matches a hash made by [`hash_password`]
+///
+/// Hashing is slow on purpose, call it from a blocking thread
+///
+/// Arguments:
+/// - password: The password in plain text
+/// - hash: The stored hash of the password
+pub fn verify_password(password: &str, hash: &str) -> bool {
+    match PasswordHash::new(hash) {
+        Ok(hash) => Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok(),
+        Err(_) => false,
+    }
+}
+
+/// Hash a token to store or look it up, tokens are random so a fast hash is enough
+///
+/// Arguments:
+/// - token: The token that was given to the user
+pub fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// Generate a random token, for a session or an API key
+pub fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Start a session for a user
+///
+/// Returns the cookie that holds the token of the session,
+/// only the hash of the token is stored
+///
+/// Arguments:
+/// - db: The database to store the session in
+/// - user_id: The id of the user that logged in
+pub async fn start_session(db: &DB, user_id: &str) -> Result<Cookie<'static>, HttpError> {
+    let token = random_token();
+
+    let now = Utc::now().timestamp();
+    db.save_session(&Session {
+        id: hash_token(&token),
+        user_id: user_id.to_string(),
+        created_at: now,
+        expires_at: now + SESSION_DAYS * 24 * 60 * 60,
+    })
+    .await?;
+
+    Ok(Cookie::build(SESSION_COOKIE, token)
+        .path("/")
+        .http_only(true)
+        .same_site(SameSite::Strict)
+        .max_age(Duration::days(SESSION_DAYS))
+        .finish())
+}
+
+/// Make the user named by `ADMIN_USERNAME` an admin, if they have registered already
+///
+/// This only runs at startup, registering never makes an admin,
+/// so nobody can take the name before its owner and get the role.
+///
+/// Returns whether their role changed
+///
+/// Arguments:
+/// - db: The database the user is in
+/// - username: The username of the admin
+pub async fn bootstrap_admin(db: &DB, username: &str) -> Result<bool, SqlxError> {
+    match db.get_user_login(username).await {
+        Ok((user, _)) if user.role != Role::Admin => db.set_user_role(&user.id, Role::Admin).await,
+        Ok(_) | Err(SqlxError::RowNotFound) => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+/// Find the user of a request from their API key, or else their session cookie
+///
+/// Arguments:
+/// - req: The request to find the user of
+/// - db: The database the API keys and sessions are in
+async fn find_user(
+    req: &ServiceRequest,
+    db: &DB,
+) -> Result<Option<(User, Option<ApiKey>)>, HttpError> {
+    let token = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+
+    if let Some(token) = token {
+        return match db.use_api_key(&hash_token(token.trim())).await {
+            Ok((user, api_key)) => Ok(Some((user, Some(api_key)))),
+            // Revoked, or never existed
+            Err(SqlxError::RowNotFound) => Err(HttpError::new(401, "Invalid API key".to_string())),
+            Err(err) => Err(err.into()),
+        };
+    }
+
+    match req.cookie(SESSION_COOKIE) {
+        Some(cookie) => match db.get_session_user(&hash_token(cookie.value())).await {
+            Ok(user) => Ok(Some((user, None))),
+            // Expired or logged out
+            Err(SqlxError::RowNotFound) => Ok(None),
+            Err(err) => Err(err.into()),
+        },
+        None => Ok(None),
+    }
+}
+
+/// Middleware that finds the user of the request
+/// from their API key in the `Authorization: Bearer` header, or else their session cookie
+///
+/// The user is added to the request for [`AuthUser`], along with the API key they used,
+/// requests without a valid session go through without one.
+/// Requests with an invalid API key are answered with an error,
+/// the scope of a valid one is checked by the extractor of each route
+pub async fn authenticate(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let user = match req.app_data::<web::Data<DB>>() {
+        Some(db) => find_user(&req, db).await,
+        None => Ok(None),
+    };
+
+    match user {
+        Ok(user) => {
+            if let Some((user, api_key)) = user {
+                req.extensions_mut().insert(user);
+                if let Some(api_key) = api_key {
+                    req.extensions_mut().insert(api_key);
+                }
+            }
+            next.call(req)
+                .await
+                .map(ServiceResponse::map_into_left_body)
+        }
+        Err(err) => Ok(req.error_response(err).map_into_right_body()),
+    }
+}
+
+/// Find the logged in user of a request, and the API key they used if they used one
+fn request_user(req: &HttpRequest) -> Result<(User, Option<ApiKey>), HttpError> {
+    let extensions = req.extensions();
+    match extensions.get::<User>() {
+        Some(user) => Ok((user.clone(), extensions.get::<ApiKey>().cloned())),
+        None => Err(HttpError::new(401, "You need to log in".to_string())),
+    }
+}
+
+/// The error for an API key that is used where it isn't allowed
+fn api_key_forbidden(api_key: &ApiKey) -> HttpError {
+    HttpError::new(
+        403,
+        format!(
+            "The {} scope of the API key does not allow this",
+            api_key.scope
+        ),
+    )
+}
+
+/// The logged in user, routes that take it answer 401 to everyone else
+///
+/// API keys need the `chat` scope, use [`ReadUser`] for routes that only read.
+/// Needs the [`authenticate`] middleware
+pub struct AuthUser(pub User);
+
+impl FromRequest for AuthUser {
+    type Error = HttpError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(match request_user(req) {
+            Ok((_, Some(api_key))) if api_key.scope != Scope::Chat => {
+                Err(api_key_forbidden(&api_key))
+            }
+            result => result.map(|(user, _)| AuthUser(user)),
+        })
+    }
+}
+
+/// The logged in user on a route that changes nothing, API keys of any scope can use it
+///
+/// Needs the [`authenticate`] middleware
+pub struct ReadUser(pub AuthUser);
+
+impl FromRequest for ReadUser {
+    type Error = HttpError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(request_user(req).map(|(user, _)| ReadUser(AuthUser(user))))
+    }
+}
+
+/// The logged in user when they logged in with a password rather than an API key,
+/// for managing API keys and claiming conversations
+///
+/// Needs the [`authenticate`] middleware
+pub struct SessionUser(pub AuthUser);
+
+impl FromRequest for SessionUser {
+    type Error = HttpError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(match request_user(req) {
+            Ok((_, Some(api_key))) => Err(api_key_forbidden(&api_key)),
+            result => result.map(|(user, _)| SessionUser(AuthUser(user))),
+        })
+    }
+}
+
+/// The logged in user when they are an admin, routes that take it answer 403 to other users
+///
+/// API keys never reach admin routes, whatever their scope.
+/// Needs the [`authenticate`] middleware
+pub struct AdminUser(pub User);
+
+impl FromRequest for AdminUser {
+    type Error = HttpError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(match request_user(req) {
+            Ok((_, Some(api_key))) => Err(api_key_forbidden(&api_key)),
+            Ok((user, None)) if user.role == Role::Admin => Ok(AdminUser(user)),
+            Ok(_) => Err(HttpError::new(403, "Only admins can do this".to_string())),
+            Err(err) => Err(err),
+        })
+    }
+}
+
+impl AuthUser {
+    /// Find a conversation of the user
+    ///
+    /// Conversations of other users are not found either, so their ids don't leak
+    ///
+    /// Arguments:
+    /// - db: The database to look in
+    /// - conversation_id: The id of the conversation
+    pub async fn conversation(
+        &self,
+        db: &DB,
+        conversation_id: &str,
+    ) -> Result<Conversation, HttpError> {
+        let conversation = db.get_conversation(conversation_id).await?;
+        match conversation.user_id == self.0.id {
+            true => Ok(conversation),
+            false => Err(SqlxError::RowNotFound.into()),
+        }
+    }
+
+    /// Find a message in a conversation of the user
+    ///
+    /// Messages of other users are not found either, so their ids don't leak
+    ///
+    /// Arguments:
+    /// - db: The database to look in
+    /// - message_id: The id of the message
+    pub async fn message(&self, db: &DB, message_id: &str) -> Result<Message, HttpError> {
+        let message = db.get_message(message_id).await?;
+        self.conversation(db, &message.conversation_id).await?;
+        Ok(message)
+    }
+
+    /// Check that a conversation a message is saved to belongs to the user
+    ///
+    /// It is part of the body rather than the path, so a missing one is invalid.
+    /// The conversation of another user is not found, as for every other route
+    ///
+    /// Arguments:
+    /// - db: The database to look in
+    /// - message: The message that is being saved
+    pub async fn check_message_conversation(
+        &self,
+        db: &DB,
+        message: &Message,
+    ) -> Result<(), HttpError> {
+        match db.get_conversation(&message.conversation_id).await {
+            Ok(conversation) if conversation.user_id == self.0.id => Ok(()),
+            Ok(_) => Err(SqlxError::RowNotFound.into()),
+            Err(SqlxError::RowNotFound) => Err(HttpError::invalid(vec![
+                "conversation_id does not refer to an existing conversation".to_string(),
+            ])),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_password_hash() {
+        let hash = hash_password("correct horse").unwrap();
+        assert_ne!(hash, "correct horse");
+        assert!(verify_password("correct horse", &hash));
+        assert!(!verify_password("battery staple", &hash));
+        assert!(!verify_password("correct horse", "not a hash"));
+    }
+}
@@ -0,0 +1,268 @@
+use actix_web::{rt, web};
+use models::{Author, ChatFrame, GenerationSettings, Message, ReplyToken};
+use tokio::sync::mpsc;
+
+use crate::api::error::HttpError;
+use crate::api::reply::{generate_reply, model_ready, start_job};
+use crate::db::DB;
+use crate::jobs::{Job, JobRegistry};
+use crate::llm::InferencePool;
+use crate::prompt::ChatTemplate;
+
+/// Run a chat session for a conversation until the client goes away
+///
+/// Every user message is saved and answered by the voice, one reply at a time.
+/// The reply is streamed as Token frames, followed by a Done frame.
+/// A reply that is still being generated when the client goes away is cancelled.
+///
+/// Arguments:
+/// - db: The database to save the messages to
+/// - pool: The workers that generate the replies
+/// - template: The chat template that builds the prompt
+/// - jobs: The registry of replies being generated
+/// - conversation_id: The id of the conversation to chat in
+/// - incoming: The frames sent by the client
+/// - outgoing: The frames to send to the client
+pub async fn run_chat(
+    db: DB,
+    pool: web::Data<InferencePool>,
+    template: ChatTemplate,
+    jobs: JobRegistry,
+    conversation_id: String,
+    mut incoming: mpsc::UnboundedReceiver<ChatFrame>,
+    outgoing: mpsc::UnboundedSender<ChatFrame>,
+) {
+    let error = |message: &str| ChatFrame::Error {
+        errors: vec![message.to_string()],
+    };
+    let mut generation: Option<rt::task::JoinHandle<()>> = None;
+
+    while let Some(frame) = incoming.recv().await {
+        match frame {
+            ChatFrame::UserMessage { content, settings } => {
+                if let Err(errors) = settings.validate() {
+                    let _ = outgoing.send(ChatFrame::Error { errors });
+                    continue;
+                }
+                if let Err(err) = model_ready(&db, &pool, &conversation_id).await {
+                    let _ = outgoing.send(error(&err.public_message()));
+                    continue;
+                }
+
+                // Registered before the message is saved, so a cancel frame
+                // that follows right away is not lost
+                let job = match start_job(&jobs, &conversation_id) {
+                    Ok(job) => job,
+                    Err(err) => {
+                        let _ = outgoing.send(error(&err.public_message()));
+                        continue;
+                    }
+                };
+
+                let message = Message::new(conversation_id.clone(), Author::User, content);
+                if let Err(err) = db.save_message(&message).await {
+                    let _ = outgoing.send(error(&HttpError::from(err).public_message()));
+                    continue;
+                }
+                let _ = outgoing.send(ChatFrame::Message(message));
+
+                generation = Some(rt::spawn(reply(
+                    db.clone(),
+                    pool.clone(),
+                    template,
+                    job,
+                    conversation_id.clone(),
+                    settings,
+                    outgoing.clone(),
+                )));
+            }
+            ChatFrame::Cancel => {
+                jobs.cancel(&conversation_id);
+            }
+            _ => {
+                let _ = outgoing.send(error("Only user_message and cancel frames can be sent"));
+            }
+        }
+    }
+
+    // Only cancel the reply this session started
+    if generation.is_some_and(|generation| !generation.is_finished()) {
+        jobs.cancel(&conversation_id);
+    }
+}
+
+/// Generate a reply and send it to the client
+///
+/// Arguments:
+/// - db: The database to save the reply to
+/// - pool: The workers that generate the reply
+/// - template: The chat template that builds the prompt
+/// - job: The registered job of the reply
+/// - conversation_id: The id of the conversation to reply to
+/// - overrides: Settings that override the voice's generation settings
+/// - outgoing: The frames to send to the client
+async fn reply(
+    db: DB,
+    pool: web::Data<InferencePool>,
+    template: ChatTemplate,
+    job: Job,
+    conversation_id: String,
+    overrides: GenerationSettings,
+    outgoing: mpsc::UnboundedSender<ChatFrame>,
+) {
+    let token_sender = outgoing.clone();
+    let on_text = move |text: &str| {
+        let token = ReplyToken {
+            content: text.to_string(),
+        };
+        token_sender.send(ChatFrame::Token(token)).is_ok()
+    };
+
+    let frame = match generate_reply(
+        &db,
+        pool,
+        template,
+        job,
+        conversation_id,
+        overrides,
+        on_text,
+    )
+    .await
+    {
+        Ok(reply) => ChatFrame::Done(reply),
+        Err(err) => ChatFrame::Error {
+            errors: vec![err.public_message()],
+        },
+    };
+    let _ = outgoing.send(frame);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use models::{Conversation, Voice};
+
+    use super::*;
+    use crate::db::Page;
+    use crate::llm::{MockLlm, ModelRegistry};
+
+    /// Start a chat session with a mock backend
+    async fn start_chat(
+        mock: MockLlm,
+    ) -> (
+        DB,
+        Conversation,
+        mpsc::UnboundedSender<ChatFrame>,
+        mpsc::UnboundedReceiver<ChatFrame>,
+    ) {
+        let db = DB::new("sqlite::memory:").await.unwrap();
+        db.assert_schema().await.unwrap();
+        let voice = Voice::new(
+            "Shaun".to_string(),
+            "It's me".to_string(),
+            "boring".to_string(),
+        );
+        db.save_voice(&voice).await.unwrap();
+        let conversation = Conversation::new("user".to_string(), "Test".to_string(), voice.id);
+        db.save_conversation(&conversation).await.unwrap();
+
+        let pool = web::Data::new(InferencePool::new(
+            ModelRegistry::single(Arc::new(mock)),
+            1,
+            4,
+        ));
+        let (client, incoming) = mpsc::unbounded_channel();
+        let (outgoing, server) = mpsc::unbounded_channel();
+        rt::spawn(run_chat(
+            db.clone(),
+            pool,
+            ChatTemplate::default(),
+            JobRegistry::default(),
+            conversation.id.clone(),
+            incoming,
+            outgoing,
+        ));
+
+        (db, conversation, client, server)
+    }
+
+    #[actix_web::test]
+    async fn test_chat_reply() {
+        let (db, conversation, client, mut server) =
+            start_chat(MockLlm::canned(vec!["Hello there".to_string()])).await;
+
+        client
+            .send(ChatFrame::UserMessage {
+                content: "Hi".to_string(),
+                settings: GenerationSettings::default(),
+            })
+            .unwrap();
+
+        let Some(ChatFrame::Message(message)) = server.recv().await else {
+            panic!("Expected the saved user message");
+        };
+        assert_eq!(message.author, Author::User);
+        assert_eq!(message.content, "Hi");
+
+        let mut tokens = Vec::new();
+        let reply = loop {
+            match server.recv().await {
+                Some(ChatFrame::Token(token)) => tokens.push(token.content),
+                Some(ChatFrame::Done(reply)) => break reply,
+                frame => panic!("Unexpected frame {:?}", frame),
+            }
+        };
+        assert_eq!(tokens, vec!["Hello ", "there"]);
+        assert_eq!(reply.message.content, "Hello there");
+
+        let messages = db
+            .get_messages(&conversation.id, false, &Page::default())
+            .await
+            .unwrap()
+            .items;
+        assert_eq!(messages.len(), 2);
+    }
+
+    #[actix_web::test]
+    async fn test_chat_cancel() {
+        let (_, _, client, mut server) =
+            start_chat(MockLlm::canned(vec!["one two three four".to_string()])).await;
+
+        // The cancel frame is handled before the reply starts streaming
+        client
+            .send(ChatFrame::UserMessage {
+                content: "Hi".to_string(),
+                settings: GenerationSettings::default(),
+            })
+            .unwrap();
+        client.send(ChatFrame::Cancel).unwrap();
+        let Some(ChatFrame::Message(_)) = server.recv().await else {
+            panic!("Expected the saved user message");
+        };
+
+        let reply = loop {
+            match server.recv().await {
+                Some(ChatFrame::Token(_)) => continue,
+                Some(ChatFrame::Done(reply)) => break reply,
+                frame => panic!("Unexpected frame {:?}", frame),
+            }
+        };
+        // The partial reply is kept
+        assert_eq!(reply.message.content, "one");
+        assert!(reply.truncated);
+    }
+
+    #[actix_web::test]
+    async fn test_chat_rejects_server_frames() {
+        let (_, _, client, mut server) = start_chat(MockLlm::Echo).await;
+
+        client
+            .send(ChatFrame::Token(ReplyToken {
+                content: "Hi".to_string(),
+            }))
+            .unwrap();
+
+        assert!(matches!(server.recv().await, Some(ChatFrame::Error { .. })));
+    }
+}
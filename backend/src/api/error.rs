@@ -1,10 +1,12 @@
 use actix_web::{http::StatusCode, HttpResponse, ResponseError};
 use serde::Deserialize;
-use sqlx::Error as SqlxError;
+use sqlx::{error::ErrorKind, Error as SqlxError};
 use std::fmt;
 
 use models::JsonApiResponse;
 
+use crate::llm::{LlmError, PoolError};
+
 #[derive(Debug, Deserialize)]
 pub struct HttpError {
     pub status_code: u16,
@@ -23,6 +25,24 @@ impl HttpError {
             message,
         }
     }
+
+    /// Create a 422 HttpError for input that failed validation
+    ///
+    /// Arguments:
+    /// - errors: A message for every invalid field
+    pub fn invalid(errors: Vec<String>) -> HttpError {
+        HttpError::new(422, errors.join(", "))
+    }
+
+    /// The message that is safe to show the user
+    ///
+    /// Internal server errors are hidden behind a generic message
+    pub fn public_message(&self) -> String {
+        match self.status_code {
+            500 => "Internal server error".to_string(),
+            _ => self.message.clone(),
+        }
+    }
 }
 
 impl fmt::Display for HttpError {
@@ -31,11 +51,50 @@ impl fmt::Display for HttpError {
     }
 }
 
+/// Turn a violated NOT NULL or FOREIGN KEY constraint into a 422 for the field
+///
+/// SQLite reports them as `NOT NULL constraint failed: conversation.voice_id`,
+/// foreign key violations only name the field when raised by the schema's checks
+///
+/// Arguments:
+/// - message: The message of the database error
+fn constraint_violation(message: &str) -> Option<HttpError> {
+    let (constraint, column) = message.split_once(" constraint failed")?;
+    let field = column
+        .trim_start_matches(':')
+        .trim()
+        .split_once('.')
+        .map(|(_, field)| field);
+
+    let error = match (constraint, field) {
+        ("FOREIGN KEY", Some(field)) => format!(
+            "{} does not refer to an existing {}",
+            field,
+            field.trim_end_matches("_id")
+        ),
+        ("FOREIGN KEY", None) => "A referenced record does not exist".to_string(),
+        ("NOT NULL", Some(field)) => format!("{} is required", field),
+        _ => return None,
+    };
+
+    Some(HttpError::invalid(vec![error]))
+}
+
 // Convert an SqlxError into an HttpError
 impl From<SqlxError> for HttpError {
     fn from(error: SqlxError) -> HttpError {
         match error {
-            SqlxError::Database(err) => HttpError::new(409, err.message().to_string()),
+            SqlxError::Database(err) => {
+                // PostgreSQL names the foreign key, after its column, instead of describing it
+                let message = match (err.kind(), err.constraint()) {
+                    (ErrorKind::ForeignKeyViolation, Some(constraint)) => {
+                        format!("FOREIGN KEY constraint failed: {}", constraint)
+                    }
+                    _ => err.message().to_string(),
+                };
+                constraint_violation(&message)
+                    .unwrap_or(HttpError::new(409, err.message().to_string()))
+            }
             SqlxError::PoolTimedOut => HttpError::new(408, "DB Pool timed out".to_string()),
             SqlxError::RowNotFound => HttpError::new(404, "The record was not found".to_string()),
             err => HttpError::new(500, format!("Unexpected DB error: {}", err)),
@@ -43,6 +102,43 @@ impl From<SqlxError> for HttpError {
     }
 }
 
+// Convert an LlmError into an HttpError
+impl From<LlmError> for HttpError {
+    fn from(error: LlmError) -> HttpError {
+        match error {
+            LlmError::ContextFull => HttpError::new(
+                422,
+                "The conversation is too long for the model context".to_string(),
+            ),
+            LlmError::UnknownModel(model_id) => HttpError::new(
+                422,
+                format!("The voice uses an unknown model: {}", model_id),
+            ),
+            LlmError::NotReady { model_id, progress } => HttpError::new(
+                503,
+                format!(
+                    "The model {} is warming up ({}% loaded), try again later",
+                    model_id, progress
+                ),
+            ),
+            err => HttpError::new(500, err.to_string()),
+        }
+    }
+}
+
+// Convert a PoolError into an HttpError
+impl From<PoolError> for HttpError {
+    fn from(error: PoolError) -> HttpError {
+        match error {
+            PoolError::QueueFull => HttpError::new(
+                503,
+                "Too many replies are being generated, try again later".to_string(),
+            ),
+            err => HttpError::new(500, err.to_string()),
+        }
+    }
+}
+
 // Implement the ResponseError trait to generate a JSON API response
 impl ResponseError for HttpError {
     fn error_response(&self) -> HttpResponse {
@@ -51,12 +147,37 @@ impl ResponseError for HttpError {
             Err(_) => StatusCode::INTERNAL_SERVER_ERROR,
         };
 
-        let error_message = match status_code.as_u16() < 500 {
-            true => self.message.clone(),
-            false => "Internal server error".to_string(),
-        };
+        HttpResponse::build(status_code).json(JsonApiResponse::<String>::error(
+            vec![self.public_message()],
+            None,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_constraint_violation() {
+        let error =
+            constraint_violation("FOREIGN KEY constraint failed: message.conversation_id").unwrap();
+        assert_eq!(error.status_code, 422);
+        assert_eq!(
+            error.message,
+            "conversation_id does not refer to an existing conversation"
+        );
+
+        let error = constraint_violation("NOT NULL constraint failed: voice.name").unwrap();
+        assert_eq!(error.message, "name is required");
 
-        HttpResponse::build(status_code)
-            .json(JsonApiResponse::<String>::error(vec![error_message], None))
+        assert_eq!(
+            constraint_violation("FOREIGN KEY constraint failed")
+                .unwrap()
+                .status_code,
+            422
+        );
+        // Duplicates stay a conflict
+        assert!(constraint_violation("UNIQUE constraint failed: voice.id").is_none());
     }
 }
@@ -1,2 +1,5 @@
+pub mod auth;
+pub mod chat;
 pub mod error;
+pub mod reply;
 pub mod routes;
@@ -0,0 +1,203 @@
+use actix_web::web;
+use models::{Author, GenerationSettings, Message, Reply};
+use serde::Serialize;
+
+use crate::api::error::HttpError;
+use crate::context::ContextWindow;
+use crate::db::{Page, DB};
+use crate::jobs::{Job, JobRegistry};
+use crate::llm::{InferencePool, LlmError};
+use crate::prompt::ChatTemplate;
+
+/// Register a reply for a conversation, so it can be cancelled
+///
+/// Fails with a conflict if a reply is already being generated
+///
+/// Arguments:
+/// - jobs: The registry of replies being generated
+/// - conversation_id: The id of the conversation to reply to
+pub fn start_job(jobs: &JobRegistry, conversation_id: &str) -> Result<Job, HttpError> {
+    jobs.start(conversation_id).ok_or(HttpError::new(
+        409,
+        "A reply is already being generated for this conversation".to_string(),
+    ))
+}
+
+/// Check that the model of a conversation's voice can generate a reply right away
+///
+/// A model that isn't in memory starts loading, replies fail with a 503 until it is ready
+///
+/// Arguments:
+/// - db: The database to read the conversation and voice from
+/// - pool: The workers that generate the reply
+/// - conversation_id: The id of the conversation to reply to
+pub async fn model_ready(
+    db: &DB,
+    pool: &InferencePool,
+    conversation_id: &str,
+) -> Result<(), HttpError> {
+    let conversation = db.get_conversation(conversation_id).await?;
+    let voice = db.get_voice(&conversation.voice_id).await?;
+    pool.ensure_ready(voice.model_id.as_deref())?;
+
+    Ok(())
+}
+
+/// Parse and validate generation settings overrides from JSON
+///
+/// An empty input overrides nothing
+///
+/// Arguments:
+/// - json: The JSON encoded settings
+pub fn parse_overrides(json: &[u8]) -> Result<GenerationSettings, HttpError> {
+    if json.is_empty() {
+        return Ok(GenerationSettings::default());
+    }
+
+    let overrides = serde_json::from_slice::<GenerationSettings>(json)
+        .map_err(|err| HttpError::new(400, format!("Invalid generation settings: {}", err)))?;
+    overrides.validate().map_err(HttpError::invalid)?;
+
+    Ok(overrides)
+}
+
+/// Generate the voice's reply to a conversation and save it
+///
+/// A cancelled reply is saved with what was generated so far, its message marked as truncated.
+///
+/// Arguments:
+/// - db: The database to read the conversation from and save the reply to
+/// - pool: The workers that generate the reply
+/// - template: The chat template that builds the prompt
+/// - job: The registered job of the reply, see [`start_job`]
+/// - conversation_id: The id of the conversation to reply to
+/// - overrides: Settings that override the voice's generation settings, must be valid
+/// - on_text: Called with each piece of the reply as it is generated,
+///   generation stops early when it returns false
+pub async fn generate_reply(
+    db: &DB,
+    pool: web::Data<InferencePool>,
+    template: ChatTemplate,
+    job: Job,
+    conversation_id: String,
+    overrides: GenerationSettings,
+    mut on_text: impl FnMut(&str) -> bool + Send + 'static,
+) -> Result<Reply, HttpError> {
+    let flag = job.flag();
+
+    let conversation = db.get_conversation(&conversation_id).await?;
+    let voice = db.get_voice(&conversation.voice_id).await?;
+    let messages = db
+        .get_messages(&conversation_id, false, &Page::default())
+        .await?
+        .items;
+
+    let settings = voice.generation.with_overrides(&overrides);
+    let mut stop_sequences = template.stop_sequences();
+    stop_sequences.extend(settings.stop_sequences.clone().unwrap_or_default());
+
+    let (content, excluded_message_ids) = pool
+        .run(move |models| {
+            let llm = models.get(voice.model_id.as_deref())?;
+            let llm = llm.as_ref();
+            let window =
+                ContextWindow::fit(llm, &template, &settings, &voice, &conversation, &messages)?;
+            let mut until_cancelled = |text: &str| {
+                if !on_text(text) {
+                    flag.cancel();
+                }
+                !flag.is_cancelled()
+            };
+            let content = llm.generate(
+                &window.prompt,
+                &settings,
+                &stop_sequences,
+                &mut until_cancelled,
+            )?;
+            Ok::<_, LlmError>((content, window.excluded_message_ids))
+        })
+        .await??;
+
+    let truncated = job.flag().is_cancelled();
+    let mut message = Message::new(conversation_id, Author::Voice, content.trim().to_string());
+    message.truncated = truncated;
+    db.save_message(&message).await?;
+
+    Ok(Reply {
+        message,
+        excluded_message_ids,
+        truncated,
+    })
+}
+
+/// Format a Server-Sent Event with a JSON payload
+///
+/// Arguments:
+/// - event: The name of the event
+/// - data: The payload, serialized as JSON
+pub fn sse_event<T: Serialize>(event: &str, data: &T) -> web::Bytes {
+    web::Bytes::from(format!(
+        "event: {}\ndata: {}\n\n",
+        event,
+        serde_json::to_string(data).unwrap_or_default()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use models::{Conversation, Voice};
+
+    use super::*;
+    use crate::llm::{MockLlm, ModelRegistry};
+
+    #[actix_web::test]
+    async fn test_cancelled_reply_is_kept() {
+        let db = DB::new("sqlite::memory:").await.unwrap();
+        db.assert_schema().await.unwrap();
+        let voice = Voice::new(
+            "Shaun".to_string(),
+            "It's me".to_string(),
+            "boring".to_string(),
+        );
+        db.save_voice(&voice).await.unwrap();
+        let conversation = Conversation::new("user".to_string(), "Test".to_string(), voice.id);
+        db.save_conversation(&conversation).await.unwrap();
+
+        let pool = web::Data::new(InferencePool::new(
+            ModelRegistry::single(Arc::new(MockLlm::canned(vec!["one two three".to_string()]))),
+            1,
+            4,
+        ));
+        let jobs = JobRegistry::default();
+
+        // Cancel as soon as the first token arrives
+        let canceller = jobs.clone();
+        let conversation_id = conversation.id.clone();
+        let reply = generate_reply(
+            &db,
+            pool,
+            ChatTemplate::default(),
+            start_job(&jobs, &conversation.id).unwrap(),
+            conversation.id.clone(),
+            GenerationSettings::default(),
+            move |_| {
+                canceller.cancel(&conversation_id);
+                true
+            },
+        )
+        .await
+        .unwrap();
+
+        assert!(reply.truncated);
+        assert!(reply.message.truncated);
+        assert_eq!(reply.message.content, "one");
+        assert_eq!(
+            db.get_message(&reply.message.id).await.unwrap(),
+            reply.message
+        );
+        // The job is done
+        assert!(!jobs.cancel(&conversation.id));
+    }
+}
@@ -1,15 +1,212 @@
-use actix_web::http::StatusCode;
-use actix_web::{delete, get, post, put, web, HttpResponse};
-use models::{Conversation, JsonApiResponse, Message, Voice};
+use actix_web::cookie::Cookie;
+use actix_web::http::{header, StatusCode};
+use actix_web::{delete, get, post, put, rt, web, Error, FromRequest, HttpRequest, HttpResponse};
+use actix_ws as ws;
+use futures_util::stream;
+use log::info;
+use models::{
+    ApiKey, ChatFrame, Claim, ClaimReport, Conversation, CreatedApiKey, Credentials,
+    JsonApiResponse, Message, Meta, NewApiKey, ReplyToken, Role, User, Voice,
+};
 use serde::Deserialize;
+use sqlx::Error as SqlxError;
+use tokio::sync::mpsc;
 
+use crate::api::auth::{
+    hash_password, hash_token, random_token, start_session, verify_password, AdminUser, AuthUser,
+    ReadUser, SessionUser, SESSION_COOKIE,
+};
+use crate::api::chat::run_chat;
 use crate::api::error::HttpError;
-use crate::db::DB;
+use crate::api::reply::{generate_reply, model_ready, parse_overrides, sse_event, start_job};
+use crate::db::{Cursor, Page, DB};
+use crate::jobs::JobRegistry;
+use crate::llm::InferencePool;
+use crate::prompt::ChatTemplate;
+use crate::purge::Purger;
+
+/// Check the username and password a user registers with
+///
+/// Arguments:
+/// - credentials: The username and password to check
+fn validate_credentials(credentials: &Credentials) -> Result<(), HttpError> {
+    let mut errors = Vec::new();
+
+    let username = &credentials.username;
+    if !(3..=32).contains(&username.chars().count()) {
+        errors.push("username must be between 3 and 32 characters".to_string());
+    }
+    if !username
+        .chars()
+        .all(|c| c.is_alphanumeric() || "-_.".contains(c))
+    {
+        errors.push("username can only contain letters, digits, '-', '_' and '.'".to_string());
+    }
+    if credentials.password.chars().count() < 8 {
+        errors.push("password must be at least 8 characters".to_string());
+    }
+
+    match errors.is_empty() {
+        true => Ok(()),
+        false => Err(HttpError::invalid(errors)),
+    }
+}
+
+#[post("/auth/register")]
+async fn auth_register(
+    db: web::Data<DB>,
+    credentials: web::Json<Credentials>,
+) -> Result<HttpResponse, HttpError> {
+    validate_credentials(&credentials)?;
+    if db.get_user_login(&credentials.username).await.is_ok() {
+        return Err(HttpError::new(
+            StatusCode::CONFLICT.as_u16(),
+            format!("The username {} is taken", credentials.username),
+        ));
+    }
+
+    let password = credentials.password.clone();
+    let password_hash = web::block(move || hash_password(&password))
+        .await
+        .map_err(|err| HttpError::new(500, err.to_string()))??;
+    let user = User::new(credentials.username.clone());
+    db.save_user(&user, &password_hash).await?;
+
+    // Registering logs the user in
+    let cookie = start_session(&db, &user.id).await?;
+    Ok(HttpResponse::Created()
+        .cookie(cookie)
+        .json(JsonApiResponse::success(vec![user], None)))
+}
+
+#[post("/auth/login")]
+async fn auth_login(
+    db: web::Data<DB>,
+    credentials: web::Json<Credentials>,
+) -> Result<HttpResponse, HttpError> {
+    // An unknown user and a wrong password look the same, so usernames can't be probed
+    let invalid = || HttpError::new(401, "Invalid username or password".to_string());
+
+    let (user, password_hash) = match db.get_user_login(&credentials.username).await {
+        Ok(login) => login,
+        Err(SqlxError::RowNotFound) => return Err(invalid()),
+        Err(err) => return Err(err.into()),
+    };
+    let password = credentials.password.clone();
+    let verified = web::block(move || verify_password(&password, &password_hash))
+        .await
+        .map_err(|err| HttpError::new(500, err.to_string()))?;
+    if !verified {
+        return Err(invalid());
+    }
+
+    let cookie = start_session(&db, &user.id).await?;
+    Ok(HttpResponse::Ok()
+        .cookie(cookie)
+        .json(JsonApiResponse::success(vec![user], None)))
+}
+
+#[post("/auth/logout")]
+async fn auth_logout(db: web::Data<DB>, req: HttpRequest) -> Result<HttpResponse, HttpError> {
+    if let Some(cookie) = req.cookie(SESSION_COOKIE) {
+        db.delete_session(&hash_token(cookie.value())).await?;
+    }
+
+    let mut removal = Cookie::build(SESSION_COOKIE, "").path("/").finish();
+    removal.make_removal();
+    Ok(HttpResponse::Ok().cookie(removal).finish())
+}
+
+#[get("/auth/me")]
+async fn auth_me(ReadUser(user): ReadUser) -> Result<HttpResponse, HttpError> {
+    Ok(HttpResponse::Ok().json(JsonApiResponse::success(vec![user.0], None)))
+}
+
+#[post("/auth/claim")]
+async fn auth_claim(
+    db: web::Data<DB>,
+    SessionUser(user): SessionUser,
+    claim: web::Json<Claim>,
+) -> Result<HttpResponse, HttpError> {
+    if claim.anonymous_id.trim().is_empty() {
+        return Err(HttpError::invalid(vec![
+            "anonymous_id must not be empty".to_string()
+        ]));
+    }
+
+    // The anonymous id is a bearer secret, whoever knows it owns its conversations.
+    // Every claim is logged, so a disputed one can be traced
+    let conversations = db
+        .claim_conversations(&claim.anonymous_id, &user.0.id)
+        .await?;
+    info!(
+        "User {} claimed {} conversations of anonymous id {}",
+        user.0.id, conversations, claim.anonymous_id
+    );
+    let report = ClaimReport { conversations };
+    Ok(HttpResponse::Ok().json(JsonApiResponse::success(vec![report], None)))
+}
+
+#[get("/api-keys")]
+async fn api_keys_find_all(
+    db: web::Data<DB>,
+    SessionUser(user): SessionUser,
+) -> Result<HttpResponse, HttpError> {
+    let api_keys = db.get_api_keys(&user.0.id).await?;
+    Ok(HttpResponse::Ok().json(JsonApiResponse::success(api_keys, None)))
+}
+
+#[post("/api-keys")]
+async fn api_keys_new(
+    db: web::Data<DB>,
+    SessionUser(user): SessionUser,
+    new_api_key: web::Json<NewApiKey>,
+) -> Result<HttpResponse, HttpError> {
+    let name = new_api_key.name.trim();
+    if !(1..=64).contains(&name.chars().count()) {
+        return Err(HttpError::invalid(vec![
+            "name must be between 1 and 64 characters".to_string(),
+        ]));
+    }
+
+    // The key is only shown now, only its hash is stored
+    let token = random_token();
+    let api_key = ApiKey::new(user.0.id, name.to_string(), new_api_key.scope);
+    db.save_api_key(&api_key, &hash_token(&token)).await?;
+
+    let created = CreatedApiKey { api_key, token };
+    Ok(HttpResponse::Created().json(JsonApiResponse::success(vec![created], None)))
+}
+
+#[delete("/api-keys/{api_key_id}")]
+async fn api_keys_delete(
+    db: web::Data<DB>,
+    SessionUser(user): SessionUser,
+    path: web::Path<String>,
+) -> Result<HttpResponse, HttpError> {
+    let api_key_id = path.into_inner();
+    match db.delete_api_key(&user.0.id, &api_key_id).await? {
+        true => Ok(HttpResponse::NoContent().finish()),
+        false => Err(SqlxError::RowNotFound.into()),
+    }
+}
+
+#[derive(Deserialize)]
+struct VoicesQuery {
+    #[serde(default)]
+    include_deleted: bool,
+}
 
 #[get("/voices")]
-async fn voices_find_all(db: web::Data<DB>) -> Result<HttpResponse, HttpError> {
+async fn voices_find_all(
+    db: web::Data<DB>,
+    query_params: web::Query<VoicesQuery>,
+) -> Result<HttpResponse, HttpError> {
     // An empty response is a valid response, so unwrap to an empty vec instead of 404 error
-    let voices = db.get_voices(false).await.unwrap_or(Vec::new());
+    let voices = db
+        .get_voices(query_params.include_deleted)
+        .await
+        .unwrap_or(Vec::new());
     Ok(HttpResponse::Ok().json(JsonApiResponse::success(voices, None)))
 }
 
@@ -26,8 +223,13 @@ async fn voices_find_one(
 #[post("/voices")]
 async fn voices_new(
     db: web::Data<DB>,
+    _admin: AdminUser,
     new_voice: web::Json<Voice>,
 ) -> Result<HttpResponse, HttpError> {
+    new_voice
+        .generation
+        .validate()
+        .map_err(HttpError::invalid)?;
     db.save_voice(&new_voice).await?;
     Ok(HttpResponse::Created().json(JsonApiResponse::success(vec![new_voice], None)))
 }
@@ -35,6 +237,7 @@ async fn voices_new(
 #[put("/voices/{voice_id}")]
 async fn voices_save(
     db: web::Data<DB>,
+    _admin: AdminUser,
     path: web::Path<String>,
     voice: web::Json<Voice>,
 ) -> Result<HttpResponse, HttpError> {
@@ -46,44 +249,139 @@ async fn voices_save(
             format!("Path id {} does not match object id {}", path_id, voice.id),
         ));
     }
+    voice.generation.validate().map_err(HttpError::invalid)?;
 
     db.save_voice(&voice).await?;
     Ok(HttpResponse::Ok().json(JsonApiResponse::success(vec![voice], None)))
 }
 
+#[delete("/voices/{voice_id}")]
+async fn voices_delete(
+    db: web::Data<DB>,
+    _admin: AdminUser,
+    path: web::Path<String>,
+) -> Result<HttpResponse, HttpError> {
+    // The conversations of the voice keep it, so they can still be shown
+    let voice_id = path.into_inner();
+    match db.delete_voice(&voice_id).await? {
+        true => Ok(HttpResponse::Ok().finish()),
+        false => Err(SqlxError::RowNotFound.into()),
+    }
+}
+
+/// The page size of lists when no limit is given
+const DEFAULT_PAGE_LIMIT: i64 = 50;
+
+/// The largest page size of lists
+const MAX_PAGE_LIMIT: i64 = 200;
+
+/// Build the page to fetch from the pagination query parameters
+///
+/// Arguments:
+/// - limit: The most rows to fetch
+/// - before: The cursor to fetch the rows before
+/// - after: The cursor to fetch the rows after
+fn page_from_query(
+    limit: Option<i64>,
+    before: &Option<String>,
+    after: &Option<String>,
+) -> Result<Page, HttpError> {
+    let mut errors = Vec::new();
+
+    let limit = limit.unwrap_or(DEFAULT_PAGE_LIMIT);
+    if !(1..=MAX_PAGE_LIMIT).contains(&limit) {
+        errors.push(format!("limit must be between 1 and {}", MAX_PAGE_LIMIT));
+    }
+    if before.is_some() && after.is_some() {
+        errors.push("Only one of before and after can be given".to_string());
+    }
+    let mut parse = |name: &str, cursor: &Option<String>| match cursor {
+        Some(text) => Cursor::parse(text).or_else(|| {
+            errors.push(format!("{} is not a valid cursor", name));
+            None
+        }),
+        None => None,
+    };
+    let before = parse("before", before);
+    let after = parse("after", after);
+
+    match errors.is_empty() {
+        true => Ok(Page {
+            limit: Some(limit),
+            before,
+            after,
+        }),
+        false => Err(HttpError::invalid(errors)),
+    }
+}
+
 #[derive(Deserialize)]
 struct ConversationsQuery {
-    user_id: String,
+    #[serde(default)]
+    include_deleted: bool,
+    limit: Option<i64>,
+    before: Option<String>,
+    after: Option<String>,
 }
 
 #[get("/conversations")]
 async fn conversations_find_all(
     db: web::Data<DB>,
+    ReadUser(user): ReadUser,
     query_params: web::Query<ConversationsQuery>,
 ) -> Result<HttpResponse, HttpError> {
-    // An empty response is a valid response, so unwrap to an empty vec instead of 404 error
+    let page = page_from_query(
+        query_params.limit,
+        &query_params.before,
+        &query_params.after,
+    )?;
+
     let conversations = db
-        .get_conversations(&query_params.user_id, false)
-        .await
-        .unwrap_or(Vec::new());
-    Ok(HttpResponse::Ok().json(JsonApiResponse::success(conversations, None)))
+        .get_conversations(&user.0.id, query_params.include_deleted, &page)
+        .await?;
+    let meta = Meta {
+        next_cursor: conversations.next.map(|cursor| cursor.to_string()),
+    };
+    Ok(
+        HttpResponse::Ok()
+            .json(JsonApiResponse::success(conversations.items, None).with_meta(meta)),
+    )
 }
 
 #[get("/conversations/{conversation_id}")]
 async fn conversations_find_one(
     db: web::Data<DB>,
+    ReadUser(user): ReadUser,
     path: web::Path<String>,
 ) -> Result<HttpResponse, HttpError> {
     let conversation_id = path.into_inner();
-    let conversation = db.get_conversation(&conversation_id).await?;
+    let conversation = user.conversation(&db, &conversation_id).await?;
     Ok(HttpResponse::Ok().json(JsonApiResponse::success(vec![conversation], None)))
 }
 
 #[post("/conversations")]
 async fn conversations_new(
     db: web::Data<DB>,
+    user: AuthUser,
     new_conversation: web::Json<Conversation>,
 ) -> Result<HttpResponse, HttpError> {
+    // Creating never overwrites an existing conversation, so it can't take over another user's
+    match db.get_conversation(&new_conversation.id).await {
+        Ok(_) => {
+            user.conversation(&db, &new_conversation.id).await?;
+            return Err(HttpError::new(
+                StatusCode::CONFLICT.as_u16(),
+                format!("Conversation {} already exists", new_conversation.id),
+            ));
+        }
+        Err(SqlxError::RowNotFound) => {}
+        Err(err) => return Err(err.into()),
+    }
+
+    // Conversations belong to whoever creates them
+    let mut new_conversation = new_conversation.into_inner();
+    new_conversation.user_id = user.0.id;
+
     db.save_conversation(&new_conversation).await?;
     Ok(HttpResponse::Created().json(JsonApiResponse::success(vec![new_conversation], None)))
 }
@@ -91,6 +389,7 @@ async fn conversations_new(
 #[put("/conversations/{conversation_id}")]
 async fn conversations_save(
     db: web::Data<DB>,
+    user: AuthUser,
     path: web::Path<String>,
     conversation: web::Json<Conversation>,
 ) -> Result<HttpResponse, HttpError> {
@@ -106,6 +405,17 @@ async fn conversations_save(
         ));
     }
 
+    // Saving can create the conversation, but not take over another user's
+    match db.get_conversation(&path_id).await {
+        Ok(_) => {
+            user.conversation(&db, &path_id).await?;
+        }
+        Err(SqlxError::RowNotFound) => {}
+        Err(err) => return Err(err.into()),
+    }
+    let mut conversation = conversation.into_inner();
+    conversation.user_id = user.0.id;
+
     db.save_conversation(&conversation).await?;
     Ok(HttpResponse::Ok().json(JsonApiResponse::success(vec![conversation], None)))
 }
@@ -113,48 +423,302 @@ async fn conversations_save(
 #[delete("/conversations/{conversation_id}")]
 async fn conversations_delete(
     db: web::Data<DB>,
+    user: AuthUser,
     path: web::Path<String>,
 ) -> Result<HttpResponse, HttpError> {
     let conversation_id = path.into_inner();
+    user.conversation(&db, &conversation_id).await?;
     db.delete_conversation(&conversation_id).await?;
-    db.delete_messages_by_conversation(&conversation_id).await?;
 
     Ok(HttpResponse::Ok().finish())
 }
 
+#[post("/conversations/{conversation_id}/restore")]
+async fn conversations_restore(
+    db: web::Data<DB>,
+    user: AuthUser,
+    path: web::Path<String>,
+) -> Result<HttpResponse, HttpError> {
+    let conversation_id = path.into_inner();
+    user.conversation(&db, &conversation_id).await?;
+    // Restoring a conversation that isn't deleted changes nothing
+    db.restore_conversation(&conversation_id).await?;
+
+    let conversation = db.get_conversation(&conversation_id).await?;
+    Ok(HttpResponse::Ok().json(JsonApiResponse::success(vec![conversation], None)))
+}
+
+#[post("/conversations/{conversation_id}/reply")]
+async fn conversations_reply(
+    db: web::Data<DB>,
+    pool: Option<web::Data<InferencePool>>,
+    template: web::Data<ChatTemplate>,
+    jobs: web::Data<JobRegistry>,
+    user: AuthUser,
+    path: web::Path<String>,
+    body: web::Bytes,
+) -> Result<HttpResponse, HttpError> {
+    let conversation_id = path.into_inner();
+    user.conversation(&db, &conversation_id).await?;
+
+    let pool = pool.ok_or(HttpError::new(
+        StatusCode::SERVICE_UNAVAILABLE.as_u16(),
+        "No model is loaded".to_string(),
+    ))?;
+
+    // The body is optional, it overrides the voice's generation settings
+    let overrides = parse_overrides(&body)?;
+    model_ready(&db, &pool, &conversation_id).await?;
+    let job = start_job(&jobs, &conversation_id)?;
+    let reply = generate_reply(
+        &db,
+        pool,
+        **template,
+        job,
+        conversation_id,
+        overrides,
+        |_| true,
+    )
+    .await?;
+
+    Ok(HttpResponse::Created().json(JsonApiResponse::success(vec![reply], None)))
+}
+
+#[delete("/conversations/{conversation_id}/reply")]
+async fn conversations_reply_cancel(
+    db: web::Data<DB>,
+    jobs: web::Data<JobRegistry>,
+    user: AuthUser,
+    path: web::Path<String>,
+) -> Result<HttpResponse, HttpError> {
+    let conversation_id = path.into_inner();
+    user.conversation(&db, &conversation_id).await?;
+    match jobs.cancel(&conversation_id) {
+        true => Ok(HttpResponse::NoContent().finish()),
+        false => Err(HttpError::new(
+            StatusCode::NOT_FOUND.as_u16(),
+            "No reply is being generated for this conversation".to_string(),
+        )),
+    }
+}
+
+#[derive(Deserialize)]
+struct ReplyStreamQuery {
+    /// Generation settings overrides, as JSON
+    settings: Option<String>,
+}
+
+#[get("/conversations/{conversation_id}/reply/stream")]
+async fn conversations_reply_stream(
+    db: web::Data<DB>,
+    pool: Option<web::Data<InferencePool>>,
+    template: web::Data<ChatTemplate>,
+    jobs: web::Data<JobRegistry>,
+    user: AuthUser,
+    path: web::Path<String>,
+    query: web::Query<ReplyStreamQuery>,
+) -> Result<HttpResponse, HttpError> {
+    let conversation_id = path.into_inner();
+    user.conversation(&db, &conversation_id).await?;
+
+    let pool = pool.ok_or(HttpError::new(
+        StatusCode::SERVICE_UNAVAILABLE.as_u16(),
+        "No model is loaded".to_string(),
+    ))?;
+
+    // Fail with a regular response before the stream starts
+    let overrides = parse_overrides(query.settings.as_deref().unwrap_or_default().as_bytes())?;
+    model_ready(&db, &pool, &conversation_id).await?;
+    let job = start_job(&jobs, &conversation_id)?;
+
+    // Each token is sent as a "token" event, then a "done" event with the saved reply.
+    // A client that goes away drops the receiver, which stops the generation
+    let (sender, receiver) = mpsc::unbounded_channel::<web::Bytes>();
+    let token_sender = sender.clone();
+    rt::spawn(async move {
+        let on_text = move |text: &str| {
+            let token = ReplyToken {
+                content: text.to_string(),
+            };
+            token_sender.send(sse_event("token", &token)).is_ok()
+        };
+
+        let reply = generate_reply(
+            &db,
+            pool,
+            **template,
+            job,
+            conversation_id,
+            overrides,
+            on_text,
+        )
+        .await;
+        let event = match reply {
+            Ok(reply) => sse_event("done", &reply),
+            Err(err) => sse_event(
+                "failure",
+                &JsonApiResponse::<String>::error(vec![err.public_message()], None),
+            ),
+        };
+        let _ = sender.send(event);
+    });
+
+    let stream = stream::unfold(receiver, |mut receiver| async move {
+        receiver
+            .recv()
+            .await
+            .map(|event| (Ok::<_, Error>(event), receiver))
+    });
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        .insert_header(("X-Accel-Buffering", "no"))
+        .streaming(stream))
+}
+
+#[get("/ws/conversations/{conversation_id}")]
+async fn conversations_ws(
+    req: HttpRequest,
+    body: web::Payload,
+    db: web::Data<DB>,
+    pool: Option<web::Data<InferencePool>>,
+    template: web::Data<ChatTemplate>,
+    jobs: web::Data<JobRegistry>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, HttpError> {
+    // Fail with a regular response before the connection is upgraded,
+    // the user comes from the cookies of the upgrade request
+    let user = AuthUser::extract(&req).await?;
+    let conversation_id = path.into_inner();
+    user.conversation(&db, &conversation_id).await?;
+
+    let pool = pool.ok_or(HttpError::new(
+        StatusCode::SERVICE_UNAVAILABLE.as_u16(),
+        "No model is loaded".to_string(),
+    ))?;
+
+    let (response, mut session, mut stream) = actix_ws::handle(&req, body)
+        .map_err(|err| HttpError::new(StatusCode::BAD_REQUEST.as_u16(), err.to_string()))?;
+
+    let (incoming, frames) = mpsc::unbounded_channel::<ChatFrame>();
+    let (outgoing, mut replies) = mpsc::unbounded_channel::<ChatFrame>();
+    rt::spawn(run_chat(
+        db.get_ref().clone(),
+        pool,
+        **template,
+        jobs.get_ref().clone(),
+        conversation_id,
+        frames,
+        outgoing.clone(),
+    ));
+
+    // Send the frames to the client, until the chat session is over
+    let mut writer = session.clone();
+    rt::spawn(async move {
+        while let Some(frame) = replies.recv().await {
+            let text = serde_json::to_string(&frame).unwrap_or_default();
+            if writer.text(text).await.is_err() {
+                return;
+            }
+        }
+        let _ = writer.close(None).await;
+    });
+
+    // Read the frames from the client, until the client goes away
+    rt::spawn(async move {
+        while let Some(Ok(message)) = stream.recv().await {
+            let closed = match message {
+                ws::Message::Text(text) => match serde_json::from_str::<ChatFrame>(&text) {
+                    Ok(frame) => incoming.send(frame).is_err(),
+                    Err(err) => {
+                        let _ = outgoing.send(ChatFrame::Error {
+                            errors: vec![format!("Invalid frame: {}", err)],
+                        });
+                        false
+                    }
+                },
+                ws::Message::Ping(bytes) => session.pong(&bytes).await.is_err(),
+                ws::Message::Close(_) => true,
+                _ => false,
+            };
+            if closed {
+                break;
+            }
+        }
+    });
+
+    Ok(response)
+}
+
 #[derive(Deserialize)]
 struct MessagesQuery {
     conversation_id: String,
+    #[serde(default)]
+    include_deleted: bool,
+    limit: Option<i64>,
+    before: Option<String>,
+    after: Option<String>,
 }
 
 #[get("/messages")]
 async fn messages_find_all(
     db: web::Data<DB>,
+    ReadUser(user): ReadUser,
     query_params: web::Query<MessagesQuery>,
 ) -> Result<HttpResponse, HttpError> {
-    // An empty response is a valid response, so unwrap to an empty vec instead of 404 error
+    user.conversation(&db, &query_params.conversation_id)
+        .await?;
+    let page = page_from_query(
+        query_params.limit,
+        &query_params.before,
+        &query_params.after,
+    )?;
+
     let messages = db
-        .get_messages(&query_params.conversation_id, false)
-        .await
-        .unwrap_or(Vec::new());
-    Ok(HttpResponse::Ok().json(JsonApiResponse::success(messages, None)))
+        .get_messages(
+            &query_params.conversation_id,
+            query_params.include_deleted,
+            &page,
+        )
+        .await?;
+    let meta = Meta {
+        next_cursor: messages.next.map(|cursor| cursor.to_string()),
+    };
+    Ok(HttpResponse::Ok().json(JsonApiResponse::success(messages.items, None).with_meta(meta)))
 }
 
 #[get("/messages/{message_id}")]
 async fn messages_find_one(
     db: web::Data<DB>,
+    ReadUser(user): ReadUser,
     path: web::Path<String>,
 ) -> Result<HttpResponse, HttpError> {
     let message_id = path.into_inner();
-    let message = db.get_message(&message_id).await?;
+    let message = user.message(&db, &message_id).await?;
     Ok(HttpResponse::Ok().json(JsonApiResponse::success(vec![message], None)))
 }
 
 #[post("/messages")]
 async fn messages_new(
     db: web::Data<DB>,
+    user: AuthUser,
     new_message: web::Json<Message>,
 ) -> Result<HttpResponse, HttpError> {
+    // Creating never overwrites an existing message, so it can't change another user's
+    match db.get_message(&new_message.id).await {
+        Ok(_) => {
+            user.message(&db, &new_message.id).await?;
+            return Err(HttpError::new(
+                StatusCode::CONFLICT.as_u16(),
+                format!("Message {} already exists", new_message.id),
+            ));
+        }
+        Err(SqlxError::RowNotFound) => {}
+        Err(err) => return Err(err.into()),
+    }
+    user.check_message_conversation(&db, &new_message).await?;
+
     db.save_message(&new_message).await?;
     Ok(HttpResponse::Created().json(JsonApiResponse::success(vec![new_message], None)))
 }
@@ -162,6 +726,7 @@ async fn messages_new(
 #[put("/messages/{message_id}")]
 async fn messages_save(
     db: web::Data<DB>,
+    user: AuthUser,
     path: web::Path<String>,
     message: web::Json<Message>,
 ) -> Result<HttpResponse, HttpError> {
@@ -177,17 +742,144 @@ async fn messages_save(
         ));
     }
 
+    // Saving can create the message, but not change another user's,
+    // or move it to their conversation
+    match db.get_message(&path_id).await {
+        Ok(_) => {
+            user.message(&db, &path_id).await?;
+        }
+        Err(SqlxError::RowNotFound) => {}
+        Err(err) => return Err(err.into()),
+    }
+    user.check_message_conversation(&db, &message).await?;
+
     db.save_message(&message).await?;
     Ok(HttpResponse::Ok().json(JsonApiResponse::success(vec![message], None)))
 }
 
+#[post("/messages/{message_id}/restore")]
+async fn messages_restore(
+    db: web::Data<DB>,
+    user: AuthUser,
+    path: web::Path<String>,
+) -> Result<HttpResponse, HttpError> {
+    let message_id = path.into_inner();
+    let message = user.message(&db, &message_id).await?;
+
+    // A message can't come back without its conversation
+    let conversation = db.get_conversation(&message.conversation_id).await?;
+    if conversation.deleted_at.is_some() {
+        return Err(HttpError::new(
+            StatusCode::CONFLICT.as_u16(),
+            format!(
+                "Conversation {} is deleted, restore it first",
+                conversation.id
+            ),
+        ));
+    }
+    db.restore_message(&message_id).await?;
+
+    let message = db.get_message(&message_id).await?;
+    Ok(HttpResponse::Ok().json(JsonApiResponse::success(vec![message], None)))
+}
+
+#[get("/models/status")]
+async fn models_status(pool: Option<web::Data<InferencePool>>) -> Result<HttpResponse, HttpError> {
+    // Without a model there is nothing to report
+    let statuses = pool
+        .map(|pool| pool.models().statuses())
+        .unwrap_or_default();
+    Ok(HttpResponse::Ok().json(JsonApiResponse::success(statuses, None)))
+}
+
+/// The number of search results when no limit is given
+const DEFAULT_SEARCH_LIMIT: i64 = 20;
+
+#[derive(Deserialize)]
+struct SearchQuery {
+    q: String,
+    limit: Option<i64>,
+}
+
+#[get("/search")]
+async fn search(
+    db: web::Data<DB>,
+    ReadUser(user): ReadUser,
+    query_params: web::Query<SearchQuery>,
+) -> Result<HttpResponse, HttpError> {
+    let mut errors = Vec::new();
+    if query_params.q.trim().is_empty() {
+        errors.push("q must not be empty".to_string());
+    }
+    let limit = query_params.limit.unwrap_or(DEFAULT_SEARCH_LIMIT);
+    if !(1..=MAX_PAGE_LIMIT).contains(&limit) {
+        errors.push(format!("limit must be between 1 and {}", MAX_PAGE_LIMIT));
+    }
+    if !errors.is_empty() {
+        return Err(HttpError::invalid(errors));
+    }
+
+    let results = db
+        .search_messages(&user.0.id, &query_params.q, limit)
+        .await?;
+    Ok(HttpResponse::Ok().json(JsonApiResponse::success(results, None)))
+}
+
+#[post("/admin/purge")]
+async fn admin_purge(
+    purger: web::Data<Purger>,
+    _admin: AdminUser,
+) -> Result<HttpResponse, HttpError> {
+    let report = purger.purge().await?;
+    Ok(HttpResponse::Ok().json(JsonApiResponse::success(vec![report], None)))
+}
+
+#[derive(Deserialize)]
+struct RoleBody {
+    role: Role,
+}
+
+#[put("/admin/users/{user_id}/role")]
+async fn admin_user_role(
+    db: web::Data<DB>,
+    admin: AdminUser,
+    path: web::Path<String>,
+    body: web::Json<RoleBody>,
+) -> Result<HttpResponse, HttpError> {
+    // So there is always an admin left
+    let user_id = path.into_inner();
+    if user_id == admin.0.id {
+        return Err(HttpError::new(
+            StatusCode::CONFLICT.as_u16(),
+            "Admins can't change their own role".to_string(),
+        ));
+    }
+    match db.set_user_role(&user_id, body.role).await? {
+        true => Ok(HttpResponse::Ok().finish()),
+        false => Err(SqlxError::RowNotFound.into()),
+    }
+}
+
 /// Populate all the routes onto an App Service Configuration
 pub fn init_routes(config: &mut web::ServiceConfig) {
+    // Auth
+    config.service(auth_register);
+    config.service(auth_login);
+    config.service(auth_logout);
+    config.service(auth_me);
+    config.service(auth_claim);
+
+    // API keys
+    config.service(api_keys_find_all);
+    config.service(api_keys_new);
+    config.service(api_keys_delete);
+
     // Voices
     config.service(voices_find_all);
     config.service(voices_find_one);
     config.service(voices_new);
     config.service(voices_save);
+    config.service(voices_delete);
 
     // Conversations
     config.service(conversations_find_all);
@@ -195,10 +887,1375 @@ pub fn init_routes(config: &mut web::ServiceConfig) {
     config.service(conversations_new);
     config.service(conversations_save);
     config.service(conversations_delete);
+    config.service(conversations_restore);
+    config.service(conversations_reply);
+    config.service(conversations_reply_cancel);
+    config.service(conversations_reply_stream);
+    config.service(conversations_ws);
 
     // Messages
     config.service(messages_find_all);
     config.service(messages_find_one);
     config.service(messages_new);
     config.service(messages_save);
+    config.service(messages_restore);
+
+    // Models
+    config.service(models_status);
+
+    // Search
+    config.service(search);
+
+    // Admin
+    config.service(admin_purge);
+    config.service(admin_user_role);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use actix_web::{
+        body::MessageBody,
+        cookie::Cookie,
+        dev::{Service, ServiceFactory, ServiceRequest, ServiceResponse},
+        http::{header, StatusCode},
+        middleware::from_fn,
+        rt, test, web, App,
+    };
+    use models::{
+        ApiKey, Author, Claim, ClaimReport, Conversation, CreatedApiKey, Credentials,
+        GenerationSettings, JsonApiResponse, Message, ModelState, ModelStatus, NewApiKey,
+        PurgeReport, Reply, ReplyToken, Role, Scope, SearchResult, User, Voice,
+    };
+
+    use super::init_routes;
+    use crate::api::auth::{authenticate, bootstrap_admin, start_session, SESSION_COOKIE};
+    use crate::db::DB;
+    use crate::jobs::JobRegistry;
+    use crate::llm::{Inference, InferencePool, MockLlm, ModelRegistry, RegistryConfig};
+    use crate::prompt::ChatTemplate;
+    use crate::purge::Purger;
+
+    /// Build a test DB with a voice, and a conversation of a user
+    async fn setup_db() -> (DB, Conversation) {
+        let db = DB::new("sqlite::memory:").await.unwrap();
+        db.assert_schema().await.unwrap();
+
+        let voice = Voice::new(
+            "Shaun".to_string(),
+            "It's me".to_string(),
+            "I'm boring".to_string(),
+        );
+        db.save_voice(&voice).await.unwrap();
+        let user = setup_user(&db, "shaun").await;
+        let conversation =
+            Conversation::new(user.id, "Test Conversation".to_string(), voice.id.clone());
+        db.save_conversation(&conversation).await.unwrap();
+
+        (db, conversation)
+    }
+
+    /// Save a user that can only log in through [`login`]
+    ///
+    /// Arguments:
+    /// - db: The database to save the user in
+    /// - username: The name of the user
+    async fn setup_user(db: &DB, username: &str) -> User {
+        let user = User::new(username.to_string());
+        db.save_user(&user, "not a hash").await.unwrap();
+        user
+    }
+
+    /// Save an admin that can only log in through [`login`]
+    ///
+    /// Arguments:
+    /// - db: The database to save the admin in
+    async fn setup_admin(db: &DB) -> User {
+        let mut admin = User::new("admin".to_string());
+        admin.role = Role::Admin;
+        db.save_user(&admin, "not a hash").await.unwrap();
+        admin
+    }
+
+    /// Log a user in, returning their session cookie
+    ///
+    /// Arguments:
+    /// - db: The database the user is in
+    /// - user_id: The id of the user to log in
+    async fn login(db: &DB, user_id: &str) -> Cookie<'static> {
+        start_session(db, user_id).await.unwrap()
+    }
+
+    /// The session cookie a response sets
+    ///
+    /// Arguments:
+    /// - res: The response of a register or login
+    fn session_cookie<B>(res: &ServiceResponse<B>) -> Cookie<'static> {
+        res.response()
+            .cookies()
+            .find(|cookie| cookie.name() == SESSION_COOKIE)
+            .unwrap()
+            .into_owned()
+    }
+
+    /// The routes behind the [`authenticate`] middleware, with the app data they share
+    ///
+    /// Tests add the inference pool, or replace the other data, with `app_data`
+    ///
+    /// Arguments:
+    /// - db: The database of the app
+    fn test_app(
+        db: &DB,
+    ) -> App<
+        impl ServiceFactory<
+            ServiceRequest,
+            Config = (),
+            Response = ServiceResponse<impl MessageBody>,
+            Error = actix_web::Error,
+            InitError = (),
+        >,
+    > {
+        App::new()
+            .wrap(from_fn(authenticate))
+            .app_data(web::Data::new(db.clone()))
+            .app_data(web::Data::new(ChatTemplate::default()))
+            .app_data(web::Data::new(JobRegistry::default()))
+            .configure(init_routes)
+    }
+
+    /// Wrap a mock backend as app data
+    fn mock_data(mock: MockLlm) -> web::Data<InferencePool> {
+        web::Data::new(InferencePool::new(
+            ModelRegistry::single(Arc::new(mock)),
+            1,
+            4,
+        ))
+    }
+
+    #[actix_web::test]
+    async fn test_reply_without_model() {
+        let (db, conversation) = setup_db().await;
+        let cookie = login(&db, &conversation.user_id).await;
+
+        let app = test::init_service(test_app(&db)).await;
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/conversations/{}/reply", conversation.id))
+            .cookie(cookie.clone())
+            .to_request();
+
+        let res = app.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[actix_web::test]
+    async fn test_reply_missing_conversation() {
+        let (db, conversation) = setup_db().await;
+        let cookie = login(&db, &conversation.user_id).await;
+
+        let app = test::init_service(test_app(&db).app_data(mock_data(MockLlm::Echo))).await;
+
+        let req = test::TestRequest::post()
+            .uri("/conversations/missing/reply")
+            .cookie(cookie.clone())
+            .to_request();
+
+        let res = app.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn test_chat_flow() {
+        let (db, conversation) = setup_db().await;
+        let mock = MockLlm::from_script_file(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/fixtures/mock_script.json"
+        ))
+        .unwrap();
+
+        let cookie = login(&db, &conversation.user_id).await;
+
+        let app = test::init_service(test_app(&db).app_data(mock_data(mock))).await;
+
+        // The user says hello
+        let message = Message::new(conversation.id.clone(), Author::User, "hello".to_string());
+        let req = test::TestRequest::post()
+            .uri("/messages")
+            .cookie(cookie.clone())
+            .set_json(&message)
+            .to_request();
+        let res = app.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::CREATED);
+
+        // Creating it again doesn't overwrite it
+        let req = test::TestRequest::post()
+            .uri("/messages")
+            .cookie(cookie.clone())
+            .set_json(&message)
+            .to_request();
+        let res = app.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+
+        // And the voice replies from the script
+        let req = test::TestRequest::post()
+            .uri(&format!("/conversations/{}/reply", conversation.id))
+            .cookie(cookie.clone())
+            .to_request();
+        let res: JsonApiResponse<Reply> = test::call_and_read_body_json(&app, req).await;
+        let reply = res.data.unwrap().remove(0);
+        assert_eq!(reply.message.author, Author::Voice);
+        assert_eq!(reply.message.content, "Hello! How can I help?");
+        assert!(reply.excluded_message_ids.is_empty());
+
+        // Both messages are saved to the conversation
+        let req = test::TestRequest::get()
+            .uri(&format!("/messages?conversation_id={}", conversation.id))
+            .cookie(cookie.clone())
+            .to_request();
+        let res: JsonApiResponse<Message> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(res.data.unwrap().len(), 2);
+    }
+
+    #[actix_web::test]
+    async fn test_reply_while_model_loads() {
+        let (db, conversation) = setup_db().await;
+        let config =
+            RegistryConfig::from_model_path("llama-7b.bin".to_string(), None, 2048).unwrap();
+        let models = ModelRegistry::new(config, |_, _| {
+            Ok(Arc::new(MockLlm::canned(vec!["Hi".to_string()])) as Arc<dyn Inference>)
+        });
+
+        let cookie = login(&db, &conversation.user_id).await;
+
+        let app = test::init_service(
+            test_app(&db).app_data(web::Data::new(InferencePool::new(models, 1, 4))),
+        )
+        .await;
+
+        // The first reply starts loading the model
+        let req = test::TestRequest::post()
+            .uri(&format!("/conversations/{}/reply", conversation.id))
+            .cookie(cookie.clone())
+            .to_request();
+        let res = app.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        // Wait for the model to be ready
+        loop {
+            let req = test::TestRequest::get().uri("/models/status").to_request();
+            let res: JsonApiResponse<ModelStatus> = test::call_and_read_body_json(&app, req).await;
+            let status = res.data.unwrap().remove(0);
+            assert!(status.default);
+            if status.state == ModelState::Ready {
+                assert_eq!(status.progress, 100);
+                break;
+            }
+            rt::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/conversations/{}/reply", conversation.id))
+            .cookie(cookie.clone())
+            .to_request();
+        let res = app.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::CREATED);
+    }
+
+    #[actix_web::test]
+    async fn test_models_status_without_model() {
+        let app = test::init_service(App::new().configure(init_routes)).await;
+
+        let req = test::TestRequest::get().uri("/models/status").to_request();
+        let res: JsonApiResponse<ModelStatus> = test::call_and_read_body_json(&app, req).await;
+        assert!(res.data.unwrap().is_empty());
+    }
+
+    #[actix_web::test]
+    async fn test_reply_unknown_model() {
+        let (db, conversation) = setup_db().await;
+
+        let mut voice = db.get_voice(&conversation.voice_id).await.unwrap();
+        voice.model_id = Some("missing".to_string());
+        db.save_voice(&voice).await.unwrap();
+
+        let cookie = login(&db, &conversation.user_id).await;
+
+        let app = test::init_service(test_app(&db).app_data(mock_data(MockLlm::Echo))).await;
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/conversations/{}/reply", conversation.id))
+            .cookie(cookie.clone())
+            .to_request();
+
+        let res = app.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[actix_web::test]
+    async fn test_reply_generation_settings() {
+        let (db, conversation) = setup_db().await;
+
+        let mut voice = db.get_voice(&conversation.voice_id).await.unwrap();
+        voice.generation.max_tokens = Some(2);
+        db.save_voice(&voice).await.unwrap();
+
+        let cookie = login(&db, &conversation.user_id).await;
+
+        let app = test::init_service(test_app(&db).app_data(mock_data(MockLlm::canned(vec![
+            "one two three four".to_string(),
+        ]))))
+        .await;
+        let uri = format!("/conversations/{}/reply", conversation.id);
+
+        // The voice's settings are used
+        let req = test::TestRequest::post()
+            .uri(&uri)
+            .cookie(cookie.clone())
+            .to_request();
+        let res: JsonApiResponse<Reply> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(res.data.unwrap()[0].message.content, "one two");
+
+        // And can be overridden per request
+        let req = test::TestRequest::post()
+            .uri(&uri)
+            .cookie(cookie.clone())
+            .set_json(GenerationSettings {
+                max_tokens: Some(3),
+                ..Default::default()
+            })
+            .to_request();
+        let res: JsonApiResponse<Reply> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(res.data.unwrap()[0].message.content, "one two three");
+
+        // Invalid overrides are rejected
+        let req = test::TestRequest::post()
+            .uri(&uri)
+            .cookie(cookie.clone())
+            .set_json(GenerationSettings {
+                temperature: Some(3.0),
+                ..Default::default()
+            })
+            .to_request();
+        let res = app.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[actix_web::test]
+    async fn test_messages_pagination() {
+        let (db, conversation) = setup_db().await;
+        for content in ["one", "two", "three"] {
+            let message = Message::new(conversation.id.clone(), Author::User, content.to_string());
+            db.save_message(&message).await.unwrap();
+        }
+
+        let cookie = login(&db, &conversation.user_id).await;
+
+        let app = test::init_service(test_app(&db)).await;
+
+        let req = test::TestRequest::get()
+            .uri(&format!(
+                "/messages?conversation_id={}&limit=2",
+                conversation.id
+            ))
+            .cookie(cookie.clone())
+            .to_request();
+        let res: JsonApiResponse<Message> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(res.data.unwrap().len(), 2);
+        let cursor = res.meta.unwrap().next_cursor.unwrap();
+
+        let req = test::TestRequest::get()
+            .uri(&format!(
+                "/messages?conversation_id={}&limit=2&after={}",
+                conversation.id, cursor
+            ))
+            .cookie(cookie.clone())
+            .to_request();
+        let res: JsonApiResponse<Message> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(res.data.unwrap().len(), 1);
+        assert_eq!(res.meta.unwrap().next_cursor, None);
+
+        // Invalid pages are rejected
+        let req = test::TestRequest::get()
+            .uri(&format!(
+                "/messages?conversation_id={}&limit=0&before=soon",
+                conversation.id
+            ))
+            .cookie(cookie.clone())
+            .to_request();
+        let res = app.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let res: JsonApiResponse<String> = test::read_body_json(res).await;
+        let errors = res.errors.unwrap();
+        assert!(errors[0].contains("limit"));
+        assert!(errors[0].contains("before is not a valid cursor"));
+    }
+
+    #[actix_web::test]
+    async fn test_admin_purge() {
+        let (db, mut conversation) = setup_db().await;
+        conversation.deleted_at = Some(1);
+        db.save_conversation(&conversation).await.unwrap();
+
+        let cookie = login(&db, &conversation.user_id).await;
+        let admin = setup_admin(&db).await;
+        let admin = login(&db, &admin.id).await;
+
+        let app = test::init_service(
+            test_app(&db).app_data(web::Data::new(Purger::new(db, Duration::ZERO))),
+        )
+        .await;
+
+        // Only admins can purge
+        let req = test::TestRequest::post()
+            .uri("/admin/purge")
+            .cookie(cookie.clone())
+            .to_request();
+        let res = app.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        let req = test::TestRequest::post()
+            .uri("/admin/purge")
+            .cookie(admin)
+            .to_request();
+        let res: JsonApiResponse<PurgeReport> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(res.data.unwrap()[0].conversations, 1);
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/conversations/{}", conversation.id))
+            .cookie(cookie.clone())
+            .to_request();
+        let res = app.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn test_bootstrap_admin() {
+        let db = DB::new("sqlite::memory:").await.unwrap();
+        db.assert_schema().await.unwrap();
+        let app = test::init_service(test_app(&db)).await;
+
+        // Registering never makes an admin, even with the name the server will promote
+        let req = test::TestRequest::post()
+            .uri("/auth/register")
+            .set_json(Credentials {
+                username: "ferris".to_string(),
+                password: "correct horse".to_string(),
+            })
+            .to_request();
+        let res: JsonApiResponse<User> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(res.data.unwrap()[0].role, Role::User);
+
+        // The named user is promoted at startup, once they have registered
+        assert!(!bootstrap_admin(&db, "missing").await.unwrap());
+        assert!(bootstrap_admin(&db, "ferris").await.unwrap());
+        assert!(!bootstrap_admin(&db, "ferris").await.unwrap());
+        let (ferris, _) = db.get_user_login("ferris").await.unwrap();
+        assert_eq!(ferris.role, Role::Admin);
+    }
+
+    #[actix_web::test]
+    async fn test_voice_admin() {
+        let (db, conversation) = setup_db().await;
+        let cookie = login(&db, &conversation.user_id).await;
+        let admin = setup_admin(&db).await;
+        let admin = login(&db, &admin.id).await;
+
+        let app = test::init_service(test_app(&db)).await;
+
+        // Only admins manage the voices
+        let voice = Voice::new("Gwen".to_string(), "A dog".to_string(), "Woof".to_string());
+        let voice_uri = format!("/voices/{}", voice.id);
+        for req in [
+            test::TestRequest::post().uri("/voices").set_json(&voice),
+            test::TestRequest::put().uri(&voice_uri).set_json(&voice),
+            test::TestRequest::delete().uri(&format!("/voices/{}", conversation.voice_id)),
+        ] {
+            let res = app
+                .call(req.cookie(cookie.clone()).to_request())
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        }
+        let req = test::TestRequest::post()
+            .uri("/voices")
+            .set_json(&voice)
+            .to_request();
+        let res = app.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        for (req, status) in [
+            (
+                test::TestRequest::post().uri("/voices").set_json(&voice),
+                StatusCode::CREATED,
+            ),
+            (
+                test::TestRequest::put().uri(&voice_uri).set_json(&voice),
+                StatusCode::OK,
+            ),
+            (
+                test::TestRequest::delete().uri(&format!("/voices/{}", conversation.voice_id)),
+                StatusCode::OK,
+            ),
+            (
+                test::TestRequest::delete().uri("/voices/missing"),
+                StatusCode::NOT_FOUND,
+            ),
+        ] {
+            let res = app
+                .call(req.cookie(admin.clone()).to_request())
+                .await
+                .unwrap();
+            assert_eq!(res.status(), status);
+        }
+
+        // Conversations with a deleted voice can still be shown
+        let req = test::TestRequest::get()
+            .uri(&format!("/conversations/{}", conversation.id))
+            .cookie(cookie)
+            .to_request();
+        let res = app.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let req = test::TestRequest::get()
+            .uri("/voices?include_deleted=true")
+            .to_request();
+        let res: JsonApiResponse<Voice> = test::call_and_read_body_json(&app, req).await;
+        let deleted = res
+            .data
+            .unwrap()
+            .into_iter()
+            .find(|voice| voice.id == conversation.voice_id)
+            .unwrap();
+        assert!(deleted.deleted_at.is_some());
+    }
+
+    #[actix_web::test]
+    async fn test_admin_user_role() {
+        let (db, conversation) = setup_db().await;
+        let cookie = login(&db, &conversation.user_id).await;
+        let admin = setup_admin(&db).await;
+        let admin_cookie = login(&db, &admin.id).await;
+
+        let app = test::init_service(test_app(&db)).await;
+
+        let role_request = |user_id: &str| {
+            test::TestRequest::put()
+                .uri(&format!("/admin/users/{}/role", user_id))
+                .set_json(serde_json::json!({ "role": "admin" }))
+        };
+
+        // Users can't make themselves admins
+        let req = role_request(&conversation.user_id)
+            .cookie(cookie.clone())
+            .to_request();
+        let res = app.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        // Admins can, but not change their own role
+        for (user_id, status) in [
+            (conversation.user_id.as_str(), StatusCode::OK),
+            (admin.id.as_str(), StatusCode::CONFLICT),
+            ("missing", StatusCode::NOT_FOUND),
+        ] {
+            let req = role_request(user_id)
+                .cookie(admin_cookie.clone())
+                .to_request();
+            let res = app.call(req).await.unwrap();
+            assert_eq!(res.status(), status);
+        }
+
+        let req = test::TestRequest::get()
+            .uri("/auth/me")
+            .cookie(cookie)
+            .to_request();
+        let res: JsonApiResponse<User> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(res.data.unwrap()[0].role, Role::Admin);
+    }
+
+    #[actix_web::test]
+    async fn test_api_keys() {
+        let (db, conversation) = setup_db().await;
+        let cookie = login(&db, &conversation.user_id).await;
+        let someone_else = setup_user(&db, "someone-else").await;
+        let someone_else = login(&db, &someone_else.id).await;
+        // Even an admin's keys don't reach admin routes
+        db.set_user_role(&conversation.user_id, Role::Admin)
+            .await
+            .unwrap();
+
+        let app = test::init_service(test_app(&db).app_data(mock_data(MockLlm::Echo))).await;
+
+        let mut tokens = Vec::new();
+        for scope in [Scope::Read, Scope::Chat] {
+            let req = test::TestRequest::post()
+                .uri("/api-keys")
+                .cookie(cookie.clone())
+                .set_json(NewApiKey {
+                    name: format!("{} key", scope),
+                    scope,
+                })
+                .to_request();
+            let res: JsonApiResponse<CreatedApiKey> =
+                test::call_and_read_body_json(&app, req).await;
+            let created = res.data.unwrap().remove(0);
+            assert_eq!(created.api_key.scope, scope);
+            tokens.push(created.token);
+        }
+        let bearer = |token: &str| (header::AUTHORIZATION, format!("Bearer {}", token));
+        let message = Message::new(conversation.id.clone(), Author::User, "Hi".to_string());
+
+        // Keys act as their user, within their scope
+        for (req, token, status) in [
+            (
+                test::TestRequest::get().uri("/conversations"),
+                &tokens[0],
+                StatusCode::OK,
+            ),
+            (
+                test::TestRequest::post()
+                    .uri("/messages")
+                    .set_json(&message),
+                &tokens[0],
+                StatusCode::FORBIDDEN,
+            ),
+            (
+                test::TestRequest::post()
+                    .uri("/messages")
+                    .set_json(&message),
+                &tokens[1],
+                StatusCode::CREATED,
+            ),
+            (
+                test::TestRequest::get().uri("/api-keys"),
+                &tokens[1],
+                StatusCode::FORBIDDEN,
+            ),
+            (
+                test::TestRequest::delete().uri(&format!("/voices/{}", conversation.voice_id)),
+                &tokens[1],
+                StatusCode::FORBIDDEN,
+            ),
+            // Scopes are checked on the route, however its path is encoded
+            (
+                test::TestRequest::get().uri(&format!(
+                    "/conversations/{}/reply/strea%6D",
+                    conversation.id
+                )),
+                &tokens[0],
+                StatusCode::FORBIDDEN,
+            ),
+            (
+                test::TestRequest::get().uri("/api%2Dkeys"),
+                &tokens[0],
+                StatusCode::FORBIDDEN,
+            ),
+            (
+                test::TestRequest::post()
+                    .uri("/api%2Dkeys")
+                    .set_json(NewApiKey {
+                        name: "Another key".to_string(),
+                        scope: Scope::Chat,
+                    }),
+                &tokens[1],
+                StatusCode::FORBIDDEN,
+            ),
+            (
+                test::TestRequest::get().uri("/conversations"),
+                &"not a key".to_string(),
+                StatusCode::UNAUTHORIZED,
+            ),
+        ] {
+            let res = app
+                .call(req.insert_header(bearer(token)).to_request())
+                .await
+                .unwrap();
+            assert_eq!(res.status(), status);
+        }
+
+        // The keys are listed without the key itself, along with when they were last used
+        let req = test::TestRequest::get()
+            .uri("/api-keys")
+            .cookie(cookie.clone())
+            .to_request();
+        let res: JsonApiResponse<ApiKey> = test::call_and_read_body_json(&app, req).await;
+        let api_keys = res.data.unwrap();
+        assert_eq!(api_keys.len(), 2);
+        assert!(api_keys
+            .iter()
+            .all(|api_key| api_key.last_used_at.is_some()));
+
+        // Only their user can revoke them
+        let read_key = api_keys
+            .iter()
+            .find(|api_key| api_key.scope == Scope::Read)
+            .unwrap();
+        let uri = format!("/api-keys/{}", read_key.id);
+        for (cookie, status) in [
+            (someone_else, StatusCode::NOT_FOUND),
+            (cookie.clone(), StatusCode::NO_CONTENT),
+        ] {
+            let req = test::TestRequest::delete()
+                .uri(&uri)
+                .cookie(cookie)
+                .to_request();
+            let res = app.call(req).await.unwrap();
+            assert_eq!(res.status(), status);
+        }
+        let req = test::TestRequest::get()
+            .uri("/conversations")
+            .insert_header(bearer(&tokens[0]))
+            .to_request();
+        let res = app.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        let req = test::TestRequest::post()
+            .uri("/api-keys")
+            .cookie(cookie)
+            .set_json(NewApiKey {
+                name: " ".to_string(),
+                scope: Scope::Read,
+            })
+            .to_request();
+        let res = app.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[actix_web::test]
+    async fn test_auth() {
+        let (db, existing) = setup_db().await;
+
+        let app = test::init_service(test_app(&db)).await;
+
+        let credentials = |username: &str, password: &str| Credentials {
+            username: username.to_string(),
+            password: password.to_string(),
+        };
+
+        let req = test::TestRequest::post()
+            .uri("/auth/register")
+            .set_json(credentials("ferris", "correct horse"))
+            .to_request();
+        let res = app.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::CREATED);
+        let cookie = session_cookie(&res);
+        assert!(cookie.http_only().unwrap());
+
+        // Usernames are unique, and checked
+        for (username, password, status) in [
+            ("ferris", "correct horse", StatusCode::CONFLICT),
+            ("f", "short", StatusCode::UNPROCESSABLE_ENTITY),
+        ] {
+            let req = test::TestRequest::post()
+                .uri("/auth/register")
+                .set_json(credentials(username, password))
+                .to_request();
+            let res = app.call(req).await.unwrap();
+            assert_eq!(res.status(), status);
+        }
+
+        let req = test::TestRequest::get()
+            .uri("/auth/me")
+            .cookie(cookie.clone())
+            .to_request();
+        let res: JsonApiResponse<User> = test::call_and_read_body_json(&app, req).await;
+        let user = res.data.unwrap().remove(0);
+        assert_eq!(user.username, "ferris");
+        assert_eq!(user.role, Role::User);
+
+        // Conversations belong to whoever creates them, whatever the body says
+        let mut conversation = Conversation::new(
+            "someone-else".to_string(),
+            "Mine".to_string(),
+            existing.voice_id,
+        );
+        let req = test::TestRequest::post()
+            .uri("/conversations")
+            .cookie(cookie.clone())
+            .set_json(&conversation)
+            .to_request();
+        let res = app.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::CREATED);
+        conversation.user_id = user.id.clone();
+
+        let req = test::TestRequest::get()
+            .uri("/conversations")
+            .cookie(cookie.clone())
+            .to_request();
+        let res: JsonApiResponse<Conversation> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(res.data.unwrap(), vec![conversation]);
+
+        let req = test::TestRequest::get().uri("/conversations").to_request();
+        let res = app.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        // Logging out ends the session
+        let req = test::TestRequest::post()
+            .uri("/auth/logout")
+            .cookie(cookie.clone())
+            .to_request();
+        let res = app.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let req = test::TestRequest::get()
+            .uri("/auth/me")
+            .cookie(cookie)
+            .to_request();
+        let res = app.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        let req = test::TestRequest::post()
+            .uri("/auth/login")
+            .set_json(credentials("ferris", "battery staple"))
+            .to_request();
+        let res = app.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        let req = test::TestRequest::post()
+            .uri("/auth/login")
+            .set_json(credentials("ferris", "correct horse"))
+            .to_request();
+        let res = app.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let req = test::TestRequest::get()
+            .uri("/auth/me")
+            .cookie(session_cookie(&res))
+            .to_request();
+        let res = app.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn test_claim() {
+        let (db, existing) = setup_db().await;
+        let anonymous_id = "from-local-storage".to_string();
+        let conversation = Conversation::new(
+            anonymous_id.clone(),
+            "From before accounts".to_string(),
+            existing.voice_id,
+        );
+        db.save_conversation(&conversation).await.unwrap();
+        let cookie = login(&db, &existing.user_id).await;
+
+        let app = test::init_service(test_app(&db)).await;
+
+        // Claiming again moves nothing
+        let claim = Claim { anonymous_id };
+        for conversations in [1, 0] {
+            let req = test::TestRequest::post()
+                .uri("/auth/claim")
+                .cookie(cookie.clone())
+                .set_json(&claim)
+                .to_request();
+            let res: JsonApiResponse<ClaimReport> = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(res.data.unwrap()[0].conversations, conversations);
+        }
+        assert_eq!(
+            db.get_conversation(&conversation.id).await.unwrap().user_id,
+            existing.user_id
+        );
+
+        let req = test::TestRequest::post()
+            .uri("/auth/claim")
+            .set_json(&claim)
+            .to_request();
+        let res = app.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        let req = test::TestRequest::post()
+            .uri("/auth/claim")
+            .cookie(cookie)
+            .set_json(Claim {
+                anonymous_id: " ".to_string(),
+            })
+            .to_request();
+        let res = app.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[actix_web::test]
+    async fn test_ownership() {
+        let (db, conversation) = setup_db().await;
+        let message = Message::new(conversation.id.clone(), Author::User, "Hi".to_string());
+        db.save_message(&message).await.unwrap();
+        let someone_else = setup_user(&db, "someone-else").await;
+        let cookie = login(&db, &someone_else.id).await;
+
+        let app = test::init_service(test_app(&db).app_data(mock_data(MockLlm::Echo))).await;
+
+        // Other users' conversations and messages are not found, as if they didn't exist
+        let conversation_uri = format!("/conversations/{}", conversation.id);
+        let message_uri = format!("/messages/{}", message.id);
+        let moved = Message::new(conversation.id.clone(), Author::User, "Mine".to_string());
+        let own_conversation = Conversation::new(
+            someone_else.id.clone(),
+            "Mine".to_string(),
+            conversation.voice_id.clone(),
+        );
+        db.save_conversation(&own_conversation).await.unwrap();
+        let mut stolen = message.clone();
+        stolen.conversation_id = own_conversation.id.clone();
+        for (req, status) in [
+            (
+                test::TestRequest::get().uri(&conversation_uri),
+                StatusCode::NOT_FOUND,
+            ),
+            (
+                test::TestRequest::put()
+                    .uri(&conversation_uri)
+                    .set_json(&conversation),
+                StatusCode::NOT_FOUND,
+            ),
+            (
+                test::TestRequest::delete().uri(&conversation_uri),
+                StatusCode::NOT_FOUND,
+            ),
+            (
+                test::TestRequest::post().uri(&format!("{}/restore", conversation_uri)),
+                StatusCode::NOT_FOUND,
+            ),
+            (
+                test::TestRequest::post().uri(&format!("{}/reply", conversation_uri)),
+                StatusCode::NOT_FOUND,
+            ),
+            (
+                test::TestRequest::delete().uri(&format!("{}/reply", conversation_uri)),
+                StatusCode::NOT_FOUND,
+            ),
+            (
+                test::TestRequest::get().uri(&format!("{}/reply/stream", conversation_uri)),
+                StatusCode::NOT_FOUND,
+            ),
+            (
+                ws_request(&format!("/ws{}", conversation_uri)),
+                StatusCode::NOT_FOUND,
+            ),
+            (
+                test::TestRequest::get()
+                    .uri(&format!("/messages?conversation_id={}", conversation.id)),
+                StatusCode::NOT_FOUND,
+            ),
+            (
+                test::TestRequest::get().uri(&message_uri),
+                StatusCode::NOT_FOUND,
+            ),
+            (
+                test::TestRequest::put()
+                    .uri(&message_uri)
+                    .set_json(&message),
+                StatusCode::NOT_FOUND,
+            ),
+            (
+                test::TestRequest::post().uri(&format!("{}/restore", message_uri)),
+                StatusCode::NOT_FOUND,
+            ),
+            (
+                test::TestRequest::post()
+                    .uri("/conversations")
+                    .set_json(&conversation),
+                StatusCode::NOT_FOUND,
+            ),
+            (
+                test::TestRequest::post()
+                    .uri("/messages")
+                    .set_json(&message),
+                StatusCode::NOT_FOUND,
+            ),
+            (
+                test::TestRequest::post().uri("/messages").set_json(&stolen),
+                StatusCode::NOT_FOUND,
+            ),
+            // Nor can messages be added to them
+            (
+                test::TestRequest::post().uri("/messages").set_json(&moved),
+                StatusCode::NOT_FOUND,
+            ),
+            (
+                test::TestRequest::put()
+                    .uri(&format!("/messages/{}", moved.id))
+                    .set_json(&moved),
+                StatusCode::NOT_FOUND,
+            ),
+        ] {
+            let res = app
+                .call(req.cookie(cookie.clone()).to_request())
+                .await
+                .unwrap();
+            assert_eq!(res.status(), status);
+        }
+
+        // And nothing changed
+        assert_eq!(
+            db.get_conversation(&conversation.id).await.unwrap(),
+            conversation
+        );
+        assert_eq!(db.get_message(&message.id).await.unwrap(), message);
+        assert!(db.get_message(&moved.id).await.is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_search() {
+        let (db, conversation) = setup_db().await;
+        for content in [
+            "What are lifetimes?",
+            "Lifetimes tell the compiler how long a reference is valid.",
+            "Thanks!",
+        ] {
+            let message = Message::new(conversation.id.clone(), Author::User, content.to_string());
+            db.save_message(&message).await.unwrap();
+        }
+        let cookie = login(&db, &conversation.user_id).await;
+        let someone_else = setup_user(&db, "someone-else").await;
+        let someone_else = login(&db, &someone_else.id).await;
+
+        let app = test::init_service(test_app(&db)).await;
+
+        let req = test::TestRequest::get()
+            .uri("/search?q=lifetimes%20reference")
+            .cookie(cookie.clone())
+            .to_request();
+        let res: JsonApiResponse<SearchResult> = test::call_and_read_body_json(&app, req).await;
+        let results = res.data.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].conversation_name, "Test Conversation");
+        assert_eq!(
+            results[0].snippet,
+            "<mark>Lifetimes</mark> tell the compiler how long a <mark>reference</mark> is valid."
+        );
+
+        // Other users' conversations are not searched
+        let req = test::TestRequest::get()
+            .uri("/search?q=lifetimes")
+            .cookie(someone_else)
+            .to_request();
+        let res: JsonApiResponse<SearchResult> = test::call_and_read_body_json(&app, req).await;
+        assert!(res.data.unwrap().is_empty());
+
+        // The query syntax is searched for as text
+        let req = test::TestRequest::get()
+            .uri("/search?q=%22lifetimes%20OR*")
+            .cookie(cookie.clone())
+            .to_request();
+        let res = app.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let req = test::TestRequest::get()
+            .uri("/search?q=%20")
+            .cookie(cookie)
+            .to_request();
+        let res = app.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        // Searching needs a login
+        let req = test::TestRequest::get()
+            .uri("/search?q=lifetimes")
+            .to_request();
+        let res = app.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn test_message_unknown_conversation() {
+        let (db, conversation) = setup_db().await;
+        let cookie = login(&db, &conversation.user_id).await;
+
+        let app = test::init_service(test_app(&db)).await;
+
+        let message = Message::new("missing".to_string(), Author::User, "Hi".to_string());
+        let req = test::TestRequest::post()
+            .uri("/messages")
+            .cookie(cookie.clone())
+            .set_json(&message)
+            .to_request();
+        let res = app.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let res: JsonApiResponse<String> = test::read_body_json(res).await;
+        assert_eq!(
+            res.errors.unwrap(),
+            vec!["conversation_id does not refer to an existing conversation"]
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_restore() {
+        let (db, conversation) = setup_db().await;
+        let message = Message::new(conversation.id.clone(), Author::User, "Hi".to_string());
+        db.save_message(&message).await.unwrap();
+        db.delete_conversation(&conversation.id).await.unwrap();
+        let cookie = login(&db, &conversation.user_id).await;
+
+        let app = test::init_service(test_app(&db)).await;
+
+        // Deleted conversations are only listed when asked for
+        for (query, count) in [("", 0), ("?include_deleted=true", 1)] {
+            let req = test::TestRequest::get()
+                .uri(&format!("/conversations{}", query))
+                .cookie(cookie.clone())
+                .to_request();
+            let res: JsonApiResponse<Conversation> = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(res.data.unwrap().len(), count);
+        }
+
+        // A message can't be restored while its conversation is deleted
+        let req = test::TestRequest::post()
+            .uri(&format!("/messages/{}/restore", message.id))
+            .cookie(cookie.clone())
+            .to_request();
+        let res = app.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/conversations/{}/restore", conversation.id))
+            .cookie(cookie.clone())
+            .to_request();
+        let res: JsonApiResponse<Conversation> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(res.data.unwrap()[0].deleted_at, None);
+
+        // Its messages came back with it
+        let req = test::TestRequest::get()
+            .uri(&format!("/messages?conversation_id={}", conversation.id))
+            .cookie(cookie.clone())
+            .to_request();
+        let res: JsonApiResponse<Message> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(res.data.unwrap()[0].id, message.id);
+
+        let req = test::TestRequest::post()
+            .uri("/conversations/missing/restore")
+            .cookie(cookie.clone())
+            .to_request();
+        let res = app.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn test_voice_invalid_generation_settings() {
+        let (db, _) = setup_db().await;
+        let admin = setup_admin(&db).await;
+        let cookie = login(&db, &admin.id).await;
+
+        let app = test::init_service(test_app(&db)).await;
+
+        let mut voice = Voice::new("Gwen".to_string(), "A dog".to_string(), "Woof".to_string());
+        voice.generation.top_p = Some(0.0);
+        voice.generation.stop_sequences = Some(vec!["".to_string()]);
+
+        let req = test::TestRequest::post()
+            .uri("/voices")
+            .cookie(cookie)
+            .set_json(&voice)
+            .to_request();
+        let res: JsonApiResponse<String> = test::call_and_read_body_json(&app, req).await;
+        let errors = res.errors.unwrap();
+        assert!(errors[0].contains("top_p"));
+        assert!(errors[0].contains("stop_sequences"));
+    }
+
+    #[actix_web::test]
+    async fn test_reply_reports_excluded_messages() {
+        let (db, conversation) = setup_db().await;
+
+        // Give the voice a budget that only fits the system prompt and the last message
+        let mut voice = db.get_voice(&conversation.voice_id).await.unwrap();
+        voice.context_budget = Some(20);
+        db.save_voice(&voice).await.unwrap();
+
+        let mut old_message = Message::new(
+            conversation.id.clone(),
+            Author::User,
+            "a long message that will not fit in the budget".to_string(),
+        );
+        old_message.created_at -= 10;
+        db.save_message(&old_message).await.unwrap();
+        let new_message = Message::new(conversation.id.clone(), Author::User, "hello".to_string());
+        db.save_message(&new_message).await.unwrap();
+
+        let cookie = login(&db, &conversation.user_id).await;
+
+        let app = test::init_service(
+            test_app(&db).app_data(mock_data(MockLlm::canned(vec!["Hi".to_string()]))),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/conversations/{}/reply", conversation.id))
+            .cookie(cookie.clone())
+            .to_request();
+        let res: JsonApiResponse<Reply> = test::call_and_read_body_json(&app, req).await;
+        let reply = res.data.unwrap().remove(0);
+        assert_eq!(reply.excluded_message_ids, vec![old_message.id]);
+    }
+
+    #[actix_web::test]
+    async fn test_reply_stream() {
+        let (db, conversation) = setup_db().await;
+        let cookie = login(&db, &conversation.user_id).await;
+
+        let app = test::init_service(test_app(&db).app_data(mock_data(MockLlm::canned(vec![
+            "Hello there friend".to_string(),
+        ]))))
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/conversations/{}/reply/stream", conversation.id))
+            .cookie(cookie.clone())
+            .to_request();
+        let res = app.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            res.headers().get("content-type").unwrap(),
+            "text/event-stream"
+        );
+
+        let body = String::from_utf8(test::read_body(res).await.to_vec()).unwrap();
+        let events = body
+            .split("\n\n")
+            .filter(|event| !event.is_empty())
+            .map(|event| {
+                let (name, data) = event.split_once('\n').unwrap();
+                (
+                    name.trim_start_matches("event: ").to_string(),
+                    data.trim_start_matches("data: ").to_string(),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        // Every word is streamed as a token
+        let tokens = events
+            .iter()
+            .filter(|(name, _)| name == "token")
+            .map(|(_, data)| serde_json::from_str::<ReplyToken>(data).unwrap().content)
+            .collect::<Vec<_>>();
+        assert_eq!(tokens, vec!["Hello ", "there ", "friend"]);
+
+        // The last event carries the saved message
+        let (name, data) = events.last().unwrap();
+        assert_eq!(name, "done");
+        let reply = serde_json::from_str::<Reply>(data).unwrap();
+        assert_eq!(reply.message.content, "Hello there friend");
+        assert_eq!(
+            db.get_message(&reply.message.id).await.unwrap(),
+            reply.message
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_reply_stream_missing_conversation() {
+        let (db, conversation) = setup_db().await;
+        let cookie = login(&db, &conversation.user_id).await;
+
+        let app = test::init_service(test_app(&db).app_data(mock_data(MockLlm::Echo))).await;
+
+        let req = test::TestRequest::get()
+            .uri("/conversations/missing/reply/stream")
+            .cookie(cookie.clone())
+            .to_request();
+        let res = app.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    /// A request that asks to upgrade to a WebSocket
+    fn ws_request(uri: &str) -> test::TestRequest {
+        test::TestRequest::get()
+            .uri(uri)
+            .insert_header(("upgrade", "websocket"))
+            .insert_header(("connection", "Upgrade"))
+            .insert_header(("sec-websocket-version", "13"))
+            .insert_header(("sec-websocket-key", "dGhlIHNhbXBsZSBub25jZQ=="))
+    }
+
+    #[actix_web::test]
+    async fn test_ws_handshake() {
+        let (db, conversation) = setup_db().await;
+        let cookie = login(&db, &conversation.user_id).await;
+
+        let app = test::init_service(test_app(&db).app_data(mock_data(MockLlm::Echo))).await;
+
+        let req = ws_request(&format!("/ws/conversations/{}", conversation.id))
+            .cookie(cookie.clone())
+            .to_request();
+        let res = app.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::SWITCHING_PROTOCOLS);
+
+        let req = ws_request("/ws/conversations/missing")
+            .cookie(cookie.clone())
+            .to_request();
+        let res = app.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        // Plain requests are not upgraded
+        let req = test::TestRequest::get()
+            .uri(&format!("/ws/conversations/{}", conversation.id))
+            .cookie(cookie.clone())
+            .to_request();
+        let res = app.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn test_reply_cancel() {
+        let (db, conversation) = setup_db().await;
+        let jobs = JobRegistry::default();
+
+        let cookie = login(&db, &conversation.user_id).await;
+
+        let app = test::init_service(
+            test_app(&db)
+                .app_data(web::Data::new(jobs.clone()))
+                .app_data(mock_data(MockLlm::Echo)),
+        )
+        .await;
+        let uri = format!("/conversations/{}/reply", conversation.id);
+
+        // Nothing to cancel
+        let req = test::TestRequest::delete()
+            .uri(&uri)
+            .cookie(cookie.clone())
+            .to_request();
+        let res = app.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        // Only one reply at a time
+        let job = jobs.start(&conversation.id).unwrap();
+        let req = test::TestRequest::post()
+            .uri(&uri)
+            .cookie(cookie.clone())
+            .to_request();
+        let res = app.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+
+        let req = test::TestRequest::delete()
+            .uri(&uri)
+            .cookie(cookie.clone())
+            .to_request();
+        let res = app.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        assert!(job.flag().is_cancelled());
+        drop(job);
+
+        // A client that goes away cancels the reply, what was generated so far is kept
+        let req = test::TestRequest::get()
+            .uri(&format!("{}/stream", uri))
+            .cookie(cookie.clone())
+            .to_request();
+        let res = app.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        drop(res);
+
+        // And marked as truncated once it is saved
+        let messages = loop {
+            let req = test::TestRequest::get()
+                .uri(&format!("/messages?conversation_id={}", conversation.id))
+                .cookie(cookie.clone())
+                .to_request();
+            let res: JsonApiResponse<Message> = test::call_and_read_body_json(&app, req).await;
+            let messages = res.data.unwrap();
+            if !messages.is_empty() {
+                break messages;
+            }
+            rt::time::sleep(Duration::from_millis(10)).await;
+        };
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].author, Author::Voice);
+        assert!(messages[0].truncated);
+    }
 }
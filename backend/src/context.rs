@@ -0,0 +1,185 @@
+use models::{Conversation, GenerationSettings, Message, Voice};
+
+use crate::llm::{Inference, LlmError};
+use crate::prompt::ChatTemplate;
+
+/// A prompt for a reply that fits the token budget
+#[derive(Debug, PartialEq, Eq)]
+pub struct ContextWindow {
+    /// The prompt to feed the model
+    pub prompt: String,
+
+    /// IDs of the messages that were left out of the prompt, oldest first
+    pub excluded_message_ids: Vec<String>,
+}
+
+impl ContextWindow {
+    /// Build the largest prompt that fits the token budget
+    ///
+    /// The voice prefix is always kept. The oldest messages are left out
+    /// until the prompt fits, so the most recent messages are always included.
+    ///
+    /// The budget is the voice's context_budget, capped by what the model can take
+    /// with room left for the reply.
+    /// Fails with ContextFull if not even the latest message fits.
+    ///
+    /// Arguments:
+    /// - llm: The backend used to count tokens
+    /// - template: The chat template that builds the prompt
+    /// - settings: The generation settings of the reply
+    /// - voice: The voice that will answer
+    /// - conversation: The conversation being continued
+    /// - messages: The conversation history, oldest first
+    pub fn fit(
+        llm: &dyn Inference,
+        template: &ChatTemplate,
+        settings: &GenerationSettings,
+        voice: &Voice,
+        conversation: &Conversation,
+        messages: &[Message],
+    ) -> Result<Self, LlmError> {
+        let model_budget = llm.prompt_budget(settings);
+        let budget = match voice.context_budget {
+            Some(budget) if budget > 0 => (budget as usize).min(model_budget),
+            Some(_) | None => model_budget,
+        };
+
+        let fits = |start: usize| -> Result<bool, LlmError> {
+            let prompt = template.build(voice, conversation, &messages[start..]);
+            Ok(llm.count_tokens(&prompt)? <= budget)
+        };
+
+        // Leaving out messages never makes the prompt longer,
+        // so search for the oldest message we can start from
+        let (mut low, mut high) = (0, messages.len());
+        if !fits(high)? {
+            return Err(LlmError::ContextFull);
+        }
+        while low < high {
+            let middle = (low + high) / 2;
+            if fits(middle)? {
+                high = middle;
+            } else {
+                low = middle + 1;
+            }
+        }
+
+        if low == messages.len() && !messages.is_empty() {
+            return Err(LlmError::ContextFull);
+        }
+
+        Ok(Self {
+            prompt: template.build(voice, conversation, &messages[low..]),
+            excluded_message_ids: messages[..low]
+                .iter()
+                .map(|message| message.id.clone())
+                .collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use models::Author;
+
+    use super::*;
+    use crate::llm::MockLlm;
+
+    /// Build a voice, conversation and a history of one word messages
+    fn fixture(count: usize) -> (Voice, Conversation, Vec<Message>) {
+        let voice = Voice::new(
+            "Shaun".to_string(),
+            "It's me".to_string(),
+            "boring".to_string(),
+        );
+        let conversation =
+            Conversation::new("user".to_string(), "Test".to_string(), voice.id.clone());
+        let messages = (0..count)
+            .map(|index| {
+                let author = match index % 2 {
+                    0 => Author::User,
+                    _ => Author::Voice,
+                };
+                Message::new(conversation.id.clone(), author, format!("m{}", index))
+            })
+            .collect();
+
+        (voice, conversation, messages)
+    }
+
+    #[test]
+    fn test_fit_everything() {
+        let (voice, conversation, messages) = fixture(4);
+        let template = ChatTemplate::Transcript;
+
+        let window = ContextWindow::fit(
+            &MockLlm::Echo,
+            &template,
+            &Default::default(),
+            &voice,
+            &conversation,
+            &messages,
+        )
+        .unwrap();
+
+        assert_eq!(
+            window.prompt,
+            template.build(&voice, &conversation, &messages)
+        );
+        assert!(window.excluded_message_ids.is_empty());
+    }
+
+    #[test]
+    fn test_fit_drops_oldest() {
+        let (mut voice, conversation, messages) = fixture(6);
+        let template = ChatTemplate::Transcript;
+
+        // Leave room for the system prompt and two messages,
+        // every message takes 2 tokens
+        let system_tokens = MockLlm::Echo
+            .count_tokens(&template.build(&voice, &conversation, &[]))
+            .unwrap();
+        voice.context_budget = Some((system_tokens + 4) as i64);
+
+        let window = ContextWindow::fit(
+            &MockLlm::Echo,
+            &template,
+            &Default::default(),
+            &voice,
+            &conversation,
+            &messages,
+        )
+        .unwrap();
+
+        assert_eq!(
+            window.prompt,
+            template.build(&voice, &conversation, &messages[4..])
+        );
+        assert_eq!(
+            window.excluded_message_ids,
+            messages[..4]
+                .iter()
+                .map(|message| message.id.clone())
+                .collect::<Vec<_>>()
+        );
+        // The voice prefix is always kept
+        assert!(window.prompt.contains(&voice.prefix));
+    }
+
+    #[test]
+    fn test_fit_too_small() {
+        let (mut voice, conversation, messages) = fixture(2);
+        voice.context_budget = Some(1);
+
+        let res = ContextWindow::fit(
+            &MockLlm::Echo,
+            &ChatTemplate::Transcript,
+            &Default::default(),
+            &voice,
+            &conversation,
+            &messages,
+        );
+
+        assert!(matches!(res, Err(LlmError::ContextFull)));
+    }
+}
@@ -0,0 +1,1148 @@
+use std::{fmt, ops::Deref, sync::Arc};
+
+use async_trait::async_trait;
+use chrono::Utc;
+use futures_util::future::BoxFuture;
+use models::{
+    ApiKey, Conversation, GenerationSettings, Message, PurgeReport, Role, SearchResult, User, Voice,
+};
+use sqlx::{Database, Encode, Error, Pool, QueryBuilder, Type};
+use uuid::Uuid;
+
+mod postgres;
+mod sqlite;
+
+pub use postgres::PostgresRepository;
+pub use sqlite::SqliteRepository;
+
+/// A numbered change to the schema, see backend/db/migrations
+struct Migration {
+    version: i64,
+    name: &'static str,
+    sql: &'static str,
+}
+
+/// A position in a list ordered by `created_at`, then `seq`
+///
+/// `seq` grows with every row that is inserted,
+/// so rows created in the same second keep the order they were inserted in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cursor {
+    pub created_at: i64,
+    pub seq: i64,
+}
+
+impl Cursor {
+    /// Parse a cursor from the `created_at:seq` format it is displayed in
+    ///
+    /// Arguments:
+    /// - cursor: The text of the cursor
+    pub fn parse(cursor: &str) -> Option<Cursor> {
+        let (created_at, seq) = cursor.split_once(':')?;
+
+        Some(Cursor {
+            created_at: created_at.parse().ok()?,
+            seq: seq.parse().ok()?,
+        })
+    }
+}
+
+impl fmt::Display for Cursor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.created_at, self.seq)
+    }
+}
+
+/// Which part of a list to fetch
+///
+/// Pages hold rows in order of creation.
+/// The default page holds every row
+#[derive(Debug, Clone, Default)]
+pub struct Page {
+    /// The most rows to fetch, None for every row
+    pub limit: Option<i64>,
+
+    /// Only fetch rows created before this cursor, the rows closest to it
+    pub before: Option<Cursor>,
+
+    /// Only fetch rows created after this cursor, the rows closest to it
+    pub after: Option<Cursor>,
+}
+
+/// The rows of a page
+#[derive(Debug)]
+pub struct Paged<T> {
+    pub items: Vec<T>,
+
+    /// Where the next page starts, in the same direction as this page.
+    /// None when there are no more rows
+    pub next: Option<Cursor>,
+}
+
+/// A login of a user
+#[derive(Debug, Clone)]
+pub struct Session {
+    /// The hash of the token the user was given, see [`crate::api::auth::hash_token`]
+    pub id: String,
+
+    /// The user that logged in
+    pub user_id: String,
+
+    /// Unix Timestamp of when the user logged in
+    pub created_at: i64,
+
+    /// Unix Timestamp of when the session stops being accepted
+    pub expires_at: i64,
+}
+
+/// Where users, voices, conversations and messages are stored
+///
+/// See DATABASE.md for the schema
+#[async_trait]
+pub trait Repository: Send + Sync {
+    /// Asserts the database schema,
+    /// running the migrations that have not been applied yet
+    async fn assert_schema(&self) -> Result<(), Error>;
+
+    /// Initializes the database with the following:
+    /// - Inserts initial voices if the table is empty
+    async fn init(&self) -> Result<(), Error>;
+
+    /// Fetches voices from database
+    ///
+    /// Arguments:
+    /// - deleted: include deleted voices
+    async fn get_voices(&self, deleted: bool) -> Result<Vec<Voice>, Error>;
+
+    /// Fetches a voice by ID
+    ///
+    /// Arguments:
+    /// - id: the id of the voice
+    async fn get_voice(&self, id: &str) -> Result<Voice, Error>;
+
+    /// Saves a voice to the database, will upsert
+    ///
+    /// Arguments:
+    /// - voice: The voice struct to be saved
+    async fn save_voice(&self, voice: &Voice) -> Result<bool, Error>;
+
+    /// Set the deleted_at timestamp for a voice,
+    /// its conversations keep it so they can still be shown
+    ///
+    /// Arguments:
+    /// - voice_id: The id of the voice to "delete"
+    async fn delete_voice(&self, voice_id: &str) -> Result<bool, Error>;
+
+    /// Fetches a page of conversations from database
+    ///
+    /// Arguments:
+    /// - user_id: the id of the user in the conversation
+    /// - deleted: include deleted conversations
+    /// - page: which conversations to fetch
+    async fn get_conversations(
+        &self,
+        user_id: &str,
+        deleted: bool,
+        page: &Page,
+    ) -> Result<Paged<Conversation>, Error>;
+
+    /// Fetches a conversation by ID
+    ///
+    /// Arguments:
+    /// - id: the id of the voice
+    async fn get_conversation(&self, id: &str) -> Result<Conversation, Error>;
+
+    /// Saves a conversation to the database, will upsert
+    ///
+    /// Arguments:
+    /// - conversation: The conversation struct to be saved
+    async fn save_conversation(&self, conversation: &Conversation) -> Result<bool, Error>;
+
+    /// Set the deleted_at timestamp for a conversation,
+    /// the messages of the conversation are deleted along with it
+    ///
+    /// Arguments:
+    /// - conversation_id: The id of the conversation to "delete"
+    async fn delete_conversation(&self, conversation_id: &str) -> Result<bool, Error>;
+
+    /// Clear the deleted_at timestamp of a conversation,
+    /// the messages deleted along with it are restored too
+    ///
+    /// Arguments:
+    /// - conversation_id: The id of the conversation to restore
+    async fn restore_conversation(&self, conversation_id: &str) -> Result<bool, Error>;
+
+    /// Fetches a page of messages from database
+    ///
+    /// Arguments:
+    /// - conversation_id: the id of the user in the conversation
+    /// - deleted: include deleted messages
+    /// - page: which messages to fetch
+    async fn get_messages(
+        &self,
+        conversation_id: &str,
+        deleted: bool,
+        page: &Page,
+    ) -> Result<Paged<Message>, Error>;
+
+    /// Fetches a message by ID
+    ///
+    /// Arguments:
+    /// - id: the id of the voice
+    async fn get_message(&self, id: &str) -> Result<Message, Error>;
+
+    /// Saves a message to the database, will upsert
+    ///
+    /// Arguments:
+    /// - message: The message struct to be saved
+    async fn save_message(&self, message: &Message) -> Result<bool, Error>;
+
+    /// Clear the deleted_at timestamp of a message
+    ///
+    /// Arguments:
+    /// - message_id: The id of the message to restore
+    async fn restore_message(&self, message_id: &str) -> Result<bool, Error>;
+
+    /// Searches the messages of a user's conversations, best matches first
+    ///
+    /// Deleted messages and the messages of deleted conversations are left out
+    ///
+    /// Arguments:
+    /// - user_id: the id of the user whose conversations to search
+    /// - terms: the words to look for, every word must be in a message
+    /// - limit: the most messages to return
+    async fn search_messages(
+        &self,
+        user_id: &str,
+        terms: &str,
+        limit: i64,
+    ) -> Result<Vec<SearchResult>, Error>;
+
+    /// Saves a new user to the database
+    ///
+    /// Arguments:
+    /// - user: The user struct to be saved
+    /// - password_hash: The hash of the user's password
+    async fn save_user(&self, user: &User, password_hash: &str) -> Result<bool, Error>;
+
+    /// Fetches a user by username, along with the hash of their password
+    ///
+    /// Arguments:
+    /// - username: the name the user logs in with
+    async fn get_user_login(&self, username: &str) -> Result<(User, String), Error>;
+
+    /// Changes what a user is allowed to do
+    ///
+    /// Arguments:
+    /// - user_id: the id of the user
+    /// - role: the new role of the user
+    async fn set_user_role(&self, user_id: &str, role: Role) -> Result<bool, Error>;
+
+    /// Saves a new session to the database
+    ///
+    /// Arguments:
+    /// - session: The session struct to be saved
+    async fn save_session(&self, session: &Session) -> Result<bool, Error>;
+
+    /// Fetches the user of a session that has not expired
+    ///
+    /// Arguments:
+    /// - session_id: the id of the session
+    async fn get_session_user(&self, session_id: &str) -> Result<User, Error>;
+
+    /// Removes a session, logging its user out
+    ///
+    /// Arguments:
+    /// - session_id: the id of the session
+    async fn delete_session(&self, session_id: &str) -> Result<bool, Error>;
+
+    /// Saves a new API key to the database
+    ///
+    /// Arguments:
+    /// - api_key: The API key struct to be saved
+    /// - key_hash: The hash of the key itself
+    async fn save_api_key(&self, api_key: &ApiKey, key_hash: &str) -> Result<bool, Error>;
+
+    /// Fetches the API keys of a user, oldest first
+    ///
+    /// Arguments:
+    /// - user_id: the id of the user
+    async fn get_api_keys(&self, user_id: &str) -> Result<Vec<ApiKey>, Error>;
+
+    /// Fetches the API key with a hash and its user, marking the key as used now
+    ///
+    /// Arguments:
+    /// - key_hash: the hash of the key
+    async fn use_api_key(&self, key_hash: &str) -> Result<(User, ApiKey), Error>;
+
+    /// Removes an API key of a user, so it can't be used anymore
+    ///
+    /// Arguments:
+    /// - user_id: the id of the user
+    /// - api_key_id: the id of the API key
+    async fn delete_api_key(&self, user_id: &str, api_key_id: &str) -> Result<bool, Error>;
+
+    /// Moves the conversations of an anonymous user to a registered user, in one transaction
+    ///
+    /// Registered users' conversations can't be claimed, and claiming again moves nothing
+    ///
+    /// Arguments:
+    /// - anonymous_id: the id the browser generated for the anonymous user
+    /// - user_id: the id of the registered user
+    async fn claim_conversations(&self, anonymous_id: &str, user_id: &str) -> Result<u64, Error>;
+
+    /// Removes conversations and messages for good, once they were deleted before a time
+    ///
+    /// The messages of a removed conversation are removed with it
+    ///
+    /// Arguments:
+    /// - before: the timestamp, rows deleted before it are removed
+    async fn purge_deleted(&self, before: i64) -> Result<PurgeReport, Error>;
+
+    /// Gives the space of removed rows back and refreshes the query planner statistics
+    async fn optimize(&self) -> Result<(), Error>;
+}
+
+/// The repository the backend stores its data in
+#[derive(Clone)]
+pub struct DB {
+    repository: Arc<dyn Repository>,
+}
+
+impl DB {
+    /// Creates a new instance of DB
+    ///
+    /// The scheme of the url picks the database:
+    /// `postgres://` or `postgresql://` for PostgreSQL, `sqlite:` for SQLite.
+    /// A url without a scheme is the path of an SQLite file, e.g. `data/chat.db`
+    ///
+    /// Arguments:
+    /// - url: a database connection string
+    pub async fn new(url: &str) -> Result<Self, Error> {
+        let repository: Arc<dyn Repository> = match url.split_once(':') {
+            Some(("postgres" | "postgresql", _)) => {
+                Arc::new(PostgresRepository::connect(url).await?)
+            }
+            Some(("sqlite", _)) => Arc::new(SqliteRepository::connect(url).await?),
+            // Any other scheme is a database that isn't supported,
+            // a colon without `//`, like `C:\chat.db`, is still a path
+            Some((_, rest)) if rest.starts_with("//") => {
+                return Err(Error::Configuration(
+                    format!("Unsupported database url: {}", url).into(),
+                ))
+            }
+            _ => Arc::new(SqliteRepository::connect(url).await?),
+        };
+
+        Ok(DB { repository })
+    }
+}
+
+impl Deref for DB {
+    type Target = dyn Repository;
+
+    fn deref(&self) -> &Self::Target {
+        self.repository.as_ref()
+    }
+}
+
+/// Run several writes as one, in a transaction
+///
+/// The transaction is committed when the writes succeed,
+/// and rolled back when any of them fails so none of them are kept
+///
+/// Arguments:
+/// - pool: The pool to take the connection of the transaction from
+/// - writes: The writes to run on the connection of the transaction
+async fn with_transaction<D, T, F>(pool: &Pool<D>, writes: F) -> Result<T, Error>
+where
+    D: Database,
+    F: for<'c> FnOnce(&'c mut D::Connection) -> BoxFuture<'c, Result<T, Error>>,
+{
+    let mut transaction = pool.begin().await?;
+
+    match writes(&mut transaction).await {
+        Ok(result) => {
+            transaction.commit().await?;
+            Ok(result)
+        }
+        Err(err) => {
+            transaction.rollback().await?;
+            Err(err)
+        }
+    }
+}
+
+/// The voices a new database starts with
+fn initial_voices() -> Vec<Voice> {
+    vec![
+        Voice {
+            id: Uuid::new_v4().to_string(),
+            name: "Shaun Burdick".to_string(),
+            description: "The developer of this tool".to_string(),
+            prefix: "A software developer; Learning Rust; Too busy to focus on you;".to_string(),
+            context_budget: None,
+            generation: GenerationSettings::default(),
+            model_id: None,
+            created_at: Utc::now().timestamp_micros(),
+            deleted_at: None
+        },
+        Voice {
+            id: Uuid::new_v4().to_string(),
+            name: "Gwen Burdick".to_string(),
+            description: "My dog".to_string(),
+            prefix: "A dog; Just discovered the English language; Learned how to type; Just happy to be here;".to_string(),
+            context_budget: None,
+            generation: GenerationSettings::default(),
+            model_id: None,
+            created_at: Utc::now().timestamp_micros(),
+            deleted_at: None
+        },
+    ]
+}
+
+/// Add the conditions, order and limit of a page to a query
+///
+/// Rows are fetched closest to the cursor first, one more than the limit
+/// to tell if there is a next page
+///
+/// Arguments:
+/// - query: A query of rows with \"created_at\" and \"seq\" columns, up to its conditions
+/// - page: which rows to fetch
+fn push_page<'a, D>(query: &mut QueryBuilder<'a, D>, page: &'a Page)
+where
+    D: Database,
+    i64: Encode<'a, D> + Type<D>,
+{
+    if let Some(after) = &page.after {
+        query.push(r#" AND ("created_at", "seq") > ("#);
+        query.push_bind(after.created_at);
+        query.push(", ");
+        query.push_bind(after.seq);
+        query.push(")");
+    }
+    if let Some(before) = &page.before {
+        query.push(r#" AND ("created_at", "seq") < ("#);
+        query.push_bind(before.created_at);
+        query.push(", ");
+        query.push_bind(before.seq);
+        query.push(")");
+    }
+
+    match page.before.is_some() && page.after.is_none() {
+        true => query.push(r#" ORDER BY "created_at" DESC, "seq" DESC"#),
+        false => query.push(r#" ORDER BY "created_at" ASC, "seq" ASC"#),
+    };
+
+    if let Some(limit) = page.limit {
+        query.push(" LIMIT ");
+        query.push_bind(limit + 1);
+    }
+}
+
+/// Turn the rows fetched by a query with [`push_page`] into a page
+///
+/// Arguments:
+/// - rows: The fetched rows, along with their cursors
+/// - page: which rows were fetched
+fn paginate<T>(mut rows: Vec<(T, Cursor)>, page: &Page) -> Paged<T> {
+    let mut next = None;
+    if let Some(limit) = page.limit {
+        if rows.len() as i64 > limit {
+            rows.truncate(limit as usize);
+            next = rows.last().map(|(_, cursor)| cursor.clone());
+        }
+    }
+
+    // Rows before a cursor are fetched backwards
+    if page.before.is_some() && page.after.is_none() {
+        rows.reverse();
+    }
+
+    Paged {
+        items: rows.into_iter().map(|(row, _)| row).collect(),
+        next,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, str::FromStr};
+
+    use models::{Author, Scope};
+    use sqlx::{postgres::PgConnectOptions, PgPool};
+
+    use super::*;
+
+    /// A new SQLite database with the schema
+    async fn sqlite_db() -> DB {
+        let db = DB::new("sqlite::memory:").await.unwrap();
+        db.assert_schema().await.unwrap();
+        db
+    }
+
+    /// A new schema in the PostgreSQL database at TEST_POSTGRES_URL,
+    /// None when it is not set and the test should be skipped
+    ///
+    /// Every test gets its own schema, so tests don't see each other's rows.
+    /// A local database can be started with
+    /// `docker run -p 5432:5432 -e POSTGRES_HOST_AUTH_METHOD=trust postgres:15`
+    /// and used with `TEST_POSTGRES_URL=postgres://postgres@localhost/postgres`
+    async fn postgres_db() -> Option<DB> {
+        let url = match env::var("TEST_POSTGRES_URL") {
+            Ok(s) if !s.is_empty() => s,
+            Ok(_) | Err(_) => {
+                eprintln!("TEST_POSTGRES_URL is not set, skipping");
+                return None;
+            }
+        };
+
+        let schema = format!("test_{}", Uuid::new_v4().simple());
+        let pool = PgPool::connect(&url).await.unwrap();
+        sqlx::query(&format!(r#"CREATE SCHEMA "{}""#, schema))
+            .execute(&pool)
+            .await
+            .unwrap();
+        pool.close().await;
+
+        let options = PgConnectOptions::from_str(&url)
+            .unwrap()
+            .options([("search_path", schema.as_str())]);
+        let repository = PostgresRepository::connect_with(options).await.unwrap();
+        let db = DB {
+            repository: Arc::new(repository),
+        };
+        db.assert_schema().await.unwrap();
+
+        Some(db)
+    }
+
+    #[sqlx::test]
+    async fn test_db_new_unsupported_url() {
+        assert!(matches!(
+            DB::new("mysql://localhost/chat").await,
+            Err(Error::Configuration(_))
+        ));
+    }
+
+    #[sqlx::test]
+    async fn test_db_new_sqlite_path() {
+        // A path without a scheme is an SQLite file
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let path = file.path().to_str().unwrap();
+        let db = DB::new(path).await.unwrap();
+        db.assert_schema().await.unwrap();
+        let voice = Voice::new(
+            "Shaun".to_string(),
+            "It's me".to_string(),
+            "I'm boring".to_string(),
+        );
+        db.save_voice(&voice).await.unwrap();
+
+        // The same file as with the scheme
+        let db = DB::new(&format!("sqlite:{}", path)).await.unwrap();
+        assert_eq!(db.get_voice(&voice.id).await.unwrap(), voice);
+    }
+
+    #[sqlx::test]
+    async fn test_db_crud_voice() {
+        crud_voice(sqlite_db().await).await;
+    }
+
+    #[sqlx::test]
+    async fn test_db_crud_voice_postgres() {
+        if let Some(db) = postgres_db().await {
+            crud_voice(db).await;
+        }
+    }
+
+    #[sqlx::test]
+    async fn test_db_crud_conversation() {
+        crud_conversation(sqlite_db().await).await;
+    }
+
+    #[sqlx::test]
+    async fn test_db_crud_conversation_postgres() {
+        if let Some(db) = postgres_db().await {
+            crud_conversation(db).await;
+        }
+    }
+
+    #[sqlx::test]
+    async fn test_db_crud_message() {
+        crud_message(sqlite_db().await).await;
+    }
+
+    #[sqlx::test]
+    async fn test_db_crud_message_postgres() {
+        if let Some(db) = postgres_db().await {
+            crud_message(db).await;
+        }
+    }
+
+    #[sqlx::test]
+    async fn test_db_restore() {
+        restore(sqlite_db().await).await;
+    }
+
+    #[sqlx::test]
+    async fn test_db_restore_postgres() {
+        if let Some(db) = postgres_db().await {
+            restore(db).await;
+        }
+    }
+
+    #[sqlx::test]
+    async fn test_db_purge() {
+        purge(sqlite_db().await).await;
+    }
+
+    #[sqlx::test]
+    async fn test_db_purge_postgres() {
+        if let Some(db) = postgres_db().await {
+            purge(db).await;
+        }
+    }
+
+    #[sqlx::test]
+    async fn test_db_claim() {
+        claim(sqlite_db().await).await;
+    }
+
+    #[sqlx::test]
+    async fn test_db_claim_postgres() {
+        if let Some(db) = postgres_db().await {
+            claim(db).await;
+        }
+    }
+
+    #[sqlx::test]
+    async fn test_db_roles() {
+        roles(sqlite_db().await).await;
+    }
+
+    #[sqlx::test]
+    async fn test_db_roles_postgres() {
+        if let Some(db) = postgres_db().await {
+            roles(db).await;
+        }
+    }
+
+    #[sqlx::test]
+    async fn test_db_api_keys() {
+        api_keys(sqlite_db().await).await;
+    }
+
+    #[sqlx::test]
+    async fn test_db_api_keys_postgres() {
+        if let Some(db) = postgres_db().await {
+            api_keys(db).await;
+        }
+    }
+
+    #[sqlx::test]
+    async fn test_db_insertion_order() {
+        insertion_order(sqlite_db().await).await;
+    }
+
+    #[sqlx::test]
+    async fn test_db_insertion_order_postgres() {
+        if let Some(db) = postgres_db().await {
+            insertion_order(db).await;
+        }
+    }
+
+    #[sqlx::test]
+    async fn test_db_postgres_schema() {
+        let Some(db) = postgres_db().await else {
+            return;
+        };
+        // Running it again changes nothing
+        db.assert_schema().await.unwrap();
+        db.init().await.unwrap();
+        db.init().await.unwrap();
+        assert_eq!(db.get_voices(false).await.unwrap().len(), 2);
+
+        // Foreign keys are named after their column
+        let voice = db.get_voices(false).await.unwrap().remove(0);
+        let message = Message::new("missing".to_string(), Author::User, "Hi".to_string());
+        match db.save_message(&message).await {
+            Err(Error::Database(err)) => {
+                assert_eq!(err.constraint(), Some("message.conversation_id"))
+            }
+            result => panic!("Expected a foreign key violation, got {:?}", result),
+        }
+
+        // Messages can be found, and are deleted with their conversation
+        let conversation = Conversation::new(
+            "user".to_string(),
+            "Learning Rust".to_string(),
+            voice.id.clone(),
+        );
+        db.save_conversation(&conversation).await.unwrap();
+        let message = Message::new(
+            conversation.id.clone(),
+            Author::Voice,
+            "Lifetimes tell the compiler how long a reference is valid.".to_string(),
+        );
+        db.save_message(&message).await.unwrap();
+
+        let results = db
+            .search_messages(&conversation.user_id, "lifetimes reference", 10)
+            .await
+            .unwrap();
+        assert_eq!(results[0].conversation_name, "Learning Rust");
+        assert_eq!(
+            results[0].snippet,
+            "<mark>Lifetimes</mark> tell the compiler how long a <mark>reference</mark> is valid."
+        );
+
+        assert!(db.delete_conversation(&conversation.id).await.unwrap());
+        let messages = db
+            .get_messages(&conversation.id, true, &Page::default())
+            .await
+            .unwrap();
+        assert_eq!(messages.items.len(), 1);
+        assert!(db
+            .search_messages(&conversation.user_id, "lifetimes", 10)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    async fn crud_voice(db: DB) {
+        // create a voice and insert it into the database
+        let mut voice = Voice::new(
+            "Shaun".to_string(),
+            "It's me".to_string(),
+            "I'm boring".to_string(),
+        );
+        voice.generation = GenerationSettings {
+            temperature: Some(0.7),
+            top_k: Some(20),
+            top_p: Some(0.9),
+            repeat_penalty: Some(1.1),
+            max_tokens: Some(128),
+            stop_sequences: Some(vec!["\n\n".to_string()]),
+        };
+
+        let res = db.save_voice(&voice).await;
+        assert!(res.unwrap());
+
+        // Grab the record from the database
+        let fetched_voice = db.get_voice(&voice.id).await;
+
+        // And it should match
+        assert_eq!(fetched_voice.unwrap(), voice);
+
+        // Grab all the records from the database
+        let voices = db.get_voices(false).await.unwrap();
+
+        // Should be one record
+        assert_eq!(voices.len(), 1);
+        // And it should match
+        assert_eq!(voices[0], voice);
+
+        // "Delete" a voice
+        voice.deleted_at = Some(Utc::now().timestamp());
+        assert!(db.save_voice(&voice).await.unwrap());
+
+        // It should no longer show up in list of voices
+        let new_voices = db.get_voices(false).await.unwrap();
+        assert_eq!(new_voices.len(), 0);
+
+        // It should show up if you ask for deleted
+        let deleted_voices = db.get_voices(true).await.unwrap();
+        assert_eq!(deleted_voices.len(), 1);
+
+        // Or deleted by id
+        voice.deleted_at = None;
+        assert!(db.save_voice(&voice).await.unwrap());
+        assert!(db.delete_voice(&voice.id).await.unwrap());
+        assert!(db.get_voice(&voice.id).await.unwrap().deleted_at.is_some());
+        assert!(!db.delete_voice("missing").await.unwrap());
+    }
+
+    async fn crud_conversation(db: DB) {
+        // create a voice and insert it into the database
+        let voice = Voice::new(
+            "Shaun".to_string(),
+            "It's me".to_string(),
+            "I'm boring".to_string(),
+        );
+
+        let voice_res = db.save_voice(&voice).await;
+        assert!(voice_res.unwrap());
+
+        // create a conversation and insert it into the database
+        let mut conversation = Conversation::new(
+            Uuid::new_v4().to_string(),
+            "Test Conversation".to_string(),
+            voice.id.clone(),
+        );
+        let conversation_res = db.save_conversation(&conversation).await;
+        assert!(conversation_res.unwrap());
+
+        // Grab the record from the database
+        let fetched_conversation = db.get_conversation(&conversation.id).await;
+
+        // And it should match
+        assert_eq!(fetched_conversation.unwrap(), conversation);
+
+        // Grab all the records from the database
+        let conversations = db
+            .get_conversations(&conversation.user_id, false, &Page::default())
+            .await
+            .unwrap()
+            .items;
+
+        // Should be one record
+        assert_eq!(conversations.len(), 1);
+        // And it should match
+        assert_eq!(conversations[0], conversation);
+
+        // "Delete" a conversation
+        conversation.deleted_at = Some(Utc::now().timestamp());
+        assert!(db.save_conversation(&conversation).await.unwrap());
+
+        // It should no longer show up in list of conversations
+        let new_conversations = db
+            .get_conversations(&conversation.user_id, false, &Page::default())
+            .await
+            .unwrap()
+            .items;
+        assert_eq!(new_conversations.len(), 0);
+
+        // It should show up if you ask for deleted
+        let deleted_conversations = db
+            .get_conversations(&conversation.user_id, true, &Page::default())
+            .await
+            .unwrap()
+            .items;
+        assert_eq!(deleted_conversations.len(), 1);
+    }
+
+    async fn crud_message(db: DB) {
+        // create a voice and insert it into the database
+        let voice = Voice::new(
+            "Shaun".to_string(),
+            "It's me".to_string(),
+            "I'm boring".to_string(),
+        );
+
+        let voice_res = db.save_voice(&voice).await;
+        assert!(voice_res.unwrap());
+
+        // create a conversation and insert it into the database
+        let conversation = Conversation::new(
+            Uuid::new_v4().to_string(),
+            "Test Conversation".to_string(),
+            voice.id.clone(),
+        );
+        let conversation_res = db.save_conversation(&conversation).await;
+        assert!(conversation_res.unwrap());
+
+        // create a message and insert it into the database
+        let mut message = Message::new(
+            conversation.id.clone(),
+            Author::User,
+            "This is a test message".to_string(),
+        );
+        let message_res = db.save_message(&message).await;
+        assert!(message_res.unwrap());
+
+        // Grab the record from the database
+        let fetched_message = db.get_message(&message.id).await;
+
+        // And it should match
+        assert_eq!(fetched_message.unwrap(), message);
+
+        // Grab all the records from the database
+        let messages = db
+            .get_messages(&conversation.id, false, &Page::default())
+            .await
+            .unwrap()
+            .items;
+
+        // Should be one record
+        assert_eq!(messages.len(), 1);
+        // And it should match
+        assert_eq!(messages[0], message);
+
+        // "Delete" a message
+        message.deleted_at = Some(Utc::now().timestamp());
+        assert!(db.save_message(&message).await.unwrap());
+
+        // It should no longer show up in list of messages
+        let new_message = db
+            .get_messages(&conversation.id, false, &Page::default())
+            .await
+            .unwrap()
+            .items;
+        assert_eq!(new_message.len(), 0);
+
+        // It should show up if you ask for deleted
+        let deleted_message = db
+            .get_messages(&conversation.id, true, &Page::default())
+            .await
+            .unwrap()
+            .items;
+        assert_eq!(deleted_message.len(), 1);
+    }
+
+    async fn restore(db: DB) {
+        let voice = Voice::new(
+            "Shaun".to_string(),
+            "It's me".to_string(),
+            "I'm boring".to_string(),
+        );
+        db.save_voice(&voice).await.unwrap();
+        let conversation = Conversation::new(
+            Uuid::new_v4().to_string(),
+            "Test Conversation".to_string(),
+            voice.id.clone(),
+        );
+        db.save_conversation(&conversation).await.unwrap();
+        let kept = Message::new(conversation.id.clone(), Author::User, "Kept".to_string());
+        db.save_message(&kept).await.unwrap();
+        // Deleted before the conversation was, so it stays deleted
+        let mut removed = Message::new(conversation.id.clone(), Author::User, "Gone".to_string());
+        removed.deleted_at = Some(Utc::now().timestamp() - 60);
+        db.save_message(&removed).await.unwrap();
+
+        // Nothing to restore yet
+        assert!(!db.restore_conversation(&conversation.id).await.unwrap());
+
+        db.delete_conversation(&conversation.id).await.unwrap();
+        assert!(db.restore_conversation(&conversation.id).await.unwrap());
+        assert_eq!(
+            db.get_conversation(&conversation.id)
+                .await
+                .unwrap()
+                .deleted_at,
+            None
+        );
+        let messages = db
+            .get_messages(&conversation.id, false, &Page::default())
+            .await
+            .unwrap()
+            .items;
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].id, kept.id);
+        // Asking for deleted messages lists them along with the others
+        assert_eq!(
+            db.get_messages(&conversation.id, true, &Page::default())
+                .await
+                .unwrap()
+                .items
+                .len(),
+            2
+        );
+
+        assert!(db.restore_message(&removed.id).await.unwrap());
+        assert!(!db.restore_message(&removed.id).await.unwrap());
+        assert_eq!(db.get_message(&removed.id).await.unwrap().deleted_at, None);
+    }
+
+    async fn purge(db: DB) {
+        let voice = Voice::new(
+            "Shaun".to_string(),
+            "It's me".to_string(),
+            "I'm boring".to_string(),
+        );
+        db.save_voice(&voice).await.unwrap();
+        let mut conversation = Conversation::new(
+            Uuid::new_v4().to_string(),
+            "Test Conversation".to_string(),
+            voice.id.clone(),
+        );
+        db.save_conversation(&conversation).await.unwrap();
+        let message = Message::new(conversation.id.clone(), Author::User, "Hi".to_string());
+        db.save_message(&message).await.unwrap();
+
+        conversation.deleted_at = Some(100);
+        db.save_conversation(&conversation).await.unwrap();
+
+        // Only what was deleted before the time is removed
+        assert_eq!(db.purge_deleted(100).await.unwrap(), PurgeReport::default());
+        assert_eq!(
+            db.purge_deleted(101).await.unwrap(),
+            PurgeReport {
+                conversations: 1,
+                messages: 1,
+            }
+        );
+        assert!(db.get_message(&message.id).await.is_err());
+
+        db.optimize().await.unwrap();
+    }
+
+    async fn claim(db: DB) {
+        let voice = Voice::new(
+            "Shaun".to_string(),
+            "It's me".to_string(),
+            "I'm boring".to_string(),
+        );
+        db.save_voice(&voice).await.unwrap();
+        let user = User::new(format!("shaun-{}", Uuid::new_v4()));
+        db.save_user(&user, "not a hash").await.unwrap();
+        let someone_else = User::new(format!("someone-else-{}", Uuid::new_v4()));
+        db.save_user(&someone_else, "not a hash").await.unwrap();
+
+        let anonymous_id = Uuid::new_v4().to_string();
+        for user_id in [&anonymous_id, &anonymous_id, &someone_else.id] {
+            let conversation = Conversation::new(
+                user_id.clone(),
+                "Test Conversation".to_string(),
+                voice.id.clone(),
+            );
+            db.save_conversation(&conversation).await.unwrap();
+        }
+
+        assert_eq!(
+            db.claim_conversations(&anonymous_id, &user.id)
+                .await
+                .unwrap(),
+            2
+        );
+        let conversations = db
+            .get_conversations(&user.id, false, &Page::default())
+            .await
+            .unwrap();
+        assert_eq!(conversations.items.len(), 2);
+
+        // Claiming again moves nothing
+        assert_eq!(
+            db.claim_conversations(&anonymous_id, &user.id)
+                .await
+                .unwrap(),
+            0
+        );
+
+        // Nor can a registered user's conversations be claimed
+        assert_eq!(
+            db.claim_conversations(&someone_else.id, &user.id)
+                .await
+                .unwrap(),
+            0
+        );
+        let conversations = db
+            .get_conversations(&someone_else.id, false, &Page::default())
+            .await
+            .unwrap();
+        assert_eq!(conversations.items.len(), 1);
+    }
+
+    async fn roles(db: DB) {
+        let mut user = User::new(format!("shaun-{}", Uuid::new_v4()));
+        user.role = Role::Admin;
+        db.save_user(&user, "not a hash").await.unwrap();
+
+        let (fetched, _) = db.get_user_login(&user.username).await.unwrap();
+        assert_eq!(fetched, user);
+
+        assert!(db.set_user_role(&user.id, Role::User).await.unwrap());
+        assert!(!db.set_user_role("missing", Role::User).await.unwrap());
+        let (fetched, _) = db.get_user_login(&user.username).await.unwrap();
+        assert_eq!(fetched.role, Role::User);
+    }
+
+    async fn api_keys(db: DB) {
+        let user = User::new(format!("shaun-{}", Uuid::new_v4()));
+        db.save_user(&user, "not a hash").await.unwrap();
+        let api_key = ApiKey::new(user.id.clone(), "CI".to_string(), Scope::Read);
+        let key_hash = Uuid::new_v4().to_string();
+        assert!(db.save_api_key(&api_key, &key_hash).await.unwrap());
+        assert_eq!(
+            db.get_api_keys(&user.id).await.unwrap(),
+            vec![api_key.clone()]
+        );
+
+        // Using a key marks it as used
+        let (fetched_user, fetched_key) = db.use_api_key(&key_hash).await.unwrap();
+        assert_eq!(fetched_user, user);
+        assert_eq!(fetched_key.scope, Scope::Read);
+        assert!(fetched_key.last_used_at.is_some());
+        assert_eq!(
+            db.get_api_keys(&user.id).await.unwrap()[0].last_used_at,
+            fetched_key.last_used_at
+        );
+
+        // Only its user can remove it
+        assert!(!db
+            .delete_api_key("someone-else", &api_key.id)
+            .await
+            .unwrap());
+        assert!(db.delete_api_key(&user.id, &api_key.id).await.unwrap());
+        assert!(matches!(
+            db.use_api_key(&key_hash).await,
+            Err(Error::RowNotFound)
+        ));
+    }
+
+    async fn insertion_order(db: DB) {
+        let voice = Voice::new(
+            "Shaun".to_string(),
+            "It's me".to_string(),
+            "I'm boring".to_string(),
+        );
+        db.save_voice(&voice).await.unwrap();
+
+        // Rows saved in the same second come back in the order they were saved,
+        // whatever their random ids
+        let user_id = Uuid::new_v4().to_string();
+        let now = Utc::now().timestamp();
+        let mut conversations = Vec::new();
+        for i in 0..10 {
+            let mut conversation =
+                Conversation::new(user_id.clone(), format!("Chat {}", i), voice.id.clone());
+            conversation.created_at = now;
+            db.save_conversation(&conversation).await.unwrap();
+            conversations.push(conversation);
+        }
+        let mut messages = Vec::new();
+        for i in 0..10 {
+            let mut message =
+                Message::new(conversations[0].id.clone(), Author::User, i.to_string());
+            message.created_at = now;
+            db.save_message(&message).await.unwrap();
+            messages.push(message);
+        }
+
+        // Saving a row again doesn't move it
+        conversations[0].name = "Renamed".to_string();
+        db.save_conversation(&conversations[0]).await.unwrap();
+        messages[0].content = "edited".to_string();
+        db.save_message(&messages[0]).await.unwrap();
+
+        let fetched = db
+            .get_conversations(&user_id, false, &Page::default())
+            .await
+            .unwrap();
+        assert_eq!(fetched.items, conversations);
+        let fetched = db
+            .get_messages(&conversations[0].id, false, &Page::default())
+            .await
+            .unwrap();
+        assert_eq!(fetched.items, messages);
+
+        // Also when paging through them
+        let mut page = Page {
+            limit: Some(3),
+            ..Default::default()
+        };
+        let mut paged = Vec::new();
+        loop {
+            let fetched = db
+                .get_messages(&conversations[0].id, false, &page)
+                .await
+                .unwrap();
+            paged.extend(fetched.items);
+            match fetched.next {
+                Some(next) => page.after = Some(next),
+                None => break,
+            }
+        }
+        assert_eq!(paged, messages);
+    }
+}
@@ -0,0 +1,939 @@
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use futures_util::future::BoxFuture;
+use models::{
+    ApiKey, Author, Conversation, GenerationSettings, Message, PurgeReport, Role, Scope,
+    SearchResult, User, Voice,
+};
+use sqlx::{
+    postgres::{PgConnectOptions, PgConnection, PgPool, PgRow},
+    Error, Executor, QueryBuilder, Row,
+};
+
+use super::{
+    initial_voices, paginate, push_page, with_transaction, Cursor, Migration, Page, Paged,
+    Repository, Session,
+};
+
+/// Every migration, in the order they are applied
+const MIGRATIONS: [Migration; 7] = [
+    Migration {
+        version: 1,
+        name: "initial",
+        sql: include_str!("../../db/migrations/postgres/0001_initial.sql"),
+    },
+    Migration {
+        version: 2,
+        name: "conversation_restore",
+        sql: include_str!("../../db/migrations/postgres/0002_conversation_restore.sql"),
+    },
+    Migration {
+        version: 3,
+        name: "users",
+        sql: include_str!("../../db/migrations/postgres/0003_users.sql"),
+    },
+    Migration {
+        version: 4,
+        name: "user_roles",
+        sql: include_str!("../../db/migrations/postgres/0004_user_roles.sql"),
+    },
+    Migration {
+        version: 5,
+        name: "api_keys",
+        sql: include_str!("../../db/migrations/postgres/0005_api_keys.sql"),
+    },
+    Migration {
+        version: 6,
+        name: "message_truncated",
+        sql: include_str!("../../db/migrations/postgres/0006_message_truncated.sql"),
+    },
+    Migration {
+        version: 7,
+        name: "insertion_order",
+        sql: include_str!("../../db/migrations/postgres/0007_insertion_order.sql"),
+    },
+];
+
+/// The advisory lock held while the schema is changed or seeded,
+/// so backends that start together don't do it twice
+const SCHEMA_LOCK: i64 = 0x7275_7374_7963_6874;
+
+/// Stores the data in a PostgreSQL database, that several backends can share
+#[derive(Clone)]
+pub struct PostgresRepository {
+    pool: PgPool,
+}
+
+impl PostgresRepository {
+    /// Connects to a PostgreSQL database
+    ///
+    /// Arguments:
+    /// - url: a PostgreSQL connection string
+    pub async fn connect(url: &str) -> Result<Self, Error> {
+        PostgresRepository::connect_with(PgConnectOptions::from_str(url)?).await
+    }
+
+    /// Connects to a PostgreSQL database
+    ///
+    /// Arguments:
+    /// - options: the options of the connections
+    pub async fn connect_with(options: PgConnectOptions) -> Result<Self, Error> {
+        let pool = PgPool::connect_with(options).await?;
+
+        Ok(PostgresRepository { pool })
+    }
+
+    /// Run several writes as one, in a transaction, see [`with_transaction`]
+    ///
+    /// Arguments:
+    /// - writes: The writes to run on the connection of the transaction
+    pub async fn with_transaction<T, F>(&self, writes: F) -> Result<T, Error>
+    where
+        F: for<'c> FnOnce(&'c mut PgConnection) -> BoxFuture<'c, Result<T, Error>>,
+    {
+        with_transaction(&self.pool, writes).await
+    }
+
+    /// Wait for the schema lock, it is released when the transaction ends
+    ///
+    /// Arguments:
+    /// - connection: The connection of a transaction
+    async fn lock_schema(connection: &mut PgConnection) -> Result<(), Error> {
+        sqlx::query(r#"SELECT pg_advisory_xact_lock($1)"#)
+            .bind(SCHEMA_LOCK)
+            .execute(&mut *connection)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Apply the migrations that have not been applied yet
+    ///
+    /// Arguments:
+    /// - connection: The connection of a transaction that holds the schema lock
+    async fn migrate(connection: &mut PgConnection) -> Result<(), Error> {
+        connection
+            .execute(
+                r#"
+                CREATE TABLE IF NOT EXISTS "schema_version" (
+                    "version"       BIGINT NOT NULL PRIMARY KEY,
+                    "name"          TEXT NOT NULL,
+                    "applied_at"    BIGINT NOT NULL
+                );
+            "#,
+            )
+            .await?;
+
+        let version = sqlx::query(r#"SELECT MAX("version") AS "version" FROM "schema_version""#)
+            .fetch_one(&mut *connection)
+            .await?
+            .get::<Option<i64>, &str>("version")
+            .unwrap_or(0);
+
+        for migration in MIGRATIONS.iter().filter(|m| m.version > version) {
+            // Without arguments the statements of the migration are sent all at once
+            connection.execute(migration.sql).await?;
+
+            sqlx::query(
+                r#"
+                INSERT INTO "schema_version" ("version", "name", "applied_at")
+                VALUES ($1, $2, $3)
+            "#,
+            )
+            .bind(migration.version)
+            .bind(migration.name)
+            .bind(Utc::now().timestamp())
+            .execute(&mut *connection)
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Inserts the initial voices if the table is empty
+    ///
+    /// Arguments:
+    /// - connection: The connection of a transaction that holds the schema lock
+    async fn seed_voices(connection: &mut PgConnection) -> Result<(), Error> {
+        let voice_count = sqlx::query(r#"SELECT COUNT(*) AS "count" FROM "voice""#)
+            .fetch_one(&mut *connection)
+            .await?
+            .get::<i64, &str>("count");
+
+        if voice_count == 0 {
+            let initial_voices = initial_voices();
+
+            let mut bulk_voice_query = QueryBuilder::new(
+                r#"
+                INSERT INTO "voice" ("id", "name", "description", "prefix", "created_at")
+            "#,
+            );
+
+            bulk_voice_query.push_values(initial_voices.iter(), |mut b, voice| {
+                b.push_bind(&voice.id);
+                b.push_bind(&voice.name);
+                b.push_bind(&voice.description);
+                b.push_bind(&voice.prefix);
+                b.push_bind(voice.created_at);
+            });
+
+            bulk_voice_query.build().execute(&mut *connection).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Converts a PostgreSQL Row to a Voice
+    ///
+    /// Arguments:
+    /// - row: The row in the DB
+    fn row_to_voice(row: &PgRow) -> Voice {
+        Voice {
+            id: row.get::<String, &str>("id"),
+            name: row.get::<String, &str>("name"),
+            description: row.get::<String, &str>("description"),
+            prefix: row.get::<String, &str>("prefix"),
+            context_budget: row.get::<Option<i64>, &str>("context_budget"),
+            generation: GenerationSettings {
+                temperature: row.get::<Option<f32>, &str>("temperature"),
+                top_k: row.get::<Option<i64>, &str>("top_k"),
+                top_p: row.get::<Option<f32>, &str>("top_p"),
+                repeat_penalty: row.get::<Option<f32>, &str>("repeat_penalty"),
+                max_tokens: row.get::<Option<i64>, &str>("max_tokens"),
+                stop_sequences: row
+                    .get::<Option<String>, &str>("stop_sequences")
+                    .and_then(|stop_sequences| serde_json::from_str(&stop_sequences).ok()),
+            },
+            model_id: row.get::<Option<String>, &str>("model_id"),
+            created_at: row.get::<i64, &str>("created_at"),
+            deleted_at: row.get::<Option<i64>, &str>("deleted_at"),
+        }
+    }
+
+    /// Converts a PostgreSQL Row to a User
+    ///
+    /// Arguments:
+    /// - row: The row in the DB
+    fn row_to_user(row: &PgRow) -> User {
+        User {
+            id: row.get::<String, &str>("id"),
+            username: row.get::<String, &str>("username"),
+            created_at: row.get::<i64, &str>("created_at"),
+            role: Role::from_str(&row.get::<String, &str>("role")).unwrap_or_default(),
+        }
+    }
+
+    /// Converts a PostgreSQL Row to an ApiKey
+    ///
+    /// Arguments:
+    /// - row: The row in the DB
+    fn row_to_api_key(row: &PgRow) -> ApiKey {
+        ApiKey {
+            id: row.get::<String, &str>("id"),
+            user_id: row.get::<String, &str>("user_id"),
+            name: row.get::<String, &str>("name"),
+            scope: Scope::from_str(&row.get::<String, &str>("scope")).unwrap(),
+            created_at: row.get::<i64, &str>("created_at"),
+            last_used_at: row.get::<Option<i64>, &str>("last_used_at"),
+        }
+    }
+
+    /// Converts a PostgreSQL Row to a Conversation
+    ///
+    /// Arguments:
+    /// - row: The row in the DB
+    fn row_to_conversation(row: &PgRow) -> Conversation {
+        Conversation {
+            id: row.get::<String, &str>("id"),
+            user_id: row.get::<String, &str>("user_id"),
+            name: row.get::<String, &str>("name"),
+            voice_id: row.get::<String, &str>("voice_id"),
+            created_at: row.get::<i64, &str>("created_at"),
+            deleted_at: row.get::<Option<i64>, &str>("deleted_at"),
+        }
+    }
+
+    /// Converts a PostgreSQL Row to a Message
+    ///
+    /// Arguments:
+    /// - row: The row in the DB
+    fn row_to_message(row: &PgRow) -> Message {
+        Message {
+            id: row.get::<String, &str>("id"),
+            conversation_id: row.get::<String, &str>("conversation_id"),
+            author: Author::from_str(&row.get::<String, &str>("author")).unwrap(),
+            content: row.get::<String, &str>("content"),
+            created_at: row.get::<i64, &str>("created_at"),
+            deleted_at: row.get::<Option<i64>, &str>("deleted_at"),
+            truncated: row.get::<bool, &str>("truncated"),
+        }
+    }
+
+    /// Converts a row of a paginated list to its cursor, see [`push_page`]
+    ///
+    /// Arguments:
+    /// - row: The row in the DB
+    fn row_to_cursor(row: &PgRow) -> Cursor {
+        Cursor {
+            created_at: row.get::<i64, &str>("created_at"),
+            seq: row.get::<i64, &str>("seq"),
+        }
+    }
+}
+
+#[async_trait]
+impl Repository for PostgresRepository {
+    /// Asserts the database schema,
+    /// running the migrations that have not been applied yet
+    ///
+    /// The migrations run in one transaction that holds the schema lock,
+    /// so backends that start together apply them once.
+    /// See DATABASE.md for schema reference
+    async fn assert_schema(&self) -> Result<(), Error> {
+        self.with_transaction(|connection| {
+            Box::pin(async move {
+                PostgresRepository::lock_schema(connection).await?;
+                PostgresRepository::migrate(connection).await
+            })
+        })
+        .await
+    }
+
+    async fn init(&self) -> Result<(), Error> {
+        self.with_transaction(|connection| {
+            Box::pin(async move {
+                PostgresRepository::lock_schema(connection).await?;
+                PostgresRepository::seed_voices(connection).await
+            })
+        })
+        .await
+    }
+
+    async fn get_voices(&self, deleted: bool) -> Result<Vec<Voice>, Error> {
+        let sql = format!(
+            r#"
+            SELECT "id", "name", "description", "prefix", "context_budget", "temperature", "top_k",
+                "top_p", "repeat_penalty", "max_tokens", "stop_sequences", "model_id", "created_at",
+                "deleted_at"
+            FROM "voice"
+            WHERE ({} OR "deleted_at" IS NULL)
+        "#,
+            deleted
+        );
+
+        sqlx::query(&sql)
+            .map(|row| PostgresRepository::row_to_voice(&row))
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn get_voice(&self, id: &str) -> Result<Voice, Error> {
+        sqlx::query(
+            r#"
+            SELECT "id", "name", "description", "prefix", "context_budget", "temperature", "top_k",
+                "top_p", "repeat_penalty", "max_tokens", "stop_sequences", "model_id", "created_at",
+                "deleted_at"
+            FROM "voice"
+            WHERE "id" = $1
+        "#,
+        )
+        .bind(id)
+        .map(|row| PostgresRepository::row_to_voice(&row))
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    async fn save_voice(&self, voice: &Voice) -> Result<bool, Error> {
+        let rows_affected = sqlx::query(
+            r#"
+            INSERT INTO "voice" ("id", "name", "description", "prefix", "context_budget",
+                "temperature", "top_k", "top_p", "repeat_penalty", "max_tokens", "stop_sequences",
+                "model_id", "created_at", "deleted_at")
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
+            ON CONFLICT ("id")
+            DO UPDATE SET
+                "name" = EXCLUDED."name",
+                "description" = EXCLUDED."description",
+                "prefix" = EXCLUDED."prefix",
+                "context_budget" = EXCLUDED."context_budget",
+                "temperature" = EXCLUDED."temperature",
+                "top_k" = EXCLUDED."top_k",
+                "top_p" = EXCLUDED."top_p",
+                "repeat_penalty" = EXCLUDED."repeat_penalty",
+                "max_tokens" = EXCLUDED."max_tokens",
+                "stop_sequences" = EXCLUDED."stop_sequences",
+                "model_id" = EXCLUDED."model_id",
+                "deleted_at" = EXCLUDED."deleted_at"
+        "#,
+        )
+        .bind(&voice.id)
+        .bind(&voice.name)
+        .bind(&voice.description)
+        .bind(&voice.prefix)
+        .bind(voice.context_budget)
+        .bind(voice.generation.temperature)
+        .bind(voice.generation.top_k)
+        .bind(voice.generation.top_p)
+        .bind(voice.generation.repeat_penalty)
+        .bind(voice.generation.max_tokens)
+        // Stop sequences are stored as a JSON array
+        .bind(
+            voice
+                .generation
+                .stop_sequences
+                .as_ref()
+                .map(|stop_sequences| serde_json::to_string(stop_sequences).unwrap_or_default()),
+        )
+        .bind(&voice.model_id)
+        .bind(voice.created_at)
+        .bind(voice.deleted_at)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        Ok(rows_affected == 1)
+    }
+
+    async fn delete_voice(&self, voice_id: &str) -> Result<bool, Error> {
+        let rows_affected = sqlx::query(
+            r#"
+            UPDATE "voice"
+            SET "deleted_at" = $1
+            WHERE "id" = $2
+        "#,
+        )
+        .bind(Utc::now().timestamp())
+        .bind(voice_id)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        Ok(rows_affected == 1)
+    }
+
+    async fn get_conversations(
+        &self,
+        user_id: &str,
+        deleted: bool,
+        page: &Page,
+    ) -> Result<Paged<Conversation>, Error> {
+        let mut query = QueryBuilder::new(format!(
+            r#"
+            SELECT "id", "user_id", "name", "voice_id", "created_at", "deleted_at",
+                "seq"
+            FROM "conversation"
+            WHERE ({} OR "deleted_at" IS NULL)
+                AND "user_id" = "#,
+            deleted
+        ));
+        query.push_bind(user_id);
+        push_page(&mut query, page);
+
+        let rows = query
+            .build()
+            .map(|row| {
+                (
+                    PostgresRepository::row_to_conversation(&row),
+                    PostgresRepository::row_to_cursor(&row),
+                )
+            })
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(paginate(rows, page))
+    }
+
+    async fn get_conversation(&self, id: &str) -> Result<Conversation, Error> {
+        sqlx::query(
+            r#"
+            SELECT "id", "user_id", "name", "voice_id", "created_at", "deleted_at"
+            FROM "conversation"
+            WHERE "id" = $1
+        "#,
+        )
+        .bind(id)
+        .map(|row| PostgresRepository::row_to_conversation(&row))
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    async fn save_conversation(&self, conversation: &Conversation) -> Result<bool, Error> {
+        let rows_affected = sqlx::query(
+            r#"
+            INSERT INTO "conversation" ("id", "user_id", "name", "voice_id", "created_at",
+                "deleted_at")
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT ("id")
+            DO UPDATE SET
+                "user_id" = EXCLUDED."user_id",
+                "name" = EXCLUDED."name",
+                "voice_id" = EXCLUDED."voice_id",
+                "deleted_at" = EXCLUDED."deleted_at"
+        "#,
+        )
+        .bind(&conversation.id)
+        .bind(&conversation.user_id)
+        .bind(&conversation.name)
+        .bind(&conversation.voice_id)
+        .bind(conversation.created_at)
+        .bind(conversation.deleted_at)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        Ok(rows_affected == 1)
+    }
+
+    async fn delete_conversation(&self, conversation_id: &str) -> Result<bool, Error> {
+        let rows_affected = sqlx::query(
+            r#"
+            UPDATE "conversation"
+            SET "deleted_at" = $1
+            WHERE "id" = $2
+        "#,
+        )
+        .bind(Utc::now().timestamp())
+        .bind(conversation_id)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        Ok(rows_affected == 1)
+    }
+
+    async fn restore_conversation(&self, conversation_id: &str) -> Result<bool, Error> {
+        let rows_affected = sqlx::query(
+            r#"
+            UPDATE "conversation"
+            SET "deleted_at" = NULL
+            WHERE "id" = $1 AND "deleted_at" IS NOT NULL
+        "#,
+        )
+        .bind(conversation_id)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        Ok(rows_affected == 1)
+    }
+
+    async fn get_messages(
+        &self,
+        conversation_id: &str,
+        deleted: bool,
+        page: &Page,
+    ) -> Result<Paged<Message>, Error> {
+        let mut query = QueryBuilder::new(format!(
+            r#"
+            SELECT "id", "conversation_id", "author", "content", "created_at", "deleted_at",
+                "truncated", "seq"
+            FROM "message"
+            WHERE ({} OR "deleted_at" IS NULL)
+                AND "conversation_id" = "#,
+            deleted
+        ));
+        query.push_bind(conversation_id);
+        push_page(&mut query, page);
+
+        let rows = query
+            .build()
+            .map(|row| {
+                (
+                    PostgresRepository::row_to_message(&row),
+                    PostgresRepository::row_to_cursor(&row),
+                )
+            })
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(paginate(rows, page))
+    }
+
+    async fn get_message(&self, id: &str) -> Result<Message, Error> {
+        sqlx::query(
+            r#"
+            SELECT "id", "conversation_id", "author", "content", "created_at", "deleted_at",
+                "truncated"
+            FROM "message"
+            WHERE "id" = $1
+        "#,
+        )
+        .bind(id)
+        .map(|row| PostgresRepository::row_to_message(&row))
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    async fn save_message(&self, message: &Message) -> Result<bool, Error> {
+        let rows_affected = sqlx::query(
+            r#"
+            INSERT INTO "message" ("id", "conversation_id", "author", "content", "created_at",
+                "deleted_at", "truncated")
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            ON CONFLICT ("id")
+            DO UPDATE SET
+                "conversation_id" = EXCLUDED."conversation_id",
+                "author" = EXCLUDED."author",
+                "content" = EXCLUDED."content",
+                "deleted_at" = EXCLUDED."deleted_at",
+                "truncated" = EXCLUDED."truncated"
+        "#,
+        )
+        .bind(&message.id)
+        .bind(&message.conversation_id)
+        .bind(message.author.to_string())
+        .bind(&message.content)
+        .bind(message.created_at)
+        .bind(message.deleted_at)
+        .bind(message.truncated)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        Ok(rows_affected == 1)
+    }
+
+    async fn restore_message(&self, message_id: &str) -> Result<bool, Error> {
+        let rows_affected = sqlx::query(
+            r#"
+            UPDATE "message"
+            SET "deleted_at" = NULL
+            WHERE "id" = $1 AND "deleted_at" IS NOT NULL
+        "#,
+        )
+        .bind(message_id)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        Ok(rows_affected == 1)
+    }
+
+    // The words are matched without stemming, like the SQLite index
+    async fn search_messages(
+        &self,
+        user_id: &str,
+        terms: &str,
+        limit: i64,
+    ) -> Result<Vec<SearchResult>, Error> {
+        sqlx::query(
+            r#"
+            SELECT "message"."id", "message"."conversation_id", "message"."author",
+                "message"."content", "message"."created_at", "message"."deleted_at",
+                "message"."truncated",
+                "conversation"."name" AS "conversation_name",
+                ts_headline('simple', "message"."content", "terms",
+                    'StartSel=<mark>, StopSel=</mark>, MaxWords=32, MinWords=16') AS "snippet"
+            FROM "message"
+            JOIN "conversation" ON "conversation"."id" = "message"."conversation_id",
+                plainto_tsquery('simple', $1) AS "terms"
+            WHERE to_tsvector('simple', "message"."content") @@ "terms"
+                AND "conversation"."user_id" = $2
+                AND "message"."deleted_at" IS NULL
+                AND "conversation"."deleted_at" IS NULL
+            ORDER BY ts_rank(to_tsvector('simple', "message"."content"), "terms") DESC
+            LIMIT $3
+        "#,
+        )
+        .bind(terms)
+        .bind(user_id)
+        .bind(limit)
+        .map(|row: PgRow| SearchResult {
+            message: PostgresRepository::row_to_message(&row),
+            conversation_name: row.get("conversation_name"),
+            snippet: row.get("snippet"),
+        })
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn save_user(&self, user: &User, password_hash: &str) -> Result<bool, Error> {
+        let rows_affected = sqlx::query(
+            r#"
+            INSERT INTO "user" ("id", "username", "password_hash", "created_at", "role")
+            VALUES ($1, $2, $3, $4, $5)
+        "#,
+        )
+        .bind(&user.id)
+        .bind(&user.username)
+        .bind(password_hash)
+        .bind(user.created_at)
+        .bind(user.role.to_string())
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        Ok(rows_affected == 1)
+    }
+
+    async fn get_user_login(&self, username: &str) -> Result<(User, String), Error> {
+        sqlx::query(
+            r#"
+            SELECT "user"."id", "user"."username", "user"."created_at", "user"."role",
+                "user"."password_hash"
+            FROM "user"
+            WHERE "username" = $1
+        "#,
+        )
+        .bind(username)
+        .map(|row: PgRow| {
+            (
+                PostgresRepository::row_to_user(&row),
+                row.get("password_hash"),
+            )
+        })
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    async fn set_user_role(&self, user_id: &str, role: Role) -> Result<bool, Error> {
+        let rows_affected = sqlx::query(
+            r#"
+            UPDATE "user"
+            SET "role" = $1
+            WHERE "id" = $2
+        "#,
+        )
+        .bind(role.to_string())
+        .bind(user_id)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        Ok(rows_affected == 1)
+    }
+
+    async fn save_session(&self, session: &Session) -> Result<bool, Error> {
+        let rows_affected = sqlx::query(
+            r#"
+            INSERT INTO "session" ("id", "user_id", "created_at", "expires_at")
+            VALUES ($1, $2, $3, $4)
+        "#,
+        )
+        .bind(&session.id)
+        .bind(&session.user_id)
+        .bind(session.created_at)
+        .bind(session.expires_at)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        Ok(rows_affected == 1)
+    }
+
+    async fn get_session_user(&self, session_id: &str) -> Result<User, Error> {
+        sqlx::query(
+            r#"
+            SELECT "user"."id", "user"."username", "user"."created_at", "user"."role"
+            FROM "session"
+            JOIN "user" ON "user"."id" = "session"."user_id"
+            WHERE "session"."id" = $1 AND "session"."expires_at" > $2
+        "#,
+        )
+        .bind(session_id)
+        .bind(Utc::now().timestamp())
+        .map(|row| PostgresRepository::row_to_user(&row))
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    async fn delete_session(&self, session_id: &str) -> Result<bool, Error> {
+        let rows_affected = sqlx::query(
+            r#"
+            DELETE FROM "session"
+            WHERE "id" = $1
+        "#,
+        )
+        .bind(session_id)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        Ok(rows_affected == 1)
+    }
+
+    async fn save_api_key(&self, api_key: &ApiKey, key_hash: &str) -> Result<bool, Error> {
+        let rows_affected = sqlx::query(
+            r#"
+            INSERT INTO "api_key" ("id", "user_id", "name", "scope", "key_hash", "created_at",
+                "last_used_at")
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+        "#,
+        )
+        .bind(&api_key.id)
+        .bind(&api_key.user_id)
+        .bind(&api_key.name)
+        .bind(api_key.scope.to_string())
+        .bind(key_hash)
+        .bind(api_key.created_at)
+        .bind(api_key.last_used_at)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        Ok(rows_affected == 1)
+    }
+
+    async fn get_api_keys(&self, user_id: &str) -> Result<Vec<ApiKey>, Error> {
+        sqlx::query(
+            r#"
+            SELECT "id", "user_id", "name", "scope", "created_at", "last_used_at"
+            FROM "api_key"
+            WHERE "user_id" = $1
+            ORDER BY "created_at" ASC, "id" ASC
+        "#,
+        )
+        .bind(user_id)
+        .map(|row| PostgresRepository::row_to_api_key(&row))
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn use_api_key(&self, key_hash: &str) -> Result<(User, ApiKey), Error> {
+        let key_hash = key_hash.to_string();
+        self.with_transaction(move |connection| {
+            Box::pin(async move {
+                sqlx::query(
+                    r#"
+                    UPDATE "api_key"
+                    SET "last_used_at" = $1
+                    WHERE "key_hash" = $2
+                "#,
+                )
+                .bind(Utc::now().timestamp())
+                .bind(&key_hash)
+                .execute(&mut *connection)
+                .await?;
+
+                sqlx::query(
+                    r#"
+                    SELECT "user"."id", "user"."username", "user"."created_at", "user"."role",
+                        "api_key"."id" AS "api_key_id", "api_key"."name", "api_key"."scope",
+                        "api_key"."created_at" AS "api_key_created_at", "api_key"."last_used_at"
+                    FROM "api_key"
+                    JOIN "user" ON "user"."id" = "api_key"."user_id"
+                    WHERE "api_key"."key_hash" = $1
+                "#,
+                )
+                .bind(&key_hash)
+                .map(|row: PgRow| {
+                    let user = PostgresRepository::row_to_user(&row);
+                    let api_key = ApiKey {
+                        id: row.get::<String, &str>("api_key_id"),
+                        user_id: user.id.clone(),
+                        name: row.get::<String, &str>("name"),
+                        scope: Scope::from_str(&row.get::<String, &str>("scope")).unwrap(),
+                        created_at: row.get::<i64, &str>("api_key_created_at"),
+                        last_used_at: row.get::<Option<i64>, &str>("last_used_at"),
+                    };
+                    (user, api_key)
+                })
+                .fetch_one(&mut *connection)
+                .await
+            })
+        })
+        .await
+    }
+
+    async fn delete_api_key(&self, user_id: &str, api_key_id: &str) -> Result<bool, Error> {
+        let rows_affected = sqlx::query(
+            r#"
+            DELETE FROM "api_key"
+            WHERE "id" = $1 AND "user_id" = $2
+        "#,
+        )
+        .bind(api_key_id)
+        .bind(user_id)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        Ok(rows_affected == 1)
+    }
+
+    async fn claim_conversations(&self, anonymous_id: &str, user_id: &str) -> Result<u64, Error> {
+        let anonymous_id = anonymous_id.to_string();
+        let user_id = user_id.to_string();
+        self.with_transaction(move |connection| {
+            Box::pin(async move {
+                let registered: i64 = sqlx::query_scalar(
+                    r#"
+                    SELECT COUNT(*) FROM "user"
+                    WHERE "id" = $1
+                "#,
+                )
+                .bind(&anonymous_id)
+                .fetch_one(&mut *connection)
+                .await?;
+                if registered > 0 {
+                    return Ok(0);
+                }
+
+                let rows_affected = sqlx::query(
+                    r#"
+                    UPDATE "conversation"
+                    SET "user_id" = $1
+                    WHERE "user_id" = $2
+                "#,
+                )
+                .bind(&user_id)
+                .bind(&anonymous_id)
+                .execute(&mut *connection)
+                .await?
+                .rows_affected();
+
+                Ok(rows_affected)
+            })
+        })
+        .await
+    }
+
+    async fn purge_deleted(&self, before: i64) -> Result<PurgeReport, Error> {
+        self.with_transaction(move |connection| {
+            Box::pin(async move {
+                // Messages go first, their conversations can't be removed while they exist
+                let messages = sqlx::query(
+                    r#"
+                    DELETE FROM "message"
+                    WHERE "deleted_at" < $1
+                        OR "conversation_id" IN (
+                            SELECT "id" FROM "conversation" WHERE "deleted_at" < $1
+                        )
+                "#,
+                )
+                .bind(before)
+                .execute(&mut *connection)
+                .await?
+                .rows_affected();
+
+                let conversations = sqlx::query(
+                    r#"
+                    DELETE FROM "conversation"
+                    WHERE "deleted_at" < $1
+                "#,
+                )
+                .bind(before)
+                .execute(&mut *connection)
+                .await?
+                .rows_affected();
+
+                Ok(PurgeReport {
+                    conversations,
+                    messages,
+                })
+            })
+        })
+        .await
+    }
+
+    async fn optimize(&self) -> Result<(), Error> {
+        // VACUUM can't run in a transaction, or as a prepared statement
+        self.pool
+            .execute(r#"VACUUM ANALYZE "conversation", "message""#)
+            .await?;
+
+        Ok(())
+    }
+}
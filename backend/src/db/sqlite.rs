@@ -0,0 +1,1507 @@
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use futures_util::future::BoxFuture;
+use models::{
+    ApiKey, Author, Conversation, GenerationSettings, Message, PurgeReport, Role, Scope,
+    SearchResult, User, Voice,
+};
+use sqlx::{
+    sqlite::{SqliteConnectOptions, SqliteConnection, SqliteJournalMode, SqlitePool, SqliteRow},
+    Error, Executor, QueryBuilder, Row,
+};
+
+use super::{
+    initial_voices, paginate, push_page, with_transaction, Cursor, Migration, Page, Paged,
+    Repository, Session,
+};
+
+/// Every migration, in the order they are applied
+const MIGRATIONS: [Migration; 13] = [
+    Migration {
+        version: 1,
+        name: "initial",
+        sql: include_str!("../../db/migrations/0001_initial.sql"),
+    },
+    Migration {
+        version: 2,
+        name: "voice_context_budget",
+        sql: include_str!("../../db/migrations/0002_voice_context_budget.sql"),
+    },
+    Migration {
+        version: 3,
+        name: "voice_generation_settings",
+        sql: include_str!("../../db/migrations/0003_voice_generation_settings.sql"),
+    },
+    Migration {
+        version: 4,
+        name: "voice_model_id",
+        sql: include_str!("../../db/migrations/0004_voice_model_id.sql"),
+    },
+    Migration {
+        version: 5,
+        name: "references",
+        sql: include_str!("../../db/migrations/0005_references.sql"),
+    },
+    Migration {
+        version: 6,
+        name: "pagination_indexes",
+        sql: include_str!("../../db/migrations/0006_pagination_indexes.sql"),
+    },
+    Migration {
+        version: 7,
+        name: "message_search",
+        sql: include_str!("../../db/migrations/0007_message_search.sql"),
+    },
+    Migration {
+        version: 8,
+        name: "conversation_restore",
+        sql: include_str!("../../db/migrations/0008_conversation_restore.sql"),
+    },
+    Migration {
+        version: 9,
+        name: "users",
+        sql: include_str!("../../db/migrations/0009_users.sql"),
+    },
+    Migration {
+        version: 10,
+        name: "user_roles",
+        sql: include_str!("../../db/migrations/0010_user_roles.sql"),
+    },
+    Migration {
+        version: 11,
+        name: "api_keys",
+        sql: include_str!("../../db/migrations/0011_api_keys.sql"),
+    },
+    Migration {
+        version: 12,
+        name: "message_truncated",
+        sql: include_str!("../../db/migrations/0012_message_truncated.sql"),
+    },
+    Migration {
+        version: 13,
+        name: "insertion_order",
+        sql: include_str!("../../db/migrations/0013_insertion_order.sql"),
+    },
+];
+
+/// Stores the data in an SQLite database
+#[derive(Clone)]
+pub struct SqliteRepository {
+    pool: SqlitePool,
+}
+
+impl SqliteRepository {
+    /// Connects to an SQLite database
+    ///
+    /// Every connection enforces foreign keys,
+    /// and file databases use a write-ahead log so reads don't wait for writes
+    ///
+    /// Arguments:
+    /// - url: an SQLite connection string
+    pub async fn connect(url: &str) -> Result<Self, Error> {
+        let options = SqliteConnectOptions::from_str(url)?
+            .foreign_keys(true)
+            .journal_mode(SqliteJournalMode::Wal);
+        let pool = SqlitePool::connect_with(options).await?;
+
+        Ok(SqliteRepository { pool })
+    }
+
+    /// Run several writes as one, in a transaction, see [`with_transaction`]
+    ///
+    /// Arguments:
+    /// - writes: The writes to run on the connection of the transaction
+    pub async fn with_transaction<T, F>(&self, writes: F) -> Result<T, Error>
+    where
+        F: for<'c> FnOnce(&'c mut SqliteConnection) -> BoxFuture<'c, Result<T, Error>>,
+    {
+        with_transaction(&self.pool, writes).await
+    }
+
+    /// The version of a database created before migrations were tracked
+    ///
+    /// Those databases all have the baseline schema, version 1,
+    /// every later change was made as a tracked migration. Returns 0 for an empty database
+    ///
+    /// Arguments:
+    /// - connection: A connection to the database
+    async fn legacy_version(connection: &mut SqliteConnection) -> Result<i64, Error> {
+        let columns = sqlx::query(r#"SELECT `name` FROM pragma_table_info('voice')"#)
+            .map(|row: SqliteRow| row.get::<String, &str>("name"))
+            .fetch_all(&mut *connection)
+            .await?;
+
+        Ok(match columns.is_empty() {
+            true => 0,
+            false => 1,
+        })
+    }
+
+    /// Mark a migration as applied
+    ///
+    /// Arguments:
+    /// - connection: A connection to the database
+    /// - migration: The migration that was applied
+    async fn record_migration(
+        connection: &mut SqliteConnection,
+        migration: &Migration,
+    ) -> Result<(), Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO `schema_version` (version, name, applied_at)
+            VALUES (?1, ?2, ?3)
+        "#,
+        )
+        .bind(migration.version)
+        .bind(migration.name)
+        .bind(Utc::now().timestamp())
+        .execute(&mut *connection)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Inserts the initial voices if the table is empty
+    ///
+    /// Arguments:
+    /// - connection: A connection to the database
+    async fn seed_voices(connection: &mut SqliteConnection) -> Result<(), Error> {
+        let voice_count_query = r#"
+            SELECT COUNT(*) as count
+            FROM `voice`
+        "#;
+
+        let voice_count = sqlx::query(voice_count_query)
+            .fetch_one(&mut *connection)
+            .await?
+            .get::<i64, &str>("count");
+
+        if voice_count == 0 {
+            let initial_voices = initial_voices();
+
+            let mut bulk_voice_query = QueryBuilder::new(
+                r#"
+                INSERT INTO voice (id, name, description, prefix, created_at)
+            "#,
+            );
+
+            bulk_voice_query.push_values(initial_voices.iter(), |mut b, voice| {
+                b.push_bind(&voice.id);
+                b.push_bind(&voice.name);
+                b.push_bind(&voice.description);
+                b.push_bind(&voice.prefix);
+                b.push_bind(voice.created_at);
+            });
+
+            let query = bulk_voice_query.build();
+
+            query.execute(&mut *connection).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Turn the words a user searches for into a full-text query
+    ///
+    /// Every word is quoted, so characters of the query syntax are searched for as text
+    ///
+    /// Arguments:
+    /// - terms: the words to look for
+    fn match_query(terms: &str) -> String {
+        terms
+            .split_whitespace()
+            .map(|term| format!("\"{}\"", term.replace('"', "\"\"")))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Converts an SQLite Row to a Voice
+    ///
+    /// Arguments:
+    /// - row: The row in the DB
+    fn row_to_voice(row: &SqliteRow) -> Voice {
+        Voice {
+            id: row.get::<String, &str>("id"),
+            name: row.get::<String, &str>("name"),
+            description: row.get::<String, &str>("description"),
+            prefix: row.get::<String, &str>("prefix"),
+            context_budget: row.get::<Option<i64>, &str>("context_budget"),
+            generation: GenerationSettings {
+                temperature: row.get::<Option<f32>, &str>("temperature"),
+                top_k: row.get::<Option<i64>, &str>("top_k"),
+                top_p: row.get::<Option<f32>, &str>("top_p"),
+                repeat_penalty: row.get::<Option<f32>, &str>("repeat_penalty"),
+                max_tokens: row.get::<Option<i64>, &str>("max_tokens"),
+                stop_sequences: row
+                    .get::<Option<String>, &str>("stop_sequences")
+                    .and_then(|stop_sequences| serde_json::from_str(&stop_sequences).ok()),
+            },
+            model_id: row.get::<Option<String>, &str>("model_id"),
+            created_at: row.get::<i64, &str>("created_at"),
+            deleted_at: row.get::<Option<i64>, &str>("deleted_at"),
+        }
+    }
+
+    /// Converts an SQLite Row to a User
+    ///
+    /// Arguments:
+    /// - row: The row in the DB
+    fn row_to_user(row: &SqliteRow) -> User {
+        User {
+            id: row.get::<String, &str>("id"),
+            username: row.get::<String, &str>("username"),
+            created_at: row.get::<i64, &str>("created_at"),
+            role: Role::from_str(&row.get::<String, &str>("role")).unwrap_or_default(),
+        }
+    }
+
+    /// Converts an SQLite Row to an ApiKey
+    ///
+    /// Arguments:
+    /// - row: The row in the DB
+    fn row_to_api_key(row: &SqliteRow) -> ApiKey {
+        ApiKey {
+            id: row.get::<String, &str>("id"),
+            user_id: row.get::<String, &str>("user_id"),
+            name: row.get::<String, &str>("name"),
+            scope: Scope::from_str(&row.get::<String, &str>("scope")).unwrap(),
+            created_at: row.get::<i64, &str>("created_at"),
+            last_used_at: row.get::<Option<i64>, &str>("last_used_at"),
+        }
+    }
+
+    /// Converts an SQLite Row to a Conversation
+    ///
+    /// Arguments:
+    /// - row: The row in the DB
+    fn row_to_conversation(row: &SqliteRow) -> Conversation {
+        Conversation {
+            id: row.get::<String, &str>("id"),
+            user_id: row.get::<String, &str>("user_id"),
+            name: row.get::<String, &str>("name"),
+            voice_id: row.get::<String, &str>("voice_id"),
+            created_at: row.get::<i64, &str>("created_at"),
+            deleted_at: row.get::<Option<i64>, &str>("deleted_at"),
+        }
+    }
+
+    /// Converts an SQLite Row to a Message
+    ///
+    /// Arguments:
+    /// - row: The row in the DB
+    fn row_to_message(row: &SqliteRow) -> Message {
+        Message {
+            id: row.get::<String, &str>("id"),
+            conversation_id: row.get::<String, &str>("conversation_id"),
+            author: Author::from_str(&row.get::<String, &str>("author").to_string()).unwrap(),
+            content: row.get::<String, &str>("content"),
+            created_at: row.get::<i64, &str>("created_at"),
+            deleted_at: row.get::<Option<i64>, &str>("deleted_at"),
+            truncated: row.get::<bool, &str>("truncated"),
+        }
+    }
+
+    /// Converts a row of a paginated list to its cursor, see [`push_page`]
+    ///
+    /// Arguments:
+    /// - row: The row in the DB
+    fn row_to_cursor(row: &SqliteRow) -> Cursor {
+        Cursor {
+            created_at: row.get::<i64, &str>("created_at"),
+            seq: row.get::<i64, &str>("seq"),
+        }
+    }
+}
+
+#[async_trait]
+impl Repository for SqliteRepository {
+    /// Asserts the database schema,
+    /// running the migrations that have not been applied yet
+    ///
+    /// Applied migrations are tracked in the `schema_version` table.
+    /// See DATABASE.md for schema reference
+    async fn assert_schema(&self) -> Result<(), Error> {
+        let mut connection = self.pool.acquire().await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS "schema_version" (
+                "version"       INTEGER NOT NULL UNIQUE,
+                "name"          TEXT NOT NULL,
+                "applied_at"    INTEGER NOT NULL,
+                PRIMARY KEY("version")
+            );
+        "#,
+        )
+        .execute(&mut *connection)
+        .await?;
+
+        let mut version = sqlx::query(r#"SELECT MAX(`version`) AS version FROM `schema_version`"#)
+            .fetch_one(&mut *connection)
+            .await?
+            .get::<Option<i64>, &str>("version")
+            .unwrap_or(0);
+
+        if version == 0 {
+            version = SqliteRepository::legacy_version(&mut connection).await?;
+            self.with_transaction(move |connection| {
+                Box::pin(async move {
+                    for migration in MIGRATIONS.iter().filter(|m| m.version <= version) {
+                        SqliteRepository::record_migration(connection, migration).await?;
+                    }
+                    Ok(())
+                })
+            })
+            .await?;
+        }
+
+        for migration in MIGRATIONS.iter().filter(|m| m.version > version) {
+            // Each migration is applied completely or not at all
+            self.with_transaction(move |connection| {
+                Box::pin(async move {
+                    sqlx::query(migration.sql).execute(&mut *connection).await?;
+                    SqliteRepository::record_migration(connection, migration).await
+                })
+            })
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn init(&self) -> Result<(), Error> {
+        // Counted and seeded in one transaction, so the voices are only inserted once
+        self.with_transaction(|connection| Box::pin(SqliteRepository::seed_voices(connection)))
+            .await
+    }
+
+    async fn get_voices(&self, deleted: bool) -> Result<Vec<Voice>, Error> {
+        let sql = format!(
+            r#"
+            SELECT `id`, `name`, `description`, `prefix`, `context_budget`, `temperature`, `top_k`,
+                `top_p`, `repeat_penalty`, `max_tokens`, `stop_sequences`, `model_id`, `created_at`,
+                `deleted_at`
+            FROM `voice`
+            WHERE ({} OR `deleted_at` IS NULL)
+        "#,
+            deleted
+        );
+
+        let mut connection = self.pool.acquire().await?;
+        let rows = sqlx::query(&sql)
+            .map(|row| SqliteRepository::row_to_voice(&row))
+            .fetch_all(&mut *connection)
+            .await?;
+
+        Ok(rows)
+    }
+
+    async fn get_voice(&self, id: &str) -> Result<Voice, Error> {
+        let sql = String::from(
+            r#"
+            SELECT `id`, `name`, `description`, `prefix`, `context_budget`, `temperature`, `top_k`,
+                `top_p`, `repeat_penalty`, `max_tokens`, `stop_sequences`, `model_id`, `created_at`,
+                `deleted_at`
+            FROM `voice`
+            WHERE `id` = ?
+        "#,
+        );
+
+        let mut connection = self.pool.acquire().await?;
+
+        sqlx::query(&sql)
+            .bind(id)
+            .map(|row| SqliteRepository::row_to_voice(&row))
+            .fetch_one(&mut *connection)
+            .await
+    }
+
+    async fn save_voice(&self, voice: &Voice) -> Result<bool, Error> {
+        let mut connection = self.pool.acquire().await?;
+
+        let rows_affected = sqlx::query(
+            r#"
+            INSERT INTO `voice` (id, name, description, prefix, context_budget, temperature, top_k,
+                top_p, repeat_penalty, max_tokens, stop_sequences, model_id, created_at, deleted_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)
+            ON CONFLICT (id)
+            DO UPDATE SET
+                name = excluded.name,
+                description = excluded.description,
+                prefix = excluded.prefix,
+                context_budget = excluded.context_budget,
+                temperature = excluded.temperature,
+                top_k = excluded.top_k,
+                top_p = excluded.top_p,
+                repeat_penalty = excluded.repeat_penalty,
+                max_tokens = excluded.max_tokens,
+                stop_sequences = excluded.stop_sequences,
+                model_id = excluded.model_id,
+                deleted_at = excluded.deleted_at
+        "#,
+        )
+        .bind(&voice.id)
+        .bind(&voice.name)
+        .bind(&voice.description)
+        .bind(&voice.prefix)
+        .bind(voice.context_budget)
+        .bind(voice.generation.temperature)
+        .bind(voice.generation.top_k)
+        .bind(voice.generation.top_p)
+        .bind(voice.generation.repeat_penalty)
+        .bind(voice.generation.max_tokens)
+        // Stop sequences are stored as a JSON array
+        .bind(
+            voice
+                .generation
+                .stop_sequences
+                .as_ref()
+                .map(|stop_sequences| serde_json::to_string(stop_sequences).unwrap_or_default()),
+        )
+        .bind(&voice.model_id)
+        .bind(voice.created_at)
+        .bind(voice.deleted_at)
+        .execute(&mut *connection)
+        .await?
+        .rows_affected();
+
+        Ok(rows_affected == 1)
+    }
+
+    async fn delete_voice(&self, voice_id: &str) -> Result<bool, Error> {
+        let mut connection = self.pool.acquire().await?;
+
+        let rows_affected = sqlx::query(
+            r#"
+            UPDATE `voice`
+            SET `deleted_at` = ?1
+            WHERE `id` = ?2
+        "#,
+        )
+        .bind(Utc::now().timestamp())
+        .bind(voice_id)
+        .execute(&mut *connection)
+        .await?
+        .rows_affected();
+
+        Ok(rows_affected == 1)
+    }
+
+    async fn get_conversations(
+        &self,
+        user_id: &str,
+        deleted: bool,
+        page: &Page,
+    ) -> Result<Paged<Conversation>, Error> {
+        let mut query = QueryBuilder::new(format!(
+            r#"
+            SELECT `id`, `user_id`, `name`, `voice_id`, `created_at`, `deleted_at`,
+                `seq`
+            FROM `conversation`
+            WHERE ({} OR `deleted_at` IS NULL)
+                AND `user_id` = "#,
+            deleted
+        ));
+        query.push_bind(user_id);
+        push_page(&mut query, page);
+
+        let mut connection = self.pool.acquire().await?;
+        let rows = query
+            .build()
+            .map(|row| {
+                (
+                    SqliteRepository::row_to_conversation(&row),
+                    SqliteRepository::row_to_cursor(&row),
+                )
+            })
+            .fetch_all(&mut *connection)
+            .await?;
+
+        Ok(paginate(rows, page))
+    }
+
+    async fn get_conversation(&self, id: &str) -> Result<Conversation, Error> {
+        let sql = String::from(
+            r#"
+            SELECT `id`, `user_id`, `name`, `voice_id`, `created_at`, `deleted_at`
+            FROM `conversation`
+            WHERE `id` = ?
+        "#,
+        );
+
+        let mut connection = self.pool.acquire().await?;
+
+        sqlx::query(&sql)
+            .bind(id)
+            .map(|row| SqliteRepository::row_to_conversation(&row))
+            .fetch_one(&mut *connection)
+            .await
+    }
+
+    async fn save_conversation(&self, conversation: &Conversation) -> Result<bool, Error> {
+        let mut connection = self.pool.acquire().await?;
+
+        let rows_affected = sqlx::query(
+            r#"
+            INSERT INTO `conversation` (id, user_id, name, voice_id, created_at, deleted_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+            ON CONFLICT (id)
+            DO UPDATE SET
+                user_id = excluded.user_id,
+                name = excluded.name,
+                voice_id = excluded.voice_id,
+                deleted_at = excluded.deleted_at
+        "#,
+        )
+        .bind(&conversation.id)
+        .bind(&conversation.user_id)
+        .bind(&conversation.name)
+        .bind(&conversation.voice_id)
+        .bind(conversation.created_at)
+        .bind(conversation.deleted_at)
+        .execute(&mut *connection)
+        .await?
+        .rows_affected();
+
+        Ok(rows_affected == 1)
+    }
+
+    async fn delete_conversation(&self, conversation_id: &str) -> Result<bool, Error> {
+        let mut connection = self.pool.acquire().await?;
+
+        let rows_affected = sqlx::query(
+            r#"
+            UPDATE `conversation`
+            SET `deleted_at` = ?1
+            WHERE `id` = ?2
+        "#,
+        )
+        .bind(Utc::now().timestamp())
+        .bind(conversation_id)
+        .execute(&mut *connection)
+        .await?
+        .rows_affected();
+
+        Ok(rows_affected == 1)
+    }
+
+    async fn restore_conversation(&self, conversation_id: &str) -> Result<bool, Error> {
+        let mut connection = self.pool.acquire().await?;
+
+        let rows_affected = sqlx::query(
+            r#"
+            UPDATE `conversation`
+            SET `deleted_at` = NULL
+            WHERE `id` = ?1 AND `deleted_at` IS NOT NULL
+        "#,
+        )
+        .bind(conversation_id)
+        .execute(&mut *connection)
+        .await?
+        .rows_affected();
+
+        Ok(rows_affected == 1)
+    }
+
+    async fn get_messages(
+        &self,
+        conversation_id: &str,
+        deleted: bool,
+        page: &Page,
+    ) -> Result<Paged<Message>, Error> {
+        let mut query = QueryBuilder::new(format!(
+            r#"
+            SELECT `id`, `conversation_id`, `author`, `content`, `created_at`, `deleted_at`,
+                `truncated`, `seq`
+            FROM `message`
+            WHERE ({} OR `deleted_at` IS NULL)
+                AND `conversation_id` = "#,
+            deleted
+        ));
+        query.push_bind(conversation_id);
+        push_page(&mut query, page);
+
+        let mut connection = self.pool.acquire().await?;
+        let rows = query
+            .build()
+            .map(|row| {
+                (
+                    SqliteRepository::row_to_message(&row),
+                    SqliteRepository::row_to_cursor(&row),
+                )
+            })
+            .fetch_all(&mut *connection)
+            .await?;
+
+        Ok(paginate(rows, page))
+    }
+
+    async fn get_message(&self, id: &str) -> Result<Message, Error> {
+        let sql = String::from(
+            r#"
+            SELECT `id`, `conversation_id`, `author`, `content`, `created_at`, `deleted_at`,
+                `truncated`
+            FROM `message`
+            WHERE `id` = ?
+        "#,
+        );
+
+        let mut connection = self.pool.acquire().await?;
+
+        sqlx::query(&sql)
+            .bind(id)
+            .map(|row| SqliteRepository::row_to_message(&row))
+            .fetch_one(&mut *connection)
+            .await
+    }
+
+    async fn save_message(&self, message: &Message) -> Result<bool, Error> {
+        let mut connection = self.pool.acquire().await?;
+
+        let rows_affected = sqlx::query(
+            r#"
+            INSERT INTO `message` (id, conversation_id, author, content, created_at, deleted_at,
+                truncated)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+            ON CONFLICT (id)
+            DO UPDATE SET
+                conversation_id = excluded.conversation_id,
+                author = excluded.author,
+                content = excluded.content,
+                deleted_at = excluded.deleted_at,
+                truncated = excluded.truncated
+        "#,
+        )
+        .bind(&message.id)
+        .bind(&message.conversation_id)
+        .bind(message.author.to_string())
+        .bind(&message.content)
+        .bind(message.created_at)
+        .bind(message.deleted_at)
+        .bind(message.truncated)
+        .execute(&mut *connection)
+        .await?
+        .rows_affected();
+
+        Ok(rows_affected == 1)
+    }
+
+    async fn restore_message(&self, message_id: &str) -> Result<bool, Error> {
+        let mut connection = self.pool.acquire().await?;
+
+        let rows_affected = sqlx::query(
+            r#"
+            UPDATE `message`
+            SET `deleted_at` = NULL
+            WHERE `id` = ?1 AND `deleted_at` IS NOT NULL
+        "#,
+        )
+        .bind(message_id)
+        .execute(&mut *connection)
+        .await?
+        .rows_affected();
+
+        Ok(rows_affected == 1)
+    }
+
+    async fn search_messages(
+        &self,
+        user_id: &str,
+        terms: &str,
+        limit: i64,
+    ) -> Result<Vec<SearchResult>, Error> {
+        let sql = String::from(
+            r#"
+            SELECT `message`.`id`, `message`.`conversation_id`, `message`.`author`,
+                `message`.`content`, `message`.`created_at`, `message`.`deleted_at`,
+                `message`.`truncated`,
+                `conversation`.`name` AS `conversation_name`,
+                snippet(`message_search`, 0, '<mark>', '</mark>', '...', 16) AS `snippet`
+            FROM `message_search`
+            JOIN `message` ON `message`.`rowid` = `message_search`.`rowid`
+            JOIN `conversation` ON `conversation`.`id` = `message`.`conversation_id`
+            WHERE `message_search` MATCH ?1
+                AND `conversation`.`user_id` = ?2
+                AND `message`.`deleted_at` IS NULL
+                AND `conversation`.`deleted_at` IS NULL
+            ORDER BY `message_search`.`rank`
+            LIMIT ?3
+        "#,
+        );
+
+        let mut connection = self.pool.acquire().await?;
+        sqlx::query(&sql)
+            .bind(SqliteRepository::match_query(terms))
+            .bind(user_id)
+            .bind(limit)
+            .map(|row: SqliteRow| SearchResult {
+                message: SqliteRepository::row_to_message(&row),
+                conversation_name: row.get("conversation_name"),
+                snippet: row.get("snippet"),
+            })
+            .fetch_all(&mut *connection)
+            .await
+    }
+
+    async fn save_user(&self, user: &User, password_hash: &str) -> Result<bool, Error> {
+        let mut connection = self.pool.acquire().await?;
+
+        let rows_affected = sqlx::query(
+            r#"
+            INSERT INTO `user` (`id`, `username`, `password_hash`, `created_at`, `role`)
+            VALUES (?1, ?2, ?3, ?4, ?5)
+        "#,
+        )
+        .bind(&user.id)
+        .bind(&user.username)
+        .bind(password_hash)
+        .bind(user.created_at)
+        .bind(user.role.to_string())
+        .execute(&mut *connection)
+        .await?
+        .rows_affected();
+
+        Ok(rows_affected == 1)
+    }
+
+    async fn get_user_login(&self, username: &str) -> Result<(User, String), Error> {
+        let mut connection = self.pool.acquire().await?;
+
+        sqlx::query(
+            r#"
+            SELECT `user`.`id`, `user`.`username`, `user`.`created_at`, `user`.`role`,
+                `user`.`password_hash`
+            FROM `user`
+            WHERE `username` = ?1
+        "#,
+        )
+        .bind(username)
+        .map(|row: SqliteRow| {
+            (
+                SqliteRepository::row_to_user(&row),
+                row.get("password_hash"),
+            )
+        })
+        .fetch_one(&mut *connection)
+        .await
+    }
+
+    async fn set_user_role(&self, user_id: &str, role: Role) -> Result<bool, Error> {
+        let mut connection = self.pool.acquire().await?;
+
+        let rows_affected = sqlx::query(
+            r#"
+            UPDATE `user`
+            SET `role` = ?1
+            WHERE `id` = ?2
+        "#,
+        )
+        .bind(role.to_string())
+        .bind(user_id)
+        .execute(&mut *connection)
+        .await?
+        .rows_affected();
+
+        Ok(rows_affected == 1)
+    }
+
+    async fn save_session(&self, session: &Session) -> Result<bool, Error> {
+        let mut connection = self.pool.acquire().await?;
+
+        let rows_affected = sqlx::query(
+            r#"
+            INSERT INTO `session` (`id`, `user_id`, `created_at`, `expires_at`)
+            VALUES (?1, ?2, ?3, ?4)
+        "#,
+        )
+        .bind(&session.id)
+        .bind(&session.user_id)
+        .bind(session.created_at)
+        .bind(session.expires_at)
+        .execute(&mut *connection)
+        .await?
+        .rows_affected();
+
+        Ok(rows_affected == 1)
+    }
+
+    async fn get_session_user(&self, session_id: &str) -> Result<User, Error> {
+        let mut connection = self.pool.acquire().await?;
+
+        sqlx::query(
+            r#"
+            SELECT `user`.`id`, `user`.`username`, `user`.`created_at`, `user`.`role`
+            FROM `session`
+            JOIN `user` ON `user`.`id` = `session`.`user_id`
+            WHERE `session`.`id` = ?1 AND `session`.`expires_at` > ?2
+        "#,
+        )
+        .bind(session_id)
+        .bind(Utc::now().timestamp())
+        .map(|row| SqliteRepository::row_to_user(&row))
+        .fetch_one(&mut *connection)
+        .await
+    }
+
+    async fn delete_session(&self, session_id: &str) -> Result<bool, Error> {
+        let mut connection = self.pool.acquire().await?;
+
+        let rows_affected = sqlx::query(
+            r#"
+            DELETE FROM `session`
+            WHERE `id` = ?1
+        "#,
+        )
+        .bind(session_id)
+        .execute(&mut *connection)
+        .await?
+        .rows_affected();
+
+        Ok(rows_affected == 1)
+    }
+
+    async fn save_api_key(&self, api_key: &ApiKey, key_hash: &str) -> Result<bool, Error> {
+        let mut connection = self.pool.acquire().await?;
+
+        let rows_affected = sqlx::query(
+            r#"
+            INSERT INTO `api_key` (`id`, `user_id`, `name`, `scope`, `key_hash`, `created_at`,
+                `last_used_at`)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+        "#,
+        )
+        .bind(&api_key.id)
+        .bind(&api_key.user_id)
+        .bind(&api_key.name)
+        .bind(api_key.scope.to_string())
+        .bind(key_hash)
+        .bind(api_key.created_at)
+        .bind(api_key.last_used_at)
+        .execute(&mut *connection)
+        .await?
+        .rows_affected();
+
+        Ok(rows_affected == 1)
+    }
+
+    async fn get_api_keys(&self, user_id: &str) -> Result<Vec<ApiKey>, Error> {
+        let mut connection = self.pool.acquire().await?;
+
+        sqlx::query(
+            r#"
+            SELECT `id`, `user_id`, `name`, `scope`, `created_at`, `last_used_at`
+            FROM `api_key`
+            WHERE `user_id` = ?1
+            ORDER BY `created_at` ASC, `id` ASC
+        "#,
+        )
+        .bind(user_id)
+        .map(|row| SqliteRepository::row_to_api_key(&row))
+        .fetch_all(&mut *connection)
+        .await
+    }
+
+    async fn use_api_key(&self, key_hash: &str) -> Result<(User, ApiKey), Error> {
+        let key_hash = key_hash.to_string();
+        self.with_transaction(move |connection| {
+            Box::pin(async move {
+                sqlx::query(
+                    r#"
+                    UPDATE `api_key`
+                    SET `last_used_at` = ?1
+                    WHERE `key_hash` = ?2
+                "#,
+                )
+                .bind(Utc::now().timestamp())
+                .bind(&key_hash)
+                .execute(&mut *connection)
+                .await?;
+
+                sqlx::query(
+                    r#"
+                    SELECT `user`.`id`, `user`.`username`, `user`.`created_at`, `user`.`role`,
+                        `api_key`.`id` AS `api_key_id`, `api_key`.`name`, `api_key`.`scope`,
+                        `api_key`.`created_at` AS `api_key_created_at`, `api_key`.`last_used_at`
+                    FROM `api_key`
+                    JOIN `user` ON `user`.`id` = `api_key`.`user_id`
+                    WHERE `api_key`.`key_hash` = ?1
+                "#,
+                )
+                .bind(&key_hash)
+                .map(|row: SqliteRow| {
+                    let user = SqliteRepository::row_to_user(&row);
+                    let api_key = ApiKey {
+                        id: row.get::<String, &str>("api_key_id"),
+                        user_id: user.id.clone(),
+                        name: row.get::<String, &str>("name"),
+                        scope: Scope::from_str(&row.get::<String, &str>("scope")).unwrap(),
+                        created_at: row.get::<i64, &str>("api_key_created_at"),
+                        last_used_at: row.get::<Option<i64>, &str>("last_used_at"),
+                    };
+                    (user, api_key)
+                })
+                .fetch_one(&mut *connection)
+                .await
+            })
+        })
+        .await
+    }
+
+    async fn delete_api_key(&self, user_id: &str, api_key_id: &str) -> Result<bool, Error> {
+        let mut connection = self.pool.acquire().await?;
+
+        let rows_affected = sqlx::query(
+            r#"
+            DELETE FROM `api_key`
+            WHERE `id` = ?1 AND `user_id` = ?2
+        "#,
+        )
+        .bind(api_key_id)
+        .bind(user_id)
+        .execute(&mut *connection)
+        .await?
+        .rows_affected();
+
+        Ok(rows_affected == 1)
+    }
+
+    async fn claim_conversations(&self, anonymous_id: &str, user_id: &str) -> Result<u64, Error> {
+        let anonymous_id = anonymous_id.to_string();
+        let user_id = user_id.to_string();
+        self.with_transaction(move |connection| {
+            Box::pin(async move {
+                let registered: i64 = sqlx::query_scalar(
+                    r#"
+                    SELECT COUNT(*) FROM `user`
+                    WHERE `id` = ?1
+                "#,
+                )
+                .bind(&anonymous_id)
+                .fetch_one(&mut *connection)
+                .await?;
+                if registered > 0 {
+                    return Ok(0);
+                }
+
+                let rows_affected = sqlx::query(
+                    r#"
+                    UPDATE `conversation`
+                    SET `user_id` = ?1
+                    WHERE `user_id` = ?2
+                "#,
+                )
+                .bind(&user_id)
+                .bind(&anonymous_id)
+                .execute(&mut *connection)
+                .await?
+                .rows_affected();
+
+                Ok(rows_affected)
+            })
+        })
+        .await
+    }
+
+    async fn purge_deleted(&self, before: i64) -> Result<PurgeReport, Error> {
+        self.with_transaction(move |connection| {
+            Box::pin(async move {
+                // Messages go first, their conversations can't be removed while they exist
+                let messages = sqlx::query(
+                    r#"
+                    DELETE FROM `message`
+                    WHERE `deleted_at` < ?1
+                        OR `conversation_id` IN (
+                            SELECT `id` FROM `conversation` WHERE `deleted_at` < ?1
+                        )
+                "#,
+                )
+                .bind(before)
+                .execute(&mut *connection)
+                .await?
+                .rows_affected();
+
+                let conversations = sqlx::query(
+                    r#"
+                    DELETE FROM `conversation`
+                    WHERE `deleted_at` < ?1
+                "#,
+                )
+                .bind(before)
+                .execute(&mut *connection)
+                .await?
+                .rows_affected();
+
+                Ok(PurgeReport {
+                    conversations,
+                    messages,
+                })
+            })
+        })
+        .await
+    }
+
+    async fn optimize(&self) -> Result<(), Error> {
+        let mut connection = self.pool.acquire().await?;
+
+        // VACUUM can't run in a transaction, so each statement is sent on its own
+        connection.execute("VACUUM").await?;
+        connection.execute("PRAGMA optimize").await?;
+
+        Ok(())
+    }
+
+    // Set the deleted_at timestamp for a message
+    //
+    // Arguments:
+    // - message_id: The id of the message to "delete"
+    // pub async fn delete_message(&self, message_id: &String) -> Result<bool, Error> {
+    //     let mut connection = self.pool.acquire().await?;
+
+    //     let rows_affected = sqlx::query(r#"
+    //         UPDATE `message`
+    //         SET `deleted_at` = ?1
+    //         WHERE `id` = ?2
+    //     "#)
+    //     .bind(Utc::now().timestamp())
+    //     .bind(message_id)
+    //     .execute(&mut *connection)
+    //     .await?
+    //     .rows_affected();
+
+    //     Ok(rows_affected == 1)
+    // }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    #[sqlx::test]
+    async fn test_db_assert_schema() {
+        // Build test DB and run the assert_schema method
+        let db = SqliteRepository::connect("sqlite::memory:").await.unwrap();
+        db.assert_schema().await.unwrap();
+
+        // Grab a connection from the DB object, and get a list of tables
+        let table_query = r#"
+            SELECT `name`
+            FROM `sqlite_master`
+            WHERE type='table' AND `name` NOT LIKE 'message\_search\_%' ESCAPE '\'
+            ORDER BY `name`
+        "#;
+        let mut connection = db.pool.acquire().await.unwrap();
+        let tables = sqlx::query(table_query)
+            .fetch_all(&mut *connection)
+            .await
+            .unwrap();
+
+        // Compare tables to the expected list, including schema_version, users, sessions, API keys
+        // and the full-text index of messages, without the tables the index keeps its data in
+        assert_eq!(tables.len(), 8);
+        // assert_eq!(tables, vec![("voice",), ("conversation",), ("messages",)]);
+    }
+
+    #[sqlx::test]
+    async fn test_db_assert_schema_can_run_twice() {
+        // Build test DB and run the assert_schema method
+        let db = SqliteRepository::connect("sqlite::memory:").await.unwrap();
+        db.assert_schema().await.unwrap();
+        db.assert_schema().await.unwrap();
+
+        // Grab a connection from the DB object, and get a list of tables
+        let table_query = r#"
+            SELECT `name`
+            FROM `sqlite_master`
+            WHERE type='table' AND `name` NOT LIKE 'message\_search\_%' ESCAPE '\'
+            ORDER BY `name`
+        "#;
+        let mut connection = db.pool.acquire().await.unwrap();
+        let tables = sqlx::query(table_query)
+            .fetch_all(&mut *connection)
+            .await
+            .unwrap();
+
+        // Compare tables to the expected list, including schema_version, users, sessions, API keys
+        // and the full-text index of messages, without the tables the index keeps its data in
+        assert_eq!(tables.len(), 8);
+        // assert_eq!(tables, vec![("voice",), ("conversation",), ("messages",)]);
+    }
+
+    /// The versions recorded in the schema_version table
+    async fn schema_versions(db: &SqliteRepository) -> Vec<i64> {
+        sqlx::query("SELECT `version` FROM `schema_version` ORDER BY `version`")
+            .map(|row: SqliteRow| row.get::<i64, &str>("version"))
+            .fetch_all(&db.pool)
+            .await
+            .unwrap()
+    }
+
+    #[sqlx::test]
+    async fn test_db_migrates_legacy_db() {
+        // A database created before migrations were versioned
+        let db = SqliteRepository::connect("sqlite::memory:").await.unwrap();
+        sqlx::query(include_str!("../../fixtures/legacy_db.sql"))
+            .execute(&db.pool)
+            .await
+            .unwrap();
+
+        db.assert_schema().await.unwrap();
+        assert_eq!(
+            schema_versions(&db).await,
+            vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13]
+        );
+
+        // The old rows are kept, and the new columns can be used
+        let mut voice = db.get_voice("legacy-voice").await.unwrap();
+        assert_eq!(voice.name, "Legacy");
+        assert_eq!(voice.context_budget, None);
+        voice.model_id = Some("small".to_string());
+        voice.generation.temperature = Some(0.5);
+        db.save_voice(&voice).await.unwrap();
+        let voice = db.get_voice("legacy-voice").await.unwrap();
+        assert_eq!(voice.model_id, Some("small".to_string()));
+
+        let messages = db
+            .get_messages("legacy-conversation", false, &Page::default())
+            .await
+            .unwrap()
+            .items;
+        assert_eq!(messages[0].content, "Hello from the past");
+        assert!(!messages[0].truncated);
+
+        // Existing messages are indexed for search
+        let results = db.search_messages("legacy-user", "past", 10).await.unwrap();
+        assert_eq!(results[0].message.id, "legacy-message");
+    }
+
+    #[sqlx::test]
+    async fn test_db_adopts_unversioned_db() {
+        // A database created from the baseline schema, with nothing recorded,
+        // only gets the later migrations
+        let db = SqliteRepository::connect("sqlite::memory:").await.unwrap();
+        for migration in MIGRATIONS.iter().filter(|m| m.version == 1) {
+            sqlx::query(migration.sql).execute(&db.pool).await.unwrap();
+        }
+
+        db.assert_schema().await.unwrap();
+        assert_eq!(
+            schema_versions(&db).await,
+            vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13]
+        );
+    }
+
+    #[sqlx::test]
+    async fn test_db_init() {
+        // create instance and assert schema
+        let db = SqliteRepository::connect("sqlite::memory:").await.unwrap();
+        db.assert_schema().await.unwrap();
+
+        let mut connection = db.pool.acquire().await.unwrap();
+
+        let voice_count_query = r#"
+            SELECT COUNT(*) as count
+            FROM `voice`
+        "#;
+
+        let voice_count = sqlx::query(voice_count_query)
+            .fetch_one(&mut *connection)
+            .await
+            .unwrap()
+            .get::<i64, &str>("count");
+
+        // verify table is empty
+        assert_eq!(voice_count, 0);
+
+        // initialize db
+        db.init().await.unwrap();
+
+        let new_voice_count = sqlx::query(voice_count_query)
+            .fetch_one(&mut *connection)
+            .await
+            .unwrap()
+            .get::<i64, &str>("count");
+
+        // verify table is populated now
+        assert!(new_voice_count > 0);
+    }
+
+    #[sqlx::test]
+    async fn test_db_enforces_references() {
+        let db = SqliteRepository::connect("sqlite::memory:").await.unwrap();
+        db.assert_schema().await.unwrap();
+
+        // A conversation needs an existing voice
+        let conversation = Conversation::new(
+            Uuid::new_v4().to_string(),
+            "Test Conversation".to_string(),
+            "missing-voice".to_string(),
+        );
+        let err = db.save_conversation(&conversation).await.unwrap_err();
+        assert!(err.to_string().contains("conversation.voice_id"));
+
+        // A message needs an existing conversation
+        let message = Message::new(
+            "missing-conversation".to_string(),
+            Author::User,
+            "Hello".to_string(),
+        );
+        let err = db.save_message(&message).await.unwrap_err();
+        assert!(err.to_string().contains("message.conversation_id"));
+    }
+
+    #[sqlx::test]
+    async fn test_db_delete_conversation_deletes_messages() {
+        let db = SqliteRepository::connect("sqlite::memory:").await.unwrap();
+        db.assert_schema().await.unwrap();
+
+        let voice = Voice::new(
+            "Shaun".to_string(),
+            "It's me".to_string(),
+            "I'm boring".to_string(),
+        );
+        db.save_voice(&voice).await.unwrap();
+        let conversation = Conversation::new(
+            Uuid::new_v4().to_string(),
+            "Test Conversation".to_string(),
+            voice.id.clone(),
+        );
+        db.save_conversation(&conversation).await.unwrap();
+        for content in ["One", "Two"] {
+            let message = Message::new(conversation.id.clone(), Author::User, content.to_string());
+            db.save_message(&message).await.unwrap();
+        }
+
+        assert!(db.delete_conversation(&conversation.id).await.unwrap());
+
+        assert!(db
+            .get_messages(&conversation.id, false, &Page::default())
+            .await
+            .unwrap()
+            .items
+            .is_empty());
+        assert_eq!(
+            db.get_messages(&conversation.id, true, &Page::default())
+                .await
+                .unwrap()
+                .items
+                .len(),
+            2
+        );
+    }
+
+    #[sqlx::test]
+    async fn test_db_transaction_rolls_back() {
+        let db = SqliteRepository::connect("sqlite::memory:").await.unwrap();
+        db.assert_schema().await.unwrap();
+
+        let voice = Voice::new(
+            "Shaun".to_string(),
+            "It's me".to_string(),
+            "I'm boring".to_string(),
+        );
+        db.save_voice(&voice).await.unwrap();
+        let conversation = Conversation::new(
+            Uuid::new_v4().to_string(),
+            "Test Conversation".to_string(),
+            voice.id.clone(),
+        );
+        db.save_conversation(&conversation).await.unwrap();
+        let message = Message::new(conversation.id.clone(), Author::User, "Hello".to_string());
+        db.save_message(&message).await.unwrap();
+
+        // The conversation and its messages are deleted, then a later write fails
+        let conversation_id = conversation.id.clone();
+        let result = db
+            .with_transaction(move |connection| {
+                Box::pin(async move {
+                    sqlx::query("UPDATE `conversation` SET `deleted_at` = ?1 WHERE `id` = ?2")
+                        .bind(Utc::now().timestamp())
+                        .bind(&conversation_id)
+                        .execute(&mut *connection)
+                        .await?;
+                    Err::<(), Error>(Error::Protocol("injected failure".to_string()))
+                })
+            })
+            .await;
+        assert!(result.is_err());
+
+        // Nothing of it is kept
+        let fetched = db.get_conversation(&conversation.id).await.unwrap();
+        assert_eq!(fetched.deleted_at, None);
+        assert_eq!(
+            db.get_messages(&conversation.id, false, &Page::default())
+                .await
+                .unwrap()
+                .items
+                .len(),
+            1
+        );
+    }
+
+    #[sqlx::test]
+    async fn test_db_paginates_messages() {
+        let db = SqliteRepository::connect("sqlite::memory:").await.unwrap();
+        db.assert_schema().await.unwrap();
+
+        let voice = Voice::new(
+            "Shaun".to_string(),
+            "It's me".to_string(),
+            "I'm boring".to_string(),
+        );
+        db.save_voice(&voice).await.unwrap();
+        let conversation = Conversation::new(
+            Uuid::new_v4().to_string(),
+            "Test Conversation".to_string(),
+            voice.id.clone(),
+        );
+        db.save_conversation(&conversation).await.unwrap();
+
+        // Saved out of order, the last two at the same time
+        for (content, created_at) in [
+            ("three", 3),
+            ("one", 1),
+            ("four", 4),
+            ("two", 2),
+            ("five", 4),
+        ] {
+            let mut message =
+                Message::new(conversation.id.clone(), Author::User, content.to_string());
+            message.created_at = created_at;
+            db.save_message(&message).await.unwrap();
+        }
+        let contents = |page: &Paged<Message>| {
+            page.items
+                .iter()
+                .map(|message| message.content.clone())
+                .collect::<Vec<_>>()
+        };
+
+        // Every message, in order
+        let all = db
+            .get_messages(&conversation.id, false, &Page::default())
+            .await
+            .unwrap();
+        assert_eq!(all.items.len(), 5);
+        assert_eq!(all.next, None);
+        assert_eq!(contents(&all), ["one", "two", "three", "four", "five"]);
+
+        // Forwards from the start
+        let mut page = Page {
+            limit: Some(2),
+            ..Default::default()
+        };
+        let first = db
+            .get_messages(&conversation.id, false, &page)
+            .await
+            .unwrap();
+        assert_eq!(contents(&first), ["one", "two"]);
+
+        page.after = first.next;
+        let second = db
+            .get_messages(&conversation.id, false, &page)
+            .await
+            .unwrap();
+        assert_eq!(contents(&second), ["three", "four"]);
+
+        page.after = second.next.clone();
+        let last = db
+            .get_messages(&conversation.id, false, &page)
+            .await
+            .unwrap();
+        assert_eq!(contents(&last), ["five"]);
+        assert_eq!(last.next, None);
+
+        // Backwards from the last message, still in order
+        let page = Page {
+            limit: Some(3),
+            before: second.next,
+            after: None,
+        };
+        let previous = db
+            .get_messages(&conversation.id, false, &page)
+            .await
+            .unwrap();
+        assert_eq!(contents(&previous), ["one", "two", "three"]);
+        assert_eq!(previous.next, None);
+
+        let page = Page {
+            limit: Some(2),
+            ..page
+        };
+        let previous = db
+            .get_messages(&conversation.id, false, &page)
+            .await
+            .unwrap();
+        assert_eq!(contents(&previous), ["two", "three"]);
+        // "two" was the fourth message saved
+        assert_eq!(
+            previous.next,
+            Some(Cursor {
+                created_at: 2,
+                seq: 4,
+            })
+        );
+    }
+
+    #[test]
+    fn test_cursor() {
+        let cursor = Cursor {
+            created_at: 1700000000,
+            seq: 42,
+        };
+
+        assert_eq!(cursor.to_string(), "1700000000:42");
+        assert_eq!(Cursor::parse("1700000000:42"), Some(cursor));
+        assert_eq!(Cursor::parse("42"), None);
+        assert_eq!(Cursor::parse("soon:42"), None);
+        assert_eq!(Cursor::parse("1700000000:abc-123"), None);
+    }
+
+    #[sqlx::test]
+    async fn test_db_search_follows_changes() {
+        let db = SqliteRepository::connect("sqlite::memory:").await.unwrap();
+        db.assert_schema().await.unwrap();
+
+        let voice = Voice::new(
+            "Shaun".to_string(),
+            "It's me".to_string(),
+            "I'm boring".to_string(),
+        );
+        db.save_voice(&voice).await.unwrap();
+        let conversation = Conversation::new(
+            Uuid::new_v4().to_string(),
+            "Test Conversation".to_string(),
+            voice.id.clone(),
+        );
+        db.save_conversation(&conversation).await.unwrap();
+        let mut message = Message::new(
+            conversation.id.clone(),
+            Author::User,
+            "Borrowing rules".to_string(),
+        );
+        db.save_message(&message).await.unwrap();
+
+        let search = |terms: &'static str| {
+            let db = db.clone();
+            let user_id = conversation.user_id.clone();
+            async move { db.search_messages(&user_id, terms, 10).await.unwrap() }
+        };
+        assert_eq!(search("borrowing").await.len(), 1);
+
+        // Edited messages are found by their new content only
+        message.content = "Ownership rules".to_string();
+        db.save_message(&message).await.unwrap();
+        assert!(search("borrowing").await.is_empty());
+        assert_eq!(search("ownership").await[0].message, message);
+
+        // Deleted messages are not found
+        message.deleted_at = Some(Utc::now().timestamp());
+        db.save_message(&message).await.unwrap();
+        assert!(search("ownership").await.is_empty());
+    }
+}
@@ -0,0 +1,141 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+/// A flag that tells a generation to stop
+#[derive(Clone, Default)]
+pub struct CancelFlag(Arc<AtomicBool>);
+
+impl CancelFlag {
+    /// Ask the generation to stop
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the generation was asked to stop
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// The replies that are being generated, keyed by conversation id
+///
+/// Only one reply per conversation is generated at a time.
+/// The registry is cheap to clone, every clone shares the same jobs.
+#[derive(Clone, Default)]
+pub struct JobRegistry {
+    jobs: Arc<Mutex<HashMap<String, CancelFlag>>>,
+}
+
+impl JobRegistry {
+    /// Register a generation for a conversation
+    ///
+    /// Returns None if a reply is already being generated for the conversation.
+    /// The generation is unregistered when the returned Job is dropped.
+    ///
+    /// Arguments:
+    /// - conversation_id: The id of the conversation that is replied to
+    pub fn start(&self, conversation_id: &str) -> Option<Job> {
+        let mut jobs = self.jobs.lock().unwrap();
+        if jobs.contains_key(conversation_id) {
+            return None;
+        }
+
+        let flag = CancelFlag::default();
+        jobs.insert(conversation_id.to_string(), flag.clone());
+
+        Some(Job {
+            registry: self.clone(),
+            conversation_id: conversation_id.to_string(),
+            flag,
+        })
+    }
+
+    /// Ask the generation for a conversation to stop
+    ///
+    /// Returns false if no reply is being generated for the conversation
+    ///
+    /// Arguments:
+    /// - conversation_id: The id of the conversation that is replied to
+    pub fn cancel(&self, conversation_id: &str) -> bool {
+        match self.jobs.lock().unwrap().get(conversation_id) {
+            Some(flag) => {
+                flag.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// A registered generation, cancelled and unregistered when dropped
+///
+/// Dropping the job stops the generation that reads its flag,
+/// e.g. when the client that streamed the reply went away.
+pub struct Job {
+    registry: JobRegistry,
+    conversation_id: String,
+    flag: CancelFlag,
+}
+
+impl Job {
+    /// The flag that is set when the generation is cancelled
+    pub fn flag(&self) -> CancelFlag {
+        self.flag.clone()
+    }
+}
+
+impl Drop for Job {
+    fn drop(&mut self) {
+        self.flag.cancel();
+        self.registry
+            .jobs
+            .lock()
+            .unwrap()
+            .remove(&self.conversation_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_one_job_per_conversation() {
+        let registry = JobRegistry::default();
+
+        let job = registry.start("a").unwrap();
+        assert!(registry.start("a").is_none());
+        assert!(registry.start("b").is_some());
+
+        // Dropping the job frees the conversation
+        drop(job);
+        assert!(!registry.cancel("a"));
+        assert!(registry.start("a").is_some());
+    }
+
+    #[test]
+    fn test_cancel() {
+        let registry = JobRegistry::default();
+        assert!(!registry.cancel("a"));
+
+        let job = registry.start("a").unwrap();
+        assert!(!job.flag().is_cancelled());
+        assert!(registry.cancel("a"));
+        assert!(job.flag().is_cancelled());
+    }
+
+    #[test]
+    fn test_drop_cancels() {
+        let registry = JobRegistry::default();
+
+        let job = registry.start("a").unwrap();
+        let flag = job.flag();
+        drop(job);
+        assert!(flag.is_cancelled());
+    }
+}
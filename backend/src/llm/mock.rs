@@ -0,0 +1,267 @@
+use std::{
+    fs,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use models::GenerationSettings;
+use serde::Deserialize;
+
+use super::{Inference, LlmError};
+
+/// The prompt budget of the mock, in tokens
+const MOCK_PROMPT_BUDGET: usize = 2048;
+
+/// A scripted reply, used when the prompt contains `when`
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+pub struct ScriptedReply {
+    /// Text to look for in the prompt
+    pub when: String,
+
+    /// The reply to generate
+    pub reply: String,
+}
+
+/// A script of replies, loaded from a JSON fixture file
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+pub struct Script {
+    /// The reply to generate when no scripted reply matches
+    pub default: String,
+
+    /// The scripted replies
+    #[serde(default)]
+    pub replies: Vec<ScriptedReply>,
+}
+
+/// A deterministic inference backend that does not need a model file
+///
+/// Used for tests and for running the backend offline
+pub enum MockLlm {
+    /// Replies with the prompt itself
+    Echo,
+
+    /// Cycles through a list of canned replies
+    Canned {
+        replies: Vec<String>,
+        next: AtomicUsize,
+    },
+
+    /// Replies from a script, see [`MockLlm::from_script_file`]
+    Scripted(Script),
+}
+
+impl MockLlm {
+    /// Create a mock that cycles through a list of canned replies
+    ///
+    /// Arguments:
+    /// - replies: The replies to cycle through, must not be empty
+    pub fn canned(replies: Vec<String>) -> Self {
+        MockLlm::Canned {
+            replies,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Create a mock from a JSON script fixture
+    ///
+    /// The fixture looks like:
+    /// `{ "default": "...", "replies": [{ "when": "hello", "reply": "Hi!" }] }`
+    ///
+    /// The reply whose `when` text appears latest in the prompt wins,
+    /// so the most recent message decides the reply.
+    ///
+    /// Arguments:
+    /// - path: The path of the fixture file
+    pub fn from_script_file(path: &str) -> Result<Self, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|err| format!("Could not read mock script {}: {}", path, err))?;
+        let script = serde_json::from_str::<Script>(&contents)
+            .map_err(|err| format!("Could not parse mock script {}: {}", path, err))?;
+
+        Ok(MockLlm::Scripted(script))
+    }
+}
+
+impl Inference for MockLlm {
+    // Every whitespace separated word is a token
+    fn count_tokens(&self, text: &str) -> Result<usize, LlmError> {
+        Ok(text.split_whitespace().count())
+    }
+
+    fn prompt_budget(&self, _settings: &GenerationSettings) -> usize {
+        MOCK_PROMPT_BUDGET
+    }
+
+    // Every word of the reply is streamed as a token,
+    // max_tokens is the only setting that is used
+    fn infer(
+        &self,
+        prompt: &str,
+        settings: &GenerationSettings,
+        on_token: &mut dyn FnMut(&str) -> bool,
+    ) -> Result<(), LlmError> {
+        let output = match self {
+            MockLlm::Echo => prompt.to_string(),
+            MockLlm::Canned { replies, next } => {
+                if replies.is_empty() {
+                    return Err(LlmError::Inference("No canned replies".to_string()));
+                }
+                let index = next.fetch_add(1, Ordering::Relaxed) % replies.len();
+                replies[index].clone()
+            }
+            MockLlm::Scripted(script) => script
+                .replies
+                .iter()
+                .filter_map(|scripted| {
+                    prompt
+                        .rfind(&scripted.when)
+                        .map(|position| (position, &scripted.reply))
+                })
+                .max_by_key(|(position, _)| *position)
+                .map(|(_, reply)| reply.clone())
+                .unwrap_or(script.default.clone()),
+        };
+
+        let max_tokens = settings.max_tokens.unwrap_or(i64::MAX) as usize;
+        for token in output.split_inclusive(' ').take(max_tokens) {
+            if !on_token(token) {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mock_echo() {
+        let mock = MockLlm::Echo;
+
+        assert_eq!(
+            mock.generate("Hello", &Default::default(), &[], &mut |_| true)
+                .unwrap(),
+            "Hello"
+        );
+        assert_eq!(
+            mock.generate(
+                "Hello\nUser:",
+                &Default::default(),
+                &["\nUser:".to_string()],
+                &mut |_| true
+            )
+            .unwrap(),
+            "Hello"
+        );
+    }
+
+    #[test]
+    fn test_mock_canned() {
+        let mock = MockLlm::canned(vec!["One".to_string(), "Two".to_string()]);
+
+        assert_eq!(
+            mock.generate("", &Default::default(), &[], &mut |_| true)
+                .unwrap(),
+            "One"
+        );
+        assert_eq!(
+            mock.generate("", &Default::default(), &[], &mut |_| true)
+                .unwrap(),
+            "Two"
+        );
+        assert_eq!(
+            mock.generate("", &Default::default(), &[], &mut |_| true)
+                .unwrap(),
+            "One"
+        );
+    }
+
+    #[test]
+    fn test_mock_scripted() {
+        let mock = MockLlm::from_script_file(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/fixtures/mock_script.json"
+        ))
+        .unwrap();
+
+        assert_eq!(
+            mock.generate("User: hello", &Default::default(), &[], &mut |_| true)
+                .unwrap(),
+            "Hello! How can I help?"
+        );
+        // The latest match wins
+        assert_eq!(
+            mock.generate(
+                "User: hello\nUser: what are lifetimes?",
+                &Default::default(),
+                &[],
+                &mut |_| true
+            )
+            .unwrap(),
+            "Lifetimes tell the compiler how long a reference is valid."
+        );
+        assert_eq!(
+            mock.generate(
+                "User: something else",
+                &Default::default(),
+                &[],
+                &mut |_| true
+            )
+            .unwrap(),
+            "I'm not sure what to say."
+        );
+    }
+
+    #[test]
+    fn test_mock_streams_without_stop_sequences() {
+        let mock = MockLlm::canned(vec!["Hi there<|im_end|> ignored".to_string()]);
+        let mut streamed = Vec::new();
+
+        let output = mock
+            .generate(
+                "",
+                &Default::default(),
+                &["<|im_end|>".to_string()],
+                &mut |text| {
+                    streamed.push(text.to_string());
+                    true
+                },
+            )
+            .unwrap();
+
+        assert_eq!(output, "Hi there");
+        // The text is streamed as it is generated, and nothing of the stop sequence leaks
+        assert_eq!(streamed.concat(), "Hi there");
+        assert_eq!(streamed.first().unwrap(), "Hi ");
+    }
+
+    #[test]
+    fn test_mock_stops_when_asked() {
+        let mock = MockLlm::canned(vec!["one two three".to_string()]);
+        let mut streamed = Vec::new();
+
+        mock.generate("", &Default::default(), &[], &mut |text| {
+            streamed.push(text.to_string());
+            false
+        })
+        .unwrap();
+
+        assert_eq!(streamed, vec!["one "]);
+    }
+
+    #[test]
+    fn test_mock_max_tokens() {
+        let mock = MockLlm::canned(vec!["one two three".to_string()]);
+        let settings = GenerationSettings {
+            max_tokens: Some(2),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            mock.generate("", &settings, &[], &mut |_| true).unwrap(),
+            "one two "
+        );
+    }
+}
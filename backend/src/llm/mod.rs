@@ -0,0 +1,427 @@
+mod mock;
+mod pool;
+mod registry;
+
+use std::{convert::Infallible, fmt, path::Path};
+
+use llm::{
+    InferenceError, LoadProgress, Model, ModelArchitecture, ModelParameters, OutputRequest,
+    TokenUtf8Buffer,
+};
+use models::GenerationSettings;
+
+pub use mock::MockLlm;
+pub use pool::{InferencePool, PoolError};
+pub use registry::{ModelConfig, ModelRegistry, RegistryConfig};
+
+/// A backend that can generate text from a prompt
+///
+/// Implemented by the GGML backed [`Llm`] and the deterministic [`MockLlm`]
+pub trait Inference: Send + Sync {
+    /// Generate raw text for a prompt, one token at a time
+    ///
+    /// Generation stops at the end of the text, after max_tokens tokens
+    /// or when on_token returns false
+    ///
+    /// Arguments:
+    /// - prompt: The prompt to feed the model
+    /// - settings: How to sample the tokens, unset settings use the backend defaults
+    /// - on_token: Called with the text of every generated token
+    fn infer(
+        &self,
+        prompt: &str,
+        settings: &GenerationSettings,
+        on_token: &mut dyn FnMut(&str) -> bool,
+    ) -> Result<(), LlmError>;
+
+    /// Count the tokens a text takes up in the prompt
+    ///
+    /// Arguments:
+    /// - text: The text to tokenize
+    fn count_tokens(&self, text: &str) -> Result<usize, LlmError>;
+
+    /// The number of tokens available for the prompt,
+    /// leaving room in the context window for the reply
+    ///
+    /// Arguments:
+    /// - settings: The settings of the reply, max_tokens is the room left for it
+    fn prompt_budget(&self, settings: &GenerationSettings) -> usize;
+
+    /// Generate a completion for a prompt
+    ///
+    /// Generation stops at the end of the text, when on_text returns false
+    /// or as soon as the output contains one of the stop sequences.
+    /// The stop sequence itself is not included in the output.
+    ///
+    /// Text that could be the start of a stop sequence is held back from on_text
+    /// until it is clear it isn't, so streamed text always matches the output.
+    ///
+    /// Arguments:
+    /// - prompt: The prompt to feed the model
+    /// - settings: How to sample the tokens, unset settings use the backend defaults
+    /// - stop_sequences: Strings that end the generation when produced
+    /// - on_text: Called with each new piece of the output
+    fn generate(
+        &self,
+        prompt: &str,
+        settings: &GenerationSettings,
+        stop_sequences: &[String],
+        on_text: &mut dyn FnMut(&str) -> bool,
+    ) -> Result<String, LlmError> {
+        let mut output = String::new();
+        let mut emitted = 0;
+
+        self.infer(prompt, settings, &mut |token| {
+            output.push_str(token);
+
+            if let Some(index) = stop_sequences
+                .iter()
+                .filter_map(|stop| output.find(stop.as_str()))
+                .min()
+            {
+                output.truncate(index);
+                return false;
+            }
+
+            let safe = output.len() - held_back(&output, stop_sequences);
+            if safe > emitted {
+                let keep_going = on_text(&output[emitted..safe]);
+                emitted = safe;
+                return keep_going;
+            }
+
+            true
+        })?;
+
+        // Flush whatever was held back
+        if output.len() > emitted {
+            on_text(&output[emitted..]);
+        }
+
+        Ok(output)
+    }
+}
+
+/// The length of the longest end of the output that could start a stop sequence
+///
+/// Arguments:
+/// - output: The text generated so far
+/// - stop_sequences: Strings that end the generation when produced
+fn held_back(output: &str, stop_sequences: &[String]) -> usize {
+    stop_sequences
+        .iter()
+        .filter_map(|stop| {
+            (1..stop.len())
+                .rev()
+                .filter(|&length| stop.is_char_boundary(length))
+                .find(|&length| output.ends_with(&stop[..length]))
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// Errors that can occur while generating text
+#[derive(Debug)]
+pub enum LlmError {
+    /// The prompt and generated text no longer fit in the context window
+    ContextFull,
+
+    /// No model with this id is configured
+    UnknownModel(String),
+
+    /// The model could not be loaded
+    Load(String),
+
+    /// The model is not in memory yet
+    NotReady { model_id: String, progress: u8 },
+
+    /// Any other failure reported by the model
+    Inference(String),
+}
+
+impl fmt::Display for LlmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LlmError::ContextFull => f.write_str("The context window is full"),
+            LlmError::UnknownModel(model_id) => write!(f, "Unknown model: {}", model_id),
+            LlmError::Load(message) => write!(f, "Loading the model failed: {}", message),
+            LlmError::NotReady { model_id, progress } => {
+                write!(f, "Model {} is loading, {}% done", model_id, progress)
+            }
+            LlmError::Inference(message) => write!(f, "Inference failed: {}", message),
+        }
+    }
+}
+
+// Convert an InferenceError into an LlmError
+impl From<InferenceError> for LlmError {
+    fn from(error: InferenceError) -> LlmError {
+        match error {
+            InferenceError::ContextFull => LlmError::ContextFull,
+            err => LlmError::Inference(err.to_string()),
+        }
+    }
+}
+
+/// Pick the architecture of a model
+///
+/// GGML model files don't record their architecture,
+/// so when it isn't set it is detected from the file name, e.g. `pythia-1b-q4.bin` is a GPT-NeoX model.
+/// Names that don't tell the architecture apart, like `gpt4all`, need it set explicitly.
+///
+/// MPT models are not supported by the version of `llm` in use,
+/// they are refused with an error rather than loaded as another architecture.
+///
+/// Arguments:
+/// - architecture: The name of the architecture, e.g. llama, gpt2, gptj, neox or bloom.
+///   Detected from the file name when None
+/// - model_path: The path of the model file
+pub fn resolve_architecture(
+    architecture: Option<&str>,
+    model_path: &str,
+) -> Result<ModelArchitecture, LlmError> {
+    let unsupported_mpt = || {
+        LlmError::Load(format!(
+            "Unsupported model architecture mpt for {}, MPT models can't be loaded yet",
+            model_path
+        ))
+    };
+
+    if let Some(architecture) = architecture {
+        if architecture.eq_ignore_ascii_case("mpt") {
+            return Err(unsupported_mpt());
+        }
+        return architecture.parse::<ModelArchitecture>().map_err(|_| {
+            LlmError::Load(format!(
+                "Unsupported model architecture {}, expected one of: {}",
+                architecture,
+                ModelArchitecture::ALL
+                    .iter()
+                    .map(|architecture| architecture.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ))
+        });
+    }
+
+    let file_name = Path::new(model_path)
+        .file_name()
+        .map(|file_name| file_name.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+        .replace(|c: char| !c.is_ascii_alphanumeric(), "");
+
+    if file_name.contains("mpt") {
+        return Err(unsupported_mpt());
+    }
+
+    // NeoX and GPT-J are checked first, their names also contain gpt
+    [
+        (
+            ModelArchitecture::NeoX,
+            &["neox", "pythia", "stablelm", "redpajama"][..],
+        ),
+        (ModelArchitecture::GptJ, &["gptj"][..]),
+        (ModelArchitecture::Gpt2, &["gpt2", "cerebras"][..]),
+        (ModelArchitecture::Bloom, &["bloom"][..]),
+        (
+            ModelArchitecture::Llama,
+            &["llama", "alpaca", "vicuna", "koala", "wizard", "openllama"][..],
+        ),
+    ]
+    .into_iter()
+    .find(|(_, names)| names.iter().any(|name| file_name.contains(name)))
+    .map(|(architecture, _)| architecture)
+    .ok_or(LlmError::Load(format!(
+        "Could not detect the architecture of {}, set it explicitly",
+        model_path
+    )))
+}
+
+/// LLM Wrapper
+pub struct Llm {
+    model: Box<dyn Model>,
+    max_tokens: usize,
+}
+
+impl Llm {
+    /// Create a new instance of the LLM
+    ///
+    /// Arguments:
+    /// - model_path: A path to the modal to load
+    /// - architecture: The architecture of the model, see [`resolve_architecture`]
+    /// - model_config: Configuration for the model
+    /// - max_tokens: The default maximum number of tokens to generate for a reply
+    /// - on_progress: Called with how much of the model is loaded, in percent
+    pub fn new(
+        model_path: &str,
+        architecture: ModelArchitecture,
+        model_config: ModelParameters,
+        max_tokens: usize,
+        on_progress: &mut dyn FnMut(u8),
+    ) -> Result<Self, LlmError> {
+        let model = llm::load_dynamic(
+            architecture,
+            Path::new(model_path),
+            model_config,
+            |progress| match progress {
+                LoadProgress::TensorLoaded {
+                    current_tensor,
+                    tensor_count,
+                } => on_progress(((current_tensor + 1) * 100 / tensor_count.max(1)).min(99) as u8),
+                LoadProgress::Loaded { .. } => on_progress(100),
+                _ => (),
+            },
+        )
+        .map_err(|err| {
+            LlmError::Load(format!(
+                "Could not load {} as a {} model: {}",
+                model_path, architecture, err
+            ))
+        })?;
+
+        Ok(Self { model, max_tokens })
+    }
+
+    /// The maximum number of tokens to generate for a reply
+    ///
+    /// Arguments:
+    /// - settings: The settings of the reply, falls back to the default when unset
+    fn max_tokens(&self, settings: &GenerationSettings) -> usize {
+        settings
+            .max_tokens
+            .map(|max_tokens| max_tokens as usize)
+            .unwrap_or(self.max_tokens)
+    }
+}
+
+impl Inference for Llm {
+    fn count_tokens(&self, text: &str) -> Result<usize, LlmError> {
+        Ok(self.model.vocabulary().tokenize(text, true)?.len())
+    }
+
+    fn prompt_budget(&self, settings: &GenerationSettings) -> usize {
+        self.model
+            .n_context_tokens()
+            .saturating_sub(self.max_tokens(settings))
+    }
+
+    fn infer(
+        &self,
+        prompt: &str,
+        settings: &GenerationSettings,
+        on_token: &mut dyn FnMut(&str) -> bool,
+    ) -> Result<(), LlmError> {
+        let mut session = self.model.start_session(Default::default());
+
+        let mut params = self.model.inference_parameters().clone();
+        if let Some(temperature) = settings.temperature {
+            params.temperature = temperature;
+        }
+        if let Some(top_k) = settings.top_k {
+            params.top_k = top_k as usize;
+        }
+        if let Some(top_p) = settings.top_p {
+            params.top_p = top_p;
+        }
+        if let Some(repeat_penalty) = settings.repeat_penalty {
+            params.repeat_penalty = repeat_penalty;
+        }
+        let params = &params;
+
+        session.feed_prompt(
+            self.model.as_ref(),
+            params,
+            prompt,
+            &mut OutputRequest::default(),
+            |_| Ok::<(), Infallible>(()),
+        )?;
+
+        let mut rng = rand::thread_rng();
+        let mut buffer = TokenUtf8Buffer::new();
+
+        for _ in 0..self.max_tokens(settings) {
+            let token = match session.infer_next_token(
+                self.model.as_ref(),
+                params,
+                &mut OutputRequest::default(),
+                &mut rng,
+            ) {
+                Ok(token) => token,
+                Err(InferenceError::EndOfText) => break,
+                Err(err) => return Err(err.into()),
+            };
+
+            if let Some(text) = buffer.push(token) {
+                if !on_token(&text) {
+                    break;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_architecture() {
+        // A set architecture wins over the file name
+        assert_eq!(
+            resolve_architecture(Some("gpt-neox"), "llama-7b.bin").unwrap(),
+            ModelArchitecture::NeoX
+        );
+        assert!(matches!(
+            resolve_architecture(Some("mamba"), "llama-7b.bin"),
+            Err(LlmError::Load(_))
+        ));
+
+        assert_eq!(
+            resolve_architecture(None, "models/Llama-2-7B-q4_0.bin").unwrap(),
+            ModelArchitecture::Llama
+        );
+        assert_eq!(
+            resolve_architecture(None, "models/gpt-neox-20b.bin").unwrap(),
+            ModelArchitecture::NeoX
+        );
+        assert_eq!(
+            resolve_architecture(None, "models/gpt-j-6b.bin").unwrap(),
+            ModelArchitecture::GptJ
+        );
+        assert_eq!(
+            resolve_architecture(None, "models/gpt2-117m.bin").unwrap(),
+            ModelArchitecture::Gpt2
+        );
+        assert_eq!(
+            resolve_architecture(None, "models/bloomz-560m.bin").unwrap(),
+            ModelArchitecture::Bloom
+        );
+        assert!(matches!(
+            resolve_architecture(None, "models/model.bin"),
+            Err(LlmError::Load(_))
+        ));
+
+        // gpt4all models come in several architectures
+        assert!(matches!(
+            resolve_architecture(None, "models/ggml-gpt4all-j-v1.3-groovy.bin"),
+            Err(LlmError::Load(_))
+        ));
+        assert_eq!(
+            resolve_architecture(Some("gptj"), "models/ggml-gpt4all-j-v1.3-groovy.bin").unwrap(),
+            ModelArchitecture::GptJ
+        );
+
+        // MPT is refused, whether it is set or detected
+        for (architecture, path) in [
+            (Some("mpt"), "models/model.bin"),
+            (None, "models/mpt-7b-instruct-q4.bin"),
+        ] {
+            assert!(matches!(
+                resolve_architecture(architecture, path),
+                Err(LlmError::Load(message)) if message.contains("mpt")
+            ));
+        }
+    }
+}
@@ -0,0 +1,253 @@
+use std::{
+    any::Any,
+    fmt,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        mpsc::{self, Receiver, SyncSender, TrySendError},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use log::{error, info};
+use models::ModelState;
+use tokio::sync::oneshot;
+
+use super::{LlmError, ModelRegistry};
+
+/// A unit of work for a pool worker
+type Task = Box<dyn FnOnce(&ModelRegistry) + Send>;
+
+/// Errors when handing work to the pool
+#[derive(Debug, PartialEq, Eq)]
+pub enum PoolError {
+    /// Every worker is busy and the queue is full
+    QueueFull,
+
+    /// The task panicked, or the worker running it stopped before it was done
+    WorkerLost,
+}
+
+impl fmt::Display for PoolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PoolError::QueueFull => f.write_str("The inference queue is full"),
+            PoolError::WorkerLost => f.write_str("The inference worker stopped"),
+        }
+    }
+}
+
+/// A pool of threads that run inference, off the async runtime
+///
+/// Every worker shares the same registry of loaded models, each generation runs
+/// in its own session. Work waits in a bounded queue until a worker is free.
+pub struct InferencePool {
+    sender: SyncSender<Task>,
+    models: Arc<ModelRegistry>,
+}
+
+impl InferencePool {
+    /// Start the worker threads
+    ///
+    /// Arguments:
+    /// - models: The models the workers share
+    /// - workers: The number of worker threads, at least 1
+    /// - queue_depth: How many tasks can wait for a free worker
+    pub fn new(models: ModelRegistry, workers: usize, queue_depth: usize) -> Self {
+        let (sender, receiver) = mpsc::sync_channel::<Task>(queue_depth);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let models = Arc::new(models);
+
+        for index in 0..workers.max(1) {
+            let models = models.clone();
+            let receiver = receiver.clone();
+            thread::Builder::new()
+                .name(format!("inference-{}", index))
+                .spawn(move || InferencePool::work(models, receiver))
+                .expect("to spawn an inference worker");
+        }
+        info!(
+            "Started {} inference workers with a queue of {}",
+            workers.max(1),
+            queue_depth
+        );
+
+        Self { sender, models }
+    }
+
+    /// The models the workers share
+    pub fn models(&self) -> &ModelRegistry {
+        &self.models
+    }
+
+    /// Check that a model can generate right away
+    ///
+    /// A model that isn't in memory starts loading on a worker,
+    /// callers get NotReady until it is done instead of waiting for it.
+    ///
+    /// Arguments:
+    /// - model_id: The id of the model, None for the default model
+    pub fn ensure_ready(&self, model_id: Option<&str>) -> Result<(), LlmError> {
+        let status = self.models.status(model_id)?;
+
+        match status.state {
+            ModelState::Ready => return Ok(()),
+            ModelState::Loading => (),
+            ModelState::NotLoaded | ModelState::Failed => {
+                // With a full queue, the next caller tries again
+                let model_id = status.id.clone();
+                let _ = self.submit(move |models| {
+                    let _ = models.get(Some(&model_id));
+                });
+            }
+        }
+
+        Err(LlmError::NotReady {
+            model_id: status.id,
+            progress: status.progress,
+        })
+    }
+
+    /// Run a task on a worker and wait for its result
+    ///
+    /// Fails right away with QueueFull if the queue is full
+    ///
+    /// Arguments:
+    /// - task: The work to run with the shared models
+    pub async fn run<T, F>(&self, task: F) -> Result<T, PoolError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&ModelRegistry) -> T + Send + 'static,
+    {
+        let result = self.submit(task)?;
+
+        result.await.map_err(|_| PoolError::WorkerLost)
+    }
+
+    /// Queue a task for a worker
+    ///
+    /// Returns a channel that receives the result of the task
+    ///
+    /// Arguments:
+    /// - task: The work to run with the shared models
+    fn submit<T, F>(&self, task: F) -> Result<oneshot::Receiver<T>, PoolError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&ModelRegistry) -> T + Send + 'static,
+    {
+        let (result_sender, result) = oneshot::channel();
+        let task: Task = Box::new(move |models| {
+            let _ = result_sender.send(task(models));
+        });
+
+        match self.sender.try_send(task) {
+            Ok(()) => Ok(result),
+            Err(TrySendError::Full(_)) => Err(PoolError::QueueFull),
+            Err(TrySendError::Disconnected(_)) => Err(PoolError::WorkerLost),
+        }
+    }
+
+    /// Run tasks until the pool is dropped
+    ///
+    /// Arguments:
+    /// - models: The shared models
+    /// - receiver: The queue of tasks, shared with the other workers
+    fn work(models: Arc<ModelRegistry>, receiver: Arc<Mutex<Receiver<Task>>>) {
+        loop {
+            // The lock is released before the task runs, so another worker can wait for the next one
+            let task = match receiver.lock() {
+                Ok(receiver) => receiver.recv(),
+                Err(_) => {
+                    error!("Inference queue lock poisoned, stopping worker");
+                    return;
+                }
+            };
+
+            // A panicking task fails on its own, the worker goes on to the next one
+            match task {
+                Ok(task) => {
+                    if let Err(panic) =
+                        panic::catch_unwind(AssertUnwindSafe(|| task(models.as_ref())))
+                    {
+                        error!("Inference task panicked: {}", panic_message(&panic));
+                    }
+                }
+                Err(_) => return,
+            }
+        }
+    }
+}
+
+/// The message a panic was raised with, when it has one
+///
+/// Arguments:
+/// - panic: The payload of the panic
+pub fn panic_message(panic: &Box<dyn Any + Send>) -> &str {
+    panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc as std_mpsc;
+
+    use super::*;
+    use crate::llm::MockLlm;
+
+    #[actix_web::test]
+    async fn test_pool_runs_tasks() {
+        let pool = InferencePool::new(ModelRegistry::single(Arc::new(MockLlm::Echo)), 2, 4);
+
+        let output = pool
+            .run(|models| {
+                models
+                    .get(None)
+                    .unwrap()
+                    .generate("Hello", &Default::default(), &[], &mut |_| true)
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(output, "Hello");
+    }
+
+    #[actix_web::test]
+    async fn test_pool_survives_panics() {
+        let pool = InferencePool::new(ModelRegistry::single(Arc::new(MockLlm::Echo)), 1, 4);
+
+        // The panicking task fails, but the only worker keeps going
+        let panicked = pool.run(|_| -> () { panic!("boom") }).await;
+        assert_eq!(panicked, Err(PoolError::WorkerLost));
+
+        assert_eq!(pool.run(|_| "still working").await, Ok("still working"));
+    }
+
+    #[actix_web::test]
+    async fn test_pool_rejects_when_full() {
+        let pool = InferencePool::new(ModelRegistry::single(Arc::new(MockLlm::Echo)), 1, 1);
+
+        // Keep the only worker busy until told otherwise
+        let (started_sender, started) = std_mpsc::channel();
+        let (release, released) = std_mpsc::channel::<()>();
+        let busy = pool
+            .submit(move |_| {
+                started_sender.send(()).unwrap();
+                released.recv().unwrap();
+            })
+            .unwrap();
+        started.recv().unwrap();
+
+        // One task fits in the queue, the next is rejected
+        let queued = pool.submit(|_| "queued").unwrap();
+        assert_eq!(pool.run(|_| ()).await, Err(PoolError::QueueFull));
+
+        // The queued task runs once the worker is free
+        release.send(()).unwrap();
+        busy.await.unwrap();
+        assert_eq!(queued.await.unwrap(), "queued");
+    }
+}
@@ -0,0 +1,586 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    panic::{self, AssertUnwindSafe},
+    sync::{Arc, Mutex},
+};
+
+use log::{error, info, warn};
+use lru::LruCache;
+use models::{ModelState, ModelStatus};
+use serde::Deserialize;
+
+use super::{pool::panic_message, resolve_architecture, Inference, LlmError};
+
+/// The id of the model in a registry with a single model
+const DEFAULT_MODEL_ID: &str = "default";
+
+/// Builds a backend for a model in the registry, reporting the progress in percent
+type Loader = Box<
+    dyn Fn(&ModelConfig, &mut dyn FnMut(u8)) -> Result<Arc<dyn Inference>, LlmError> + Send + Sync,
+>;
+
+fn default_context_tokens() -> usize {
+    2048
+}
+
+/// A model that can be loaded by the registry
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+pub struct ModelConfig {
+    /// The name voices use to pick the model
+    pub id: String,
+
+    /// The path of the model file
+    pub path: String,
+
+    /// The architecture of the model, e.g. llama, gpt2, gptj, neox or bloom.
+    /// Detected from the file name when unset
+    #[serde(default)]
+    pub architecture: Option<String>,
+
+    /// The size of the context window, in tokens
+    #[serde(default = "default_context_tokens")]
+    pub context_tokens: usize,
+}
+
+/// The models a registry can load, read from a TOML file
+///
+/// The file looks like:
+/// ```toml
+/// default = "small"
+/// memory_limit_mb = 8192
+///
+/// [[models]]
+/// id = "small"
+/// path = "models/small.bin"
+/// context_tokens = 2048
+/// ```
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+pub struct RegistryConfig {
+    /// The id of the model used by voices without a model_id, defaults to the first model
+    pub default: Option<String>,
+
+    /// The most memory the loaded models can take up, in megabytes. Unlimited when unset
+    pub memory_limit_mb: Option<u64>,
+
+    /// The models that can be loaded
+    #[serde(default)]
+    pub models: Vec<ModelConfig>,
+}
+
+impl RegistryConfig {
+    /// Read and check a registry config file
+    ///
+    /// Arguments:
+    /// - path: The path of the TOML file
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|err| format!("Could not read model registry {}: {}", path, err))?;
+        let config = toml::from_str::<RegistryConfig>(&contents)
+            .map_err(|err| format!("Could not parse model registry {}: {}", path, err))?;
+        config
+            .validate()
+            .map_err(|err| format!("Invalid model registry {}: {}", path, err))?;
+
+        Ok(config)
+    }
+
+    /// Create a config with a single model, the default
+    ///
+    /// Arguments:
+    /// - path: The path of the model file
+    /// - architecture: The architecture of the model, detected from the file name when None
+    /// - context_tokens: The size of the context window, in tokens
+    pub fn from_model_path(
+        path: String,
+        architecture: Option<String>,
+        context_tokens: usize,
+    ) -> Result<Self, String> {
+        let config = RegistryConfig {
+            default: None,
+            memory_limit_mb: None,
+            models: vec![ModelConfig {
+                id: DEFAULT_MODEL_ID.to_string(),
+                path,
+                architecture,
+                context_tokens,
+            }],
+        };
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    /// Check that the models can be told apart, their architectures are known
+    /// and the default exists
+    fn validate(&self) -> Result<(), String> {
+        if self.models.is_empty() {
+            return Err("No models are configured".to_string());
+        }
+
+        let mut ids = HashSet::new();
+        for model in &self.models {
+            if !ids.insert(model.id.as_str()) {
+                return Err(format!("Model {} is configured twice", model.id));
+            }
+            resolve_architecture(model.architecture.as_deref(), &model.path)
+                .map_err(|err| format!("Model {}: {}", model.id, err))?;
+        }
+
+        match &self.default {
+            Some(default) if !ids.contains(default.as_str()) => {
+                Err(format!("The default model {} is not configured", default))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A model that is in memory
+struct LoadedModel {
+    llm: Arc<dyn Inference>,
+
+    /// The size of the model file, in bytes
+    size: u64,
+}
+
+/// The models voices can reply with, loaded when first used
+///
+/// When loading a model would go over the memory limit,
+/// the least recently used models are unloaded first.
+/// An unloaded model stays in memory until the replies using it are done.
+pub struct ModelRegistry {
+    models: Vec<ModelConfig>,
+    default_id: String,
+
+    /// The memory limit, in bytes
+    memory_limit: Option<u64>,
+    loader: Loader,
+    loaded: Mutex<LruCache<String, LoadedModel>>,
+
+    /// The status of the models that are loading or failed to load
+    pending: Mutex<HashMap<String, ModelStatus>>,
+
+    /// Held while a model loads, so a model is never loaded twice at once
+    loading: Mutex<()>,
+}
+
+impl ModelRegistry {
+    /// Create a registry that loads models on demand
+    ///
+    /// Arguments:
+    /// - config: The models that can be loaded, must be valid
+    /// - loader: Builds the backend for a model
+    pub fn new<F>(config: RegistryConfig, loader: F) -> Self
+    where
+        F: Fn(&ModelConfig, &mut dyn FnMut(u8)) -> Result<Arc<dyn Inference>, LlmError>
+            + Send
+            + Sync
+            + 'static,
+    {
+        let default_id = config
+            .default
+            .or(config.models.first().map(|model| model.id.clone()))
+            .unwrap_or(DEFAULT_MODEL_ID.to_string());
+
+        Self {
+            models: config.models,
+            default_id,
+            memory_limit: config.memory_limit_mb.map(|limit| limit * 1024 * 1024),
+            loader: Box::new(loader),
+            loaded: Mutex::new(LruCache::unbounded()),
+            pending: Mutex::new(HashMap::new()),
+            loading: Mutex::new(()),
+        }
+    }
+
+    /// Create a registry with a single model that is already loaded
+    ///
+    /// Arguments:
+    /// - llm: The backend every voice replies with
+    pub fn single(llm: Arc<dyn Inference>) -> Self {
+        let config = RegistryConfig {
+            default: None,
+            memory_limit_mb: None,
+            models: vec![ModelConfig {
+                id: DEFAULT_MODEL_ID.to_string(),
+                path: String::new(),
+                architecture: None,
+                context_tokens: default_context_tokens(),
+            }],
+        };
+        let registry = ModelRegistry::new(config, |model, _| {
+            Err(LlmError::Load(format!(
+                "Model {} can not be reloaded",
+                model.id
+            )))
+        });
+        registry
+            .loaded
+            .lock()
+            .unwrap()
+            .put(DEFAULT_MODEL_ID.to_string(), LoadedModel { llm, size: 0 });
+
+        registry
+    }
+
+    /// Get a model, loading it if it isn't in memory
+    ///
+    /// Loading blocks, so this should only be called from an inference worker
+    ///
+    /// Arguments:
+    /// - model_id: The id of the model, None for the default model
+    pub fn get(&self, model_id: Option<&str>) -> Result<Arc<dyn Inference>, LlmError> {
+        let model = self.model(model_id)?;
+
+        if let Some(loaded) = self.loaded.lock().unwrap().get(&model.id) {
+            return Ok(loaded.llm.clone());
+        }
+
+        let _loading = self.loading.lock().unwrap();
+        // Another worker may have loaded it while this one waited
+        if let Some(loaded) = self.loaded.lock().unwrap().get(&model.id) {
+            return Ok(loaded.llm.clone());
+        }
+
+        self.set_pending(model, ModelState::Loading, 0, None);
+        match self.load(model) {
+            Ok(llm) => {
+                self.pending.lock().unwrap().remove(&model.id);
+                Ok(llm)
+            }
+            Err(err) => {
+                error!("Loading model {} failed: {}", model.id, err);
+                self.set_pending(model, ModelState::Failed, 0, Some(err.to_string()));
+                Err(err)
+            }
+        }
+    }
+
+    /// The loading status of a model
+    ///
+    /// Arguments:
+    /// - model_id: The id of the model, None for the default model
+    pub fn status(&self, model_id: Option<&str>) -> Result<ModelStatus, LlmError> {
+        Ok(self.model_status(self.model(model_id)?))
+    }
+
+    /// The loading status of every model
+    pub fn statuses(&self) -> Vec<ModelStatus> {
+        self.models
+            .iter()
+            .map(|model| self.model_status(model))
+            .collect()
+    }
+
+    /// Find the config of a model
+    ///
+    /// Arguments:
+    /// - model_id: The id of the model, None for the default model
+    fn model(&self, model_id: Option<&str>) -> Result<&ModelConfig, LlmError> {
+        let model_id = model_id.unwrap_or(&self.default_id);
+
+        self.models
+            .iter()
+            .find(|model| model.id == model_id)
+            .ok_or(LlmError::UnknownModel(model_id.to_string()))
+    }
+
+    /// The loading status of a model
+    ///
+    /// Arguments:
+    /// - model: The config of the model
+    fn model_status(&self, model: &ModelConfig) -> ModelStatus {
+        if self.loaded.lock().unwrap().contains(&model.id) {
+            return ModelStatus {
+                id: model.id.clone(),
+                default: model.id == self.default_id,
+                state: ModelState::Ready,
+                progress: 100,
+                error: None,
+            };
+        }
+
+        self.pending
+            .lock()
+            .unwrap()
+            .get(&model.id)
+            .cloned()
+            .unwrap_or(ModelStatus {
+                id: model.id.clone(),
+                default: model.id == self.default_id,
+                state: ModelState::NotLoaded,
+                progress: 0,
+                error: None,
+            })
+    }
+
+    /// Record the status of a model that is not in memory
+    ///
+    /// Arguments:
+    /// - model: The config of the model
+    /// - state: Where the model is in its life cycle
+    /// - progress: How much of the model is loaded, in percent
+    /// - error: Why loading the model failed
+    fn set_pending(
+        &self,
+        model: &ModelConfig,
+        state: ModelState,
+        progress: u8,
+        error: Option<String>,
+    ) {
+        self.pending.lock().unwrap().insert(
+            model.id.clone(),
+            ModelStatus {
+                id: model.id.clone(),
+                default: model.id == self.default_id,
+                state,
+                progress,
+                error,
+            },
+        );
+    }
+
+    /// Load a model into memory, making room for it first
+    ///
+    /// Arguments:
+    /// - model: The config of the model
+    fn load(&self, model: &ModelConfig) -> Result<Arc<dyn Inference>, LlmError> {
+        let size = fs::metadata(&model.path)
+            .map(|metadata| metadata.len())
+            .unwrap_or(0);
+        if let Some(limit) = self.memory_limit {
+            if size > limit {
+                return Err(LlmError::Load(format!(
+                    "Model {} is larger than the memory limit",
+                    model.id
+                )));
+            }
+            self.make_room(size, limit);
+        }
+
+        info!("Loading model {}: {}", model.id, model.path);
+        // A loader that panics fails the load, rather than leaving the model loading forever
+        let llm = panic::catch_unwind(AssertUnwindSafe(|| {
+            (self.loader)(model, &mut |progress| {
+                self.set_pending(model, ModelState::Loading, progress, None)
+            })
+        }))
+        .unwrap_or_else(|panic| {
+            Err(LlmError::Load(format!(
+                "Loading model {} panicked: {}",
+                model.id,
+                panic_message(&panic)
+            )))
+        })?;
+        self.loaded.lock().unwrap().put(
+            model.id.clone(),
+            LoadedModel {
+                llm: llm.clone(),
+                size,
+            },
+        );
+        info!("Model {} is ready", model.id);
+
+        Ok(llm)
+    }
+
+    /// Unload the least recently used models until a model fits in memory
+    ///
+    /// Arguments:
+    /// - size: The size of the model to make room for, in bytes
+    /// - limit: The memory limit, in bytes
+    fn make_room(&self, size: u64, limit: u64) {
+        let mut loaded = self.loaded.lock().unwrap();
+        let mut used = loaded.iter().map(|(_, model)| model.size).sum::<u64>();
+
+        while used + size > limit {
+            match loaded.pop_lru() {
+                Some((model_id, model)) => {
+                    warn!("Unloading model {} to make room", model_id);
+                    used -= model.size;
+                }
+                None => break,
+            }
+        }
+    }
+
+    /// The ids of the models that are in memory, most recently used first
+    #[cfg(test)]
+    fn loaded_ids(&self) -> Vec<String> {
+        self.loaded
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(model_id, _)| model_id.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::NamedTempFile;
+
+    use super::*;
+    use crate::llm::MockLlm;
+
+    /// Create a model file of a size, in megabytes, that is removed when it is dropped
+    fn model_file(size_mb: u64) -> NamedTempFile {
+        let file = NamedTempFile::new().unwrap();
+        file.as_file().set_len(size_mb * 1024 * 1024).unwrap();
+
+        file
+    }
+
+    /// A registry of mock models that reply with their own id,
+    /// except "broken" which fails to load and "panicking" whose loader panics
+    ///
+    /// Returns the model files along with the registry, keep them until the test is done
+    fn registry(
+        memory_limit_mb: Option<u64>,
+        sizes_mb: &[(&str, u64)],
+    ) -> (ModelRegistry, Vec<NamedTempFile>) {
+        let files = sizes_mb
+            .iter()
+            .map(|(_, size)| model_file(*size))
+            .collect::<Vec<_>>();
+        let config = RegistryConfig {
+            default: None,
+            memory_limit_mb,
+            models: sizes_mb
+                .iter()
+                .zip(&files)
+                .map(|((id, _), file)| ModelConfig {
+                    id: id.to_string(),
+                    path: file.path().to_string_lossy().to_string(),
+                    architecture: None,
+                    context_tokens: default_context_tokens(),
+                })
+                .collect(),
+        };
+
+        let registry = ModelRegistry::new(config, |model, on_progress| {
+            if model.id == "broken" {
+                return Err(LlmError::Load("Not a model file".to_string()));
+            }
+            if model.id == "panicking" {
+                panic!("Corrupt model file");
+            }
+            on_progress(100);
+            Ok(Arc::new(MockLlm::canned(vec![model.id.clone()])))
+        });
+
+        (registry, files)
+    }
+
+    fn reply(registry: &ModelRegistry, model_id: Option<&str>) -> String {
+        registry
+            .get(model_id)
+            .unwrap()
+            .generate("", &Default::default(), &[], &mut |_| true)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_registry_loads_lazily() {
+        let (registry, _files) = registry(None, &[("small", 1), ("large", 2)]);
+        assert!(registry.loaded_ids().is_empty());
+
+        // The first model is the default
+        assert_eq!(reply(&registry, None), "small");
+        assert_eq!(reply(&registry, Some("large")), "large");
+        assert_eq!(registry.loaded_ids(), vec!["large", "small"]);
+
+        assert!(matches!(
+            registry.get(Some("missing")),
+            Err(LlmError::UnknownModel(_))
+        ));
+    }
+
+    #[test]
+    fn test_registry_status() {
+        let (registry, _files) = registry(None, &[("small", 1), ("broken", 1)]);
+
+        let status = registry.status(None).unwrap();
+        assert_eq!(status.id, "small");
+        assert!(status.default);
+        assert_eq!(status.state, ModelState::NotLoaded);
+
+        registry.get(None).unwrap();
+        let status = registry.status(None).unwrap();
+        assert_eq!(status.state, ModelState::Ready);
+        assert_eq!(status.progress, 100);
+
+        assert!(registry.get(Some("broken")).is_err());
+        let status = registry.status(Some("broken")).unwrap();
+        assert_eq!(status.state, ModelState::Failed);
+        assert!(status.error.is_some());
+
+        assert_eq!(registry.statuses().len(), 2);
+    }
+
+    #[test]
+    fn test_registry_survives_panicking_loader() {
+        let (registry, _files) = registry(None, &[("small", 1), ("panicking", 1)]);
+
+        assert!(matches!(
+            registry.get(Some("panicking")),
+            Err(LlmError::Load(_))
+        ));
+        let status = registry.status(Some("panicking")).unwrap();
+        assert_eq!(status.state, ModelState::Failed);
+        assert!(status.error.unwrap().contains("Corrupt model file"));
+
+        // Other models still load
+        assert_eq!(reply(&registry, Some("small")), "small");
+    }
+
+    #[test]
+    fn test_registry_evicts_least_recently_used() {
+        let (registry, _files) = registry(Some(5), &[("a", 2), ("b", 2), ("c", 2), ("huge", 6)]);
+
+        reply(&registry, Some("a"));
+        reply(&registry, Some("b"));
+        reply(&registry, Some("a"));
+        // b was used least recently, so it makes room for c
+        reply(&registry, Some("c"));
+        assert_eq!(registry.loaded_ids(), vec!["c", "a"]);
+
+        assert!(matches!(registry.get(Some("huge")), Err(LlmError::Load(_))));
+    }
+
+    #[test]
+    fn test_registry_config() {
+        let config = toml::from_str::<RegistryConfig>(
+            r#"
+            default = "large"
+            memory_limit_mb = 8192
+
+            [[models]]
+            id = "small"
+            path = "small.bin"
+            architecture = "gpt-neox"
+
+            [[models]]
+            id = "large"
+            path = "llama-2-13b.bin"
+            context_tokens = 4096
+            "#,
+        )
+        .unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.models[0].architecture.as_deref(), Some("gpt-neox"));
+        assert_eq!(config.models[1].context_tokens, 4096);
+
+        let missing_default = RegistryConfig {
+            default: Some("missing".to_string()),
+            ..config.clone()
+        };
+        assert!(missing_default.validate().is_err());
+
+        // The architecture of small.bin can not be detected
+        let mut unknown_architecture = config;
+        unknown_architecture.models[0].architecture = None;
+        assert!(unknown_architecture.validate().is_err());
+    }
+}
@@ -1,19 +1,43 @@
 mod api;
+mod context;
 mod db;
+mod jobs;
 mod llm;
+mod prompt;
+mod purge;
 
-use std::env;
+use std::{env, fmt, io::Error, sync::Arc, time::Duration};
 
+use ::llm::{InferenceParameters, ModelParameters};
 use actix_web::{
     get,
-    middleware::{DefaultHeaders, Logger},
-    web, App, HttpResponse, HttpServer, Responder,
+    middleware::{from_fn, DefaultHeaders, Logger},
+    rt, web, App, HttpResponse, HttpServer, Responder,
+};
+use api::{
+    auth::{authenticate, bootstrap_admin},
+    routes::init_routes,
 };
-use api::routes::init_routes;
 use db::DB;
 use dotenv::dotenv;
 use env_logger::Env;
-use log::info;
+use jobs::JobRegistry;
+use llm::{
+    resolve_architecture, Inference, InferencePool, Llm, MockLlm, ModelConfig, ModelRegistry,
+    RegistryConfig,
+};
+use log::{error, info, warn};
+use prompt::ChatTemplate;
+use purge::{Purger, SECONDS_PER_DAY};
+
+/// Log why the server could not start, as the error main exits with
+///
+/// Arguments:
+/// - err: The reason the server could not start
+fn startup_error(err: impl fmt::Display) -> Error {
+    error!("Startup failed: {}", err);
+    Error::other(err.to_string())
+}
 
 /// A simple hello world endpoint
 ///
@@ -43,22 +67,234 @@ async fn main() -> std::io::Result<()> {
     }
     .unwrap_or(3000);
 
+    let llm_backend = match env::var("LLM_BACKEND") {
+        Ok(s) if !s.is_empty() => s,
+        Ok(_) | Err(_) => "llama".to_string(),
+    };
+
+    let mock_script = match env::var("MOCK_SCRIPT") {
+        Ok(s) if !s.is_empty() => Some(s),
+        Ok(_) | Err(_) => None,
+    };
+
+    let mock_replies = match env::var("MOCK_REPLIES") {
+        Ok(s) if !s.is_empty() => Some(s),
+        Ok(_) | Err(_) => None,
+    };
+
+    let prompt_template = match env::var("PROMPT_TEMPLATE") {
+        Ok(s) if !s.is_empty() => s.parse::<ChatTemplate>().map_err(Error::other)?,
+        Ok(_) | Err(_) => ChatTemplate::default(),
+    };
+
+    let model_registry = match env::var("MODEL_REGISTRY") {
+        Ok(s) if !s.is_empty() => Some(s),
+        Ok(_) | Err(_) => None,
+    };
+
+    let model_path = match env::var("MODEL_PATH") {
+        Ok(s) if !s.is_empty() => Some(s),
+        Ok(_) | Err(_) => None,
+    };
+
+    let model_architecture = match env::var("MODEL_ARCHITECTURE") {
+        Ok(s) if !s.is_empty() => Some(s),
+        Ok(_) | Err(_) => None,
+    };
+
+    let model_context_tokens = match env::var("MODEL_CONTEXT_TOKENS") {
+        Ok(s) if !s.is_empty() => s.parse::<usize>(),
+        Ok(_) | Err(_) => Ok(2048),
+    }
+    .unwrap_or(2048);
+
+    let model_max_tokens = match env::var("MODEL_MAX_TOKENS") {
+        Ok(s) if !s.is_empty() => s.parse::<usize>(),
+        Ok(_) | Err(_) => Ok(256),
+    }
+    .unwrap_or(256);
+
+    let model_threads = match env::var("MODEL_THREADS") {
+        Ok(s) if !s.is_empty() => s.parse::<usize>(),
+        Ok(_) | Err(_) => Ok(8),
+    }
+    .unwrap_or(8);
+
+    let model_prefer_mmap = match env::var("MODEL_PREFER_MMAP") {
+        Ok(s) if !s.is_empty() => s.parse::<bool>(),
+        Ok(_) | Err(_) => Ok(true),
+    }
+    .unwrap_or(true);
+
+    let inference_workers = match env::var("INFERENCE_WORKERS") {
+        Ok(s) if !s.is_empty() => s.parse::<usize>(),
+        Ok(_) | Err(_) => Ok(1),
+    }
+    .unwrap_or(1);
+
+    let inference_queue_depth = match env::var("INFERENCE_QUEUE_DEPTH") {
+        Ok(s) if !s.is_empty() => s.parse::<usize>(),
+        Ok(_) | Err(_) => Ok(8),
+    }
+    .unwrap_or(8);
+
+    let purge_retention_days = match env::var("PURGE_RETENTION_DAYS") {
+        Ok(s) if !s.is_empty() => s.parse::<u64>(),
+        Ok(_) | Err(_) => Ok(30),
+    }
+    .unwrap_or(30);
+
+    let purge_interval_hours = match env::var("PURGE_INTERVAL_HOURS") {
+        Ok(s) if !s.is_empty() => s.parse::<u64>(),
+        Ok(_) | Err(_) => Ok(24),
+    }
+    .unwrap_or(24);
+
+    let admin_username = match env::var("ADMIN_USERNAME") {
+        Ok(s) if !s.is_empty() => Some(s),
+        Ok(_) | Err(_) => None,
+    };
+
     info!("Connecting to database: {}", database_url);
-    let db = DB::new(&database_url).await.unwrap();
-    db.assert_schema().await.unwrap();
-    db.init().await.unwrap();
+    let db = DB::new(&database_url).await.map_err(startup_error)?;
+    db.assert_schema().await.map_err(startup_error)?;
+    db.init().await.map_err(startup_error)?;
+
+    // Admins are named up front and promoted once registered, rather than trusting whoever registers first
+    match &admin_username {
+        Some(username) => match db.get_user_login(username).await {
+            Ok(_) => {
+                if bootstrap_admin(&db, username)
+                    .await
+                    .map_err(startup_error)?
+                {
+                    info!("Made {} an admin", username);
+                }
+            }
+            Err(_) => warn!(
+                "ADMIN_USERNAME {} has not registered, restart once they have to make them an admin",
+                username
+            ),
+        },
+        None => warn!("ADMIN_USERNAME is not set, nobody can become an admin to manage the voices"),
+    }
+
+    // Deleted data is kept for the retention period, then purged in the background
+    let purger = Purger::new(
+        db.clone(),
+        Duration::from_secs(purge_retention_days * SECONDS_PER_DAY),
+    );
+    match purge_interval_hours {
+        0 => warn!("PURGE_INTERVAL_HOURS is 0, deleted data is only purged by POST /admin/purge"),
+        hours => {
+            info!(
+                "Purging data deleted over {} days ago every {} hours",
+                purge_retention_days, hours
+            );
+            rt::spawn(purger.clone().run(Duration::from_secs(hours * 60 * 60)));
+        }
+    }
+
+    // Builds a model of the registry, on an inference worker
+    let loader = move |model: &ModelConfig, on_progress: &mut dyn FnMut(u8)| {
+        let model_config = ModelParameters {
+            prefer_mmap: model_prefer_mmap,
+            n_context_tokens: model.context_tokens,
+            inference_parameters: InferenceParameters {
+                n_threads: model_threads,
+                ..Default::default()
+            },
+        };
+        let architecture = resolve_architecture(model.architecture.as_deref(), &model.path)?;
+        let llm = Llm::new(
+            &model.path,
+            architecture,
+            model_config,
+            model_max_tokens,
+            on_progress,
+        )?;
+        Ok(Arc::new(llm) as Arc<dyn Inference>)
+    };
+
+    let models = match (llm_backend.as_str(), model_registry, model_path) {
+        ("mock", _, _) => {
+            let mock = match (mock_script, mock_replies) {
+                (Some(script), _) => {
+                    info!("Using mock backend with script: {}", script);
+                    MockLlm::from_script_file(&script).map_err(startup_error)?
+                }
+                (None, Some(replies)) => {
+                    info!("Using mock backend with canned replies");
+                    MockLlm::canned(replies.split('|').map(String::from).collect())
+                }
+                (None, None) => {
+                    info!("Using mock backend that echoes prompts");
+                    MockLlm::Echo
+                }
+            };
+            Some(ModelRegistry::single(Arc::new(mock)))
+        }
+        ("llama", Some(model_registry), _) => {
+            info!("Using model registry: {}", model_registry);
+            let config = RegistryConfig::from_file(&model_registry).map_err(startup_error)?;
+            Some(ModelRegistry::new(config, loader))
+        }
+        ("llama", None, Some(model_path)) => {
+            info!("Using model: {}", model_path);
+            let config = RegistryConfig::from_model_path(
+                model_path,
+                model_architecture,
+                model_context_tokens,
+            )
+            .map_err(startup_error)?;
+            Some(ModelRegistry::new(config, loader))
+        }
+        ("llama", None, None) => {
+            warn!("Neither MODEL_REGISTRY nor MODEL_PATH is set, replies are disabled");
+            None
+        }
+        (backend, _, _) => {
+            return Err(startup_error(format!("Unknown LLM_BACKEND: {}", backend)));
+        }
+    };
+
+    // Inference runs on its own threads, so it never blocks the HTTP workers
+    let pool = models.map(|models| {
+        web::Data::new(InferencePool::new(
+            models,
+            inference_workers,
+            inference_queue_depth,
+        ))
+    });
+
+    // The default model loads while the server starts, see GET /models/status
+    if let Some(pool) = &pool {
+        let _ = pool.ensure_ready(None);
+    }
+
+    // Shared by all workers, so a reply can be cancelled from any of them
+    let jobs = JobRegistry::default();
 
     info!(
         "Server starting. Listening on: http://{}:{}",
         http_host, http_port
     );
     HttpServer::new(move || {
-        App::new()
+        let app = App::new()
             .wrap(DefaultHeaders::new().add(("app-version", env!("CARGO_PKG_VERSION"))))
+            .wrap(from_fn(authenticate))
             .wrap(Logger::default())
             .app_data(web::Data::new(db.clone()))
+            .app_data(web::Data::new(prompt_template))
+            .app_data(web::Data::new(jobs.clone()))
+            .app_data(web::Data::new(purger.clone()))
             .configure(init_routes)
-            .service(hello)
+            .service(hello);
+
+        match &pool {
+            Some(pool) => app.app_data(pool.clone()),
+            None => app,
+        }
     })
     .bind((http_host, http_port))?
     .run()
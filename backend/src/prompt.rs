@@ -0,0 +1,351 @@
+use std::str::FromStr;
+
+use models::{Author, Conversation, Message, Voice};
+
+/// The chat template used to turn a conversation into a model prompt
+///
+/// Different model families are fine-tuned on different chat formats,
+/// so the template should match the loaded model.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum ChatTemplate {
+    /// A plain "Name: message" transcript, for base models
+    #[default]
+    Transcript,
+
+    /// Stanford Alpaca `### Instruction:` / `### Response:` blocks
+    Alpaca,
+
+    /// Vicuna v1.1 `USER:` / `ASSISTANT:` turns
+    Vicuna,
+
+    /// OpenAI ChatML `<|im_start|>` / `<|im_end|>` turns
+    ChatMl,
+
+    /// Llama 2 chat `[INST]` / `<<SYS>>` turns
+    Llama2,
+}
+
+impl FromStr for ChatTemplate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "transcript" => Ok(ChatTemplate::Transcript),
+            "alpaca" => Ok(ChatTemplate::Alpaca),
+            "vicuna" => Ok(ChatTemplate::Vicuna),
+            "chatml" => Ok(ChatTemplate::ChatMl),
+            "llama2" | "llama-2" => Ok(ChatTemplate::Llama2),
+            _ => Err(format!("Invalid chat template: {}", s)),
+        }
+    }
+}
+
+/// A run of consecutive messages from the same author
+struct Turn {
+    author: Author,
+    content: String,
+}
+
+impl ChatTemplate {
+    /// Build a prompt that asks the voice for its next message
+    ///
+    /// Arguments:
+    /// - voice: The voice that will answer
+    /// - conversation: The conversation being continued
+    /// - messages: The conversation history, oldest first
+    pub fn build(
+        &self,
+        voice: &Voice,
+        conversation: &Conversation,
+        messages: &[Message],
+    ) -> String {
+        let system = ChatTemplate::system_prompt(voice, conversation);
+        let turns = ChatTemplate::turns(messages);
+
+        match self {
+            ChatTemplate::Transcript => {
+                let mut prompt = format!("{}\n\n", system);
+                for turn in turns {
+                    let speaker = match turn.author {
+                        Author::User => "User",
+                        Author::Voice => voice.name.as_str(),
+                    };
+                    prompt.push_str(&format!("{}: {}\n", speaker, turn.content));
+                }
+                prompt.push_str(&format!("{}:", voice.name));
+
+                prompt
+            }
+            ChatTemplate::Alpaca => {
+                let mut prompt = format!("{}\n\n", system);
+                for turn in turns {
+                    let header = match turn.author {
+                        Author::User => "### Instruction:",
+                        Author::Voice => "### Response:",
+                    };
+                    prompt.push_str(&format!("{}\n{}\n\n", header, turn.content));
+                }
+                prompt.push_str("### Response:\n");
+
+                prompt
+            }
+            ChatTemplate::Vicuna => {
+                let mut prompt = format!("{}\n\n", system);
+                for turn in turns {
+                    match turn.author {
+                        Author::User => prompt.push_str(&format!("USER: {}\n", turn.content)),
+                        Author::Voice => {
+                            prompt.push_str(&format!("ASSISTANT: {}</s>\n", turn.content))
+                        }
+                    }
+                }
+                prompt.push_str("ASSISTANT:");
+
+                prompt
+            }
+            ChatTemplate::ChatMl => {
+                let mut prompt = format!("<|im_start|>system\n{}<|im_end|>\n", system);
+                for turn in turns {
+                    let role = match turn.author {
+                        Author::User => "user",
+                        Author::Voice => "assistant",
+                    };
+                    prompt.push_str(&format!(
+                        "<|im_start|>{}\n{}<|im_end|>\n",
+                        role, turn.content
+                    ));
+                }
+                prompt.push_str("<|im_start|>assistant\n");
+
+                prompt
+            }
+            ChatTemplate::Llama2 => {
+                // The system prompt lives inside the first instruction
+                let mut prompt = String::new();
+                let mut system = Some(format!("<<SYS>>\n{}\n<</SYS>>\n\n", system));
+                let mut open_instruction = false;
+                for turn in turns {
+                    match turn.author {
+                        Author::User => {
+                            prompt.push_str(&format!(
+                                "<s>[INST] {}{} [/INST]",
+                                system.take().unwrap_or_default(),
+                                turn.content
+                            ));
+                            open_instruction = true;
+                        }
+                        Author::Voice => {
+                            if !open_instruction {
+                                prompt.push_str(&format!(
+                                    "<s>[INST] {}[/INST]",
+                                    system.take().unwrap_or_default()
+                                ));
+                            }
+                            prompt.push_str(&format!(" {} </s>", turn.content));
+                            open_instruction = false;
+                        }
+                    }
+                }
+                if !open_instruction {
+                    prompt.push_str(&format!(
+                        "<s>[INST] {}[/INST]",
+                        system.take().unwrap_or_default()
+                    ));
+                }
+
+                prompt
+            }
+        }
+    }
+
+    /// Sequences that mark the start of the next user turn
+    ///
+    /// Generation should stop when the model produces one of these
+    pub fn stop_sequences(&self) -> Vec<String> {
+        match self {
+            ChatTemplate::Transcript => vec!["\nUser:".to_string()],
+            ChatTemplate::Alpaca => vec!["### Instruction:".to_string()],
+            ChatTemplate::Vicuna => vec!["USER:".to_string(), "</s>".to_string()],
+            ChatTemplate::ChatMl => vec!["<|im_end|>".to_string()],
+            ChatTemplate::Llama2 => vec!["[INST]".to_string(), "</s>".to_string()],
+        }
+    }
+
+    /// Build the system prompt from the voice and conversation
+    ///
+    /// Arguments:
+    /// - voice: The voice that will answer
+    /// - conversation: The conversation being continued
+    fn system_prompt(voice: &Voice, conversation: &Conversation) -> String {
+        format!(
+            "You are {}. {}\nThis conversation is called \"{}\".",
+            voice.name, voice.prefix, conversation.name
+        )
+    }
+
+    /// Group consecutive messages from the same author into turns
+    ///
+    /// Arguments:
+    /// - messages: The conversation history, oldest first
+    fn turns(messages: &[Message]) -> Vec<Turn> {
+        let mut turns: Vec<Turn> = Vec::new();
+        for message in messages {
+            match turns.last_mut() {
+                Some(turn) if turn.author == message.author => {
+                    turn.content.push('\n');
+                    turn.content.push_str(&message.content);
+                }
+                _ => turns.push(Turn {
+                    author: message.author.clone(),
+                    content: message.content.clone(),
+                }),
+            }
+        }
+
+        turns
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build a voice, conversation and a short history
+    fn fixture() -> (Voice, Conversation, Vec<Message>) {
+        let voice = Voice::new(
+            "Shaun".to_string(),
+            "It's me".to_string(),
+            "I'm boring.".to_string(),
+        );
+        let conversation = Conversation::new(
+            "user".to_string(),
+            "Greetings".to_string(),
+            voice.id.clone(),
+        );
+        let messages = vec![
+            Message::new(conversation.id.clone(), Author::User, "Hello".to_string()),
+            Message::new(conversation.id.clone(), Author::Voice, "Hi".to_string()),
+            Message::new(conversation.id.clone(), Author::User, "How".to_string()),
+            Message::new(
+                conversation.id.clone(),
+                Author::User,
+                "are you?".to_string(),
+            ),
+        ];
+
+        (voice, conversation, messages)
+    }
+
+    #[test]
+    fn test_template_from_str() {
+        assert_eq!(
+            ChatTemplate::from_str("ChatML").unwrap(),
+            ChatTemplate::ChatMl
+        );
+        assert_eq!(
+            ChatTemplate::from_str("llama-2").unwrap(),
+            ChatTemplate::Llama2
+        );
+        assert!(ChatTemplate::from_str("nope").is_err());
+    }
+
+    #[test]
+    fn test_template_transcript() {
+        let (voice, conversation, messages) = fixture();
+
+        assert_eq!(
+            ChatTemplate::Transcript.build(&voice, &conversation, &messages),
+            "You are Shaun. I'm boring.\nThis conversation is called \"Greetings\".\n\n\
+             User: Hello\n\
+             Shaun: Hi\n\
+             User: How\nare you?\n\
+             Shaun:"
+        );
+    }
+
+    #[test]
+    fn test_template_alpaca() {
+        let (voice, conversation, messages) = fixture();
+
+        assert_eq!(
+            ChatTemplate::Alpaca.build(&voice, &conversation, &messages),
+            "You are Shaun. I'm boring.\nThis conversation is called \"Greetings\".\n\n\
+             ### Instruction:\nHello\n\n\
+             ### Response:\nHi\n\n\
+             ### Instruction:\nHow\nare you?\n\n\
+             ### Response:\n"
+        );
+    }
+
+    #[test]
+    fn test_template_vicuna() {
+        let (voice, conversation, messages) = fixture();
+
+        assert_eq!(
+            ChatTemplate::Vicuna.build(&voice, &conversation, &messages),
+            "You are Shaun. I'm boring.\nThis conversation is called \"Greetings\".\n\n\
+             USER: Hello\n\
+             ASSISTANT: Hi</s>\n\
+             USER: How\nare you?\n\
+             ASSISTANT:"
+        );
+    }
+
+    #[test]
+    fn test_template_chatml() {
+        let (voice, conversation, messages) = fixture();
+
+        assert_eq!(
+            ChatTemplate::ChatMl.build(&voice, &conversation, &messages),
+            "<|im_start|>system\n\
+             You are Shaun. I'm boring.\nThis conversation is called \"Greetings\".<|im_end|>\n\
+             <|im_start|>user\nHello<|im_end|>\n\
+             <|im_start|>assistant\nHi<|im_end|>\n\
+             <|im_start|>user\nHow\nare you?<|im_end|>\n\
+             <|im_start|>assistant\n"
+        );
+    }
+
+    #[test]
+    fn test_template_llama2() {
+        let (voice, conversation, messages) = fixture();
+
+        assert_eq!(
+            ChatTemplate::Llama2.build(&voice, &conversation, &messages),
+            "<s>[INST] <<SYS>>\n\
+             You are Shaun. I'm boring.\nThis conversation is called \"Greetings\".\n\
+             <</SYS>>\n\n\
+             Hello [/INST] Hi </s>\
+             <s>[INST] How\nare you? [/INST]"
+        );
+    }
+
+    #[test]
+    fn test_template_llama2_voice_first() {
+        let (voice, conversation, mut messages) = fixture();
+        messages.remove(0);
+        messages.truncate(1);
+
+        // The voice speaks first, and the voice is asked to speak again
+        assert_eq!(
+            ChatTemplate::Llama2.build(&voice, &conversation, &messages),
+            "<s>[INST] <<SYS>>\n\
+             You are Shaun. I'm boring.\nThis conversation is called \"Greetings\".\n\
+             <</SYS>>\n\n\
+             [/INST] Hi </s>\
+             <s>[INST] [/INST]"
+        );
+    }
+
+    #[test]
+    fn test_template_empty_history() {
+        let (voice, conversation, _) = fixture();
+
+        assert_eq!(
+            ChatTemplate::ChatMl.build(&voice, &conversation, &[]),
+            "<|im_start|>system\n\
+             You are Shaun. I'm boring.\nThis conversation is called \"Greetings\".<|im_end|>\n\
+             <|im_start|>assistant\n"
+        );
+    }
+}
@@ -0,0 +1,218 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use log::{error, info};
+use models::PurgeReport;
+use sqlx::Error;
+use tokio::time::{interval_at, Instant, MissedTickBehavior};
+
+use crate::db::DB;
+
+/// The number of seconds in a day, the unit of the retention period
+pub const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Removes deleted conversations and messages for good,
+/// once they have been deleted for longer than the retention period
+///
+/// The purger is cheap to clone, every clone purges the same database.
+#[derive(Clone)]
+pub struct Purger {
+    db: DB,
+    retention: Duration,
+}
+
+impl Purger {
+    /// Create a purger
+    ///
+    /// Arguments:
+    /// - db: The database to purge
+    /// - retention: How long deleted data is kept before it is removed
+    pub fn new(db: DB, retention: Duration) -> Self {
+        Purger { db, retention }
+    }
+
+    /// Remove the data that was deleted before the retention period,
+    /// then give its space back
+    pub async fn purge(&self) -> Result<PurgeReport, Error> {
+        let before = Utc::now().timestamp() - self.retention.as_secs() as i64;
+        let report = self.db.purge_deleted(before).await?;
+        self.db.optimize().await?;
+
+        info!(
+            "Purged {} conversations and {} messages deleted over {} days ago",
+            report.conversations,
+            report.messages,
+            self.retention.as_secs() / SECONDS_PER_DAY
+        );
+        Ok(report)
+    }
+
+    /// Purge every interval, forever, starting one interval from now
+    ///
+    /// A failed purge is logged and tried again at the next interval
+    ///
+    /// Arguments:
+    /// - every: The time between purges
+    pub async fn run(self, every: Duration) {
+        let mut ticks = interval_at(Instant::now() + every, every);
+        // A purge catches up on everything it missed, so missed ones are not repeated
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            ticks.tick().await;
+            if let Err(err) = self.purge().await {
+                error!("Purge failed: {}", err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use actix_web::rt;
+    use chrono::Utc;
+    use models::{Author, Conversation, Message, PurgeReport, Voice};
+    use tokio::{task::yield_now, time};
+    use uuid::Uuid;
+
+    use super::{Purger, SECONDS_PER_DAY};
+    use crate::db::{Page, DB};
+
+    const DAY: Duration = Duration::from_secs(SECONDS_PER_DAY);
+
+    /// A timestamp some days ago
+    ///
+    /// Arguments:
+    /// - days: How many days ago
+    fn days_ago(days: i64) -> i64 {
+        Utc::now().timestamp() - days * SECONDS_PER_DAY as i64
+    }
+
+    /// Build a test DB with a conversation that was deleted 10 days ago,
+    /// and one that was not deleted
+    async fn setup_db() -> (DB, Conversation, Conversation) {
+        let db = DB::new("sqlite::memory:").await.unwrap();
+        db.assert_schema().await.unwrap();
+
+        let voice = Voice::new(
+            "Shaun".to_string(),
+            "It's me".to_string(),
+            "I'm boring".to_string(),
+        );
+        db.save_voice(&voice).await.unwrap();
+
+        let mut conversations = Vec::new();
+        for name in ["Deleted", "Kept"] {
+            let conversation = Conversation::new(
+                Uuid::new_v4().to_string(),
+                name.to_string(),
+                voice.id.clone(),
+            );
+            db.save_conversation(&conversation).await.unwrap();
+            for content in ["One", "Two"] {
+                let message =
+                    Message::new(conversation.id.clone(), Author::User, content.to_string());
+                db.save_message(&message).await.unwrap();
+            }
+            conversations.push(conversation);
+        }
+        let kept = conversations.pop().unwrap();
+        let mut deleted = conversations.pop().unwrap();
+
+        deleted.deleted_at = Some(days_ago(10));
+        db.save_conversation(&deleted).await.unwrap();
+
+        (db, deleted, kept)
+    }
+
+    /// The number of messages in a conversation, deleted or not
+    ///
+    /// Arguments:
+    /// - db: The database to count in
+    /// - conversation_id: The conversation to count the messages of
+    async fn count_messages(db: &DB, conversation_id: &str) -> usize {
+        db.get_messages(conversation_id, true, &Page::default())
+            .await
+            .unwrap()
+            .items
+            .len()
+    }
+
+    #[actix_web::test]
+    async fn test_purge() {
+        let (db, deleted, kept) = setup_db().await;
+        let mut old = Message::new(kept.id.clone(), Author::User, "Old".to_string());
+        old.deleted_at = Some(days_ago(10));
+        db.save_message(&old).await.unwrap();
+        let mut recent = Message::new(kept.id.clone(), Author::User, "Recent".to_string());
+        recent.deleted_at = Some(days_ago(1));
+        db.save_message(&recent).await.unwrap();
+
+        // Nothing was deleted long enough ago
+        let purger = Purger::new(db.clone(), DAY * 30);
+        assert_eq!(purger.purge().await.unwrap(), PurgeReport::default());
+
+        let purger = Purger::new(db.clone(), DAY * 7);
+        assert_eq!(
+            purger.purge().await.unwrap(),
+            PurgeReport {
+                conversations: 1,
+                messages: 3,
+            }
+        );
+        assert!(db.get_conversation(&deleted.id).await.is_err());
+        assert_eq!(count_messages(&db, &deleted.id).await, 0);
+        assert!(db.get_message(&old.id).await.is_err());
+
+        // Whatever is newer than the retention period stays
+        assert!(db.get_conversation(&kept.id).await.is_ok());
+        assert_eq!(count_messages(&db, &kept.id).await, 3);
+    }
+
+    #[actix_web::test]
+    async fn test_purge_runs_every_interval() {
+        let (db, deleted, mut kept) = setup_db().await;
+
+        time::pause();
+        rt::spawn(Purger::new(db.clone(), DAY * 7).run(DAY));
+        // Let the purger start its interval, before the clock moves
+        yield_now().await;
+        skip(DAY).await;
+        assert!(purged(&db, &deleted.id).await);
+
+        kept.deleted_at = Some(days_ago(10));
+        db.save_conversation(&kept).await.unwrap();
+        time::pause();
+        skip(DAY).await;
+        assert!(purged(&db, &kept.id).await);
+    }
+
+    /// Move the paused clock forward, then let it run again
+    ///
+    /// A paused clock jumps ahead whenever every task waits, which would time out
+    /// the database pool while it waits for SQLite, so queries run on a running clock
+    ///
+    /// Arguments:
+    /// - by: How far to move the clock
+    async fn skip(by: Duration) {
+        time::advance(by).await;
+        time::resume();
+    }
+
+    /// Wait for a purge to remove a conversation, giving up after a while
+    ///
+    /// Arguments:
+    /// - db: The database that is purged
+    /// - conversation_id: The conversation that should be removed
+    async fn purged(db: &DB, conversation_id: &str) -> bool {
+        for _ in 0..100 {
+            if db.get_conversation(conversation_id).await.is_err() {
+                return true;
+            }
+            time::sleep(Duration::from_millis(10)).await;
+        }
+        false
+    }
+}
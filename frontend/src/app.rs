@@ -1,12 +1,12 @@
 mod components;
 
-use leptos::{component, provide_context, view, IntoView, Resource};
+use leptos::{component, create_memo, provide_context, view, IntoView, Resource, Show, SignalGet};
 use leptos_router::{Route, Router, Routes};
 
 use super::store::ChatStore;
 use components::{
-    conversation::ConversationCreate, conversation::ConversationDisplay, sidebar::SidebarDisplay,
-    voice::VoiceListDisplay,
+    auth::LoginForm, conversation::ConversationCreate, conversation::ConversationDisplay,
+    model_status::ModelStatusBanner, sidebar::SidebarDisplay, voice::VoiceListDisplay,
 };
 
 #[component]
@@ -23,26 +23,37 @@ pub fn App() -> impl IntoView {
 
     provide_context(store);
 
+    // Everything but the login form needs a user
+    let logged_out = create_memo(move |_| {
+        store
+            .get()
+            .map(|store| store.user.is_none())
+            .unwrap_or(false)
+    });
+
     view! {
         <Router>
-            <div class="flex flex-row h-full">
-                <div class="basis-1/4 flex flex-col bg-slate-700 text-white">
-                    <SidebarDisplay />
-                </div>
-                <div class="basis-3/4 flex flex-col border-zinc-700 bg-zinc-900 text-white">
-                    <Routes>
-                        <Route path="/conversations" view=|| view! { <p>"Conversation List"</p> } />
-                        <Route path="/conversations/new" view=ConversationCreate />
-                        <Route path="/conversations/:id" view=ConversationDisplay />
-                        <Route path="/conversations/:id/edit" view=|| view! { <p>"Edit Conversation"</p> }/>
-                        <Route path="/voices" view=VoiceListDisplay />
-                        <Route path="/voices/new" view=|| view! { <p>"New Voice"</p> }  />
-                        <Route path="/voices/:id" view=|| view! { <p>"Voice View"</p> } />
-                        <Route path="/voices/:id/edit" view=|| view! { <p>"Edit View"</p> }/>
-                        <Route path="" view=|| view! { <p>"Home View"</p> }/>
-                    </Routes>
+            <Show when=move || !logged_out.get() fallback=LoginForm>
+                <div class="flex flex-row h-full">
+                    <div class="basis-1/4 flex flex-col bg-slate-700 text-white">
+                        <SidebarDisplay />
+                    </div>
+                    <div class="basis-3/4 flex flex-col border-zinc-700 bg-zinc-900 text-white">
+                        <ModelStatusBanner />
+                        <Routes>
+                            <Route path="/conversations" view=|| view! { <p>"Conversation List"</p> } />
+                            <Route path="/conversations/new" view=ConversationCreate />
+                            <Route path="/conversations/:id" view=ConversationDisplay />
+                            <Route path="/conversations/:id/edit" view=|| view! { <p>"Edit Conversation"</p> }/>
+                            <Route path="/voices" view=VoiceListDisplay />
+                            <Route path="/voices/new" view=|| view! { <p>"New Voice"</p> }  />
+                            <Route path="/voices/:id" view=|| view! { <p>"Voice View"</p> } />
+                            <Route path="/voices/:id/edit" view=|| view! { <p>"Edit View"</p> }/>
+                            <Route path="" view=|| view! { <p>"Home View"</p> }/>
+                        </Routes>
+                    </div>
                 </div>
-            </div>
+            </Show>
         </Router>
     }
 }
@@ -0,0 +1,81 @@
+use leptos::{
+    component, create_action, create_signal, ev::SubmitEvent, html::Input, use_context, view,
+    IntoView, NodeRef, Resource, SignalGet, SignalSet, SignalUpdate,
+};
+
+use models::Credentials;
+
+use crate::store::ChatStore;
+
+#[component]
+pub fn LoginForm() -> impl IntoView {
+    let store = use_context::<Resource<(), ChatStore>>().expect("to have store set");
+
+    let username_element: NodeRef<Input> = NodeRef::new();
+    let password_element: NodeRef<Input> = NodeRef::new();
+    // Whether the form registers a new user, instead of logging in
+    let (registering, set_registering) = create_signal(false);
+    let (error, set_error) = create_signal(None::<String>);
+
+    let authenticate = create_action(move |input: &(bool, Credentials)| {
+        let (register, credentials) = input.to_owned();
+        async move {
+            let result = match register {
+                true => ChatStore::register(credentials).await,
+                false => ChatStore::login(credentials).await,
+            };
+            match result {
+                // Load the store again, with the user's conversations
+                Ok(_) => store.refetch(),
+                Err(err) => set_error.set(Some(err.to_string())),
+            }
+        }
+    });
+
+    let on_submit = move |ev: SubmitEvent| {
+        // stop the page from reloading!
+        ev.prevent_default();
+
+        let credentials = Credentials {
+            username: username_element.get().expect("username to exist").value(),
+            password: password_element.get().expect("password to exist").value(),
+        };
+        set_error.set(None);
+        authenticate.dispatch((registering.get(), credentials));
+    };
+
+    view! {
+        <div class="h-full flex justify-center items-center bg-zinc-900 text-white">
+            <form class="w-96 p-5 flex flex-col gap-4 rounded-lg bg-slate-700" on:submit=on_submit>
+                <h2 class="text-2xl">{move || if registering.get() { "Register" } else { "Log in" }}</h2>
+                <input class="p-2 rounded bg-slate-600 text-white" type="text" placeholder="Username" autocomplete="username" node_ref=username_element />
+                <input class="p-2 rounded bg-slate-600 text-white" type="password" placeholder="Password" node_ref=password_element />
+                {move || error.get().map(|error| view! { <p class="text-red-400">{error}</p> })}
+                <button class="p-2 rounded cursor-pointer bg-green-700 hover:bg-green-600 text-white" type="submit">
+                    {move || if registering.get() { "Register" } else { "Log in" }}
+                </button>
+                <p class="text-sm text-center cursor-pointer hover:underline" on:click=move |_| set_registering.update(|registering| *registering = !*registering)>
+                    {move || if registering.get() { "Have an account? Log in" } else { "No account yet? Register" }}
+                </p>
+            </form>
+        </div>
+    }
+}
+
+#[component]
+pub fn LogoutButton() -> impl IntoView {
+    let store = use_context::<Resource<(), ChatStore>>().expect("to have store set");
+
+    let logout = create_action(move |_: &()| async move {
+        let _ = ChatStore::logout().await;
+        store.refetch();
+    });
+
+    view! {
+        <p class="text-sm">
+            {move || store.get().and_then(|store| store.user).map(|user| user.username)}
+            " · "
+            <span class="cursor-pointer hover:underline" on:click=move |_| logout.dispatch(())>"Log out"</span>
+        </p>
+    }
+}
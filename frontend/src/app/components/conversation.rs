@@ -1,17 +1,14 @@
-use std::vec;
-
-use chrono::Utc;
 use leptos::{
-    component, create_action,
+    component, create_action, create_effect, create_signal,
     ev::SubmitEvent,
     html::{Input, Select},
     leptos_dom::logging::console_error,
-    use_context, view, IntoView, NodeRef, Resource, SignalGet, SignalWith,
+    on_cleanup, spawn_local, use_context, view, For, IntoView, NodeRef, Resource, SignalGet,
+    SignalGetUntracked, SignalSet, SignalUpdate, SignalWith,
 };
 use leptos_router::{use_navigate, use_params_map, Route};
-use uuid::Uuid;
 
-use models::{Author, Conversation, Message, Voice};
+use models::{Author, Message, Voice};
 
 use crate::store::ChatStore;
 
@@ -47,74 +44,134 @@ pub fn ConversationDisplay() -> impl IntoView {
     let conversation_id =
         move || params.with(|params| params.get("id").cloned().unwrap_or_default());
 
-    let voice = Voice {
-        id: Uuid::new_v4().to_string(),
-        name: "Shaun".to_string(),
-        description: "It's Shaun".to_string(),
-        prefix: "He's a programmer".to_string(),
-        created_at: 1234,
-        deleted_at: None,
+    let conversation = move || {
+        store
+            .get()
+            .and_then(|s| s.conversations.get(&conversation_id()).cloned())
+    };
+    let voice = move || {
+        store.get().and_then(|s| {
+            let conversation = s.conversations.get(&conversation_id())?;
+            s.voices.get(&conversation.voice_id).cloned()
+        })
     };
 
-    let conversation = Conversation {
-        id: Uuid::new_v4().to_string(),
-        name: "Conversation Name".to_string(),
-        user_id: "1234".to_string(),
-        voice_id: voice.id.clone(),
-        created_at: 1234,
-        deleted_at: None,
+    let (messages, set_messages) = create_signal(Vec::<Message>::new());
+    // The reply as it is being generated
+    let (partial_reply, set_partial_reply) = create_signal(String::new());
+    // The conversation whose reply is being generated
+    let (replying_to, set_replying_to) = create_signal(None::<String>);
+
+    // Reload the messages whenever the conversation changes
+    create_effect(move |_| {
+        let conversation_id = conversation_id();
+        spawn_local(async move {
+            match ChatStore::get_messages(conversation_id).await {
+                Ok(mut fetched) => {
+                    fetched.sort_by_key(|message| message.created_at);
+                    set_messages.set(fetched);
+                }
+                Err(_) => console_error("Could not load messages"),
+            }
+        });
+    });
+
+    let input_element: NodeRef<Input> = NodeRef::new();
+
+    let send_message = create_action(move |input: &(String, String)| {
+        let (conversation_id, content) = input.to_owned();
+        async move {
+            match ChatStore::new_message(conversation_id.clone(), content).await {
+                Ok(message) => set_messages.update(|messages| messages.push(message)),
+                Err(_) => {
+                    console_error("Could not send message");
+                    return;
+                }
+            };
+
+            set_partial_reply.set(String::new());
+            set_replying_to.set(Some(conversation_id.clone()));
+            let reply = ChatStore::stream_reply(conversation_id, |token| {
+                set_partial_reply.update(|partial| partial.push_str(&token))
+            })
+            .await;
+            match reply {
+                Ok(reply) => set_messages.update(|messages| messages.push(reply)),
+                Err(_) => console_error("Could not get a reply"),
+            };
+            set_partial_reply.set(String::new());
+            set_replying_to.set(None);
+        }
+    });
+
+    // Stop the reply, also when leaving the conversation
+    let cancel_reply = move || {
+        if let Some(conversation_id) = replying_to.get_untracked() {
+            spawn_local(async move {
+                let _ = ChatStore::cancel_reply(conversation_id).await;
+            });
+        }
     };
+    on_cleanup(cancel_reply);
+
+    let on_submit = move |ev: SubmitEvent| {
+        // stop the page from reloading!
+        ev.prevent_default();
+
+        let input = input_element.get().expect("input to exist");
+        let content = input.value();
+        if content.trim().is_empty() {
+            return;
+        }
+        input.set_value("");
 
-    let messages = vec![
-        Message {
-            id: Uuid::new_v4().to_string(),
-            conversation_id: conversation.id.clone(),
-            author: Author::User,
-            content: "Hello Bot!".to_string(),
-            created_at: Utc::now().timestamp_millis(),
-            deleted_at: None,
-        },
-        Message {
-            id: Uuid::new_v4().to_string(),
-            conversation_id: conversation.id.clone(),
-            author: Author::Voice,
-            content: "Hello User!".to_string(),
-            created_at: Utc::now().timestamp_millis(),
-            deleted_at: None,
-        },
-    ];
+        send_message.dispatch((conversation_id(), content));
+    };
 
     view! {
         // <!-- Conversation Header -->
         <div class="fixed h-32 w-9/12 top-0 flex flex-col justify-center items-center p-5 border-b bg-zinc-800">
             // <!-- Conversation Info -->
             <div class="">
-                <h2 class="text-2xl">{format!("{}({})", conversation.name, conversation_id())}</h2>
+                <h2 class="text-2xl">
+                    {move || conversation().map(|conversation| conversation.name).unwrap_or_default()}
+                </h2>
             </div>
             // <!-- Voice Info -->
             <div class="">
-                <div class="w-11 inline-flex p-2 mr-1 rounded-full justify-center font-bold border-2 bg-green-500">
-                    {Voice::initials(&voice)}
-                </div>
-                <span>{voice.name}</span>
+                {move || voice().map(|voice| view! {
+                    <div class="w-11 inline-flex p-2 mr-1 rounded-full justify-center font-bold border-2 bg-green-500">
+                        {Voice::initials(&voice)}
+                    </div>
+                    <span>{voice.name}</span>
+                })}
             </div>
         </div>
 
         // <!-- Conversation Messages -->
         <div class="pt-36 pb-24 h-screen flex flex-col overflow-y-auto p-5">
-            {
-                messages.into_iter().map(|message| {
-                    view! {
-                        <MessageItem message />
-                    }
-                }).collect::<Vec<_>>()
-            }
+            <For
+                each=move || messages.get()
+                key=|message| message.id.clone()
+                children=|message| view! { <MessageItem message /> }
+            />
+            {move || send_message.pending().get().then(|| {
+                let partial = partial_reply.get();
+                view! {
+                    <div class={MESSAGE_VOICE_STYLE}>
+                        {if partial.is_empty() { "...".to_string() } else { partial }}
+                    </div>
+                    <button class="self-start px-4 py-1 mb-5 rounded-full cursor-pointer bg-zinc-700 hover:bg-zinc-600 text-white" on:click=move |_| cancel_reply()>
+                        "Stop"
+                    </button>
+                }
+            })}
         </div>
 
         // <!-- Conversation Input -->
         <div class="h-24 w-9/12 fixed bottom-0 flex justify-center items-center p-5 border-t bg-zinc-900 border-zinc-700">
-            <form class="w-full flex justify-center items-center gap-4">
-                <input class="w-2/3 p-4 border rounded-full input-field bg-zinc-700 border-zinc-700 text-white" type="text" placeholder="Ask a question!" />
+            <form class="w-full flex justify-center items-center gap-4" on:submit=on_submit>
+                <input class="w-2/3 p-4 border rounded-full input-field bg-zinc-700 border-zinc-700 text-white" type="text" placeholder="Ask a question!" node_ref=input_element />
                 <button class="h-full p-4 rounded-full cursor-pointer bg-green-700 hover:bg-green-600 text-white" type="submit">
                     <svg xmlns="http://www.w3.org/2000/svg" fill="none" viewBox="0 0 24 24" stroke-width="1.5" stroke="currentColor" class="w-6 h-6">
                         <path stroke-linecap="round" stroke-linejoin="round" d="M4.5 12h15m0 0l-6.75-6.75M19.5 12l-6.75 6.75" />
@@ -159,11 +216,11 @@ pub fn ConversationCreate() -> impl IntoView {
 
         let store = store.get().expect("store to exist");
 
-        let user = store.user_config;
+        let user_id = store.user.map(|user| user.id).unwrap_or_default();
         let name = name_element.get().expect("name to exist").value();
         let voice_id = voice_element.get().expect("voice_id to exist").value();
 
-        create_conversation.dispatch((user.id, name, voice_id));
+        create_conversation.dispatch((user_id, name, voice_id));
     };
 
     view! {
@@ -187,7 +244,8 @@ pub fn ConversationCreate() -> impl IntoView {
                         {move || match store.get() {
                             None => view! { <option>"Loading..."</option> }.into_view(),
                             Some(s) => {
-                                s.voices.into_values().map(|voice| {
+                                // Deleted voices can't start new conversations
+                                s.voices.into_values().filter(|voice| voice.deleted_at.is_none()).map(|voice| {
                                     view! {
                                         <option value={voice.id}>{voice.name}</option>
                                     }
@@ -1,3 +1,5 @@
+pub mod auth;
 pub mod conversation;
+pub mod model_status;
 pub mod sidebar;
 pub mod voice;
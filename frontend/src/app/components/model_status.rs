@@ -0,0 +1,75 @@
+use std::time::Duration;
+
+use leptos::{
+    component, create_signal, on_cleanup, set_interval_with_handle, spawn_local, view, IntoView,
+    SignalGet, SignalGetUntracked, SignalSet,
+};
+
+use models::{ModelState, ModelStatus};
+
+use crate::store::ChatStore;
+
+/// How often the model status is checked while a model is warming up
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Whether a model is not ready yet, but will be
+fn warming_up(status: &ModelStatus) -> bool {
+    match status.state {
+        ModelState::Loading => true,
+        ModelState::NotLoaded => status.default,
+        ModelState::Ready | ModelState::Failed => false,
+    }
+}
+
+#[component]
+pub fn ModelStatusBanner() -> impl IntoView {
+    let (statuses, set_statuses) = create_signal(Vec::<ModelStatus>::new());
+    let (checked, set_checked) = create_signal(false);
+
+    let refresh = move || {
+        spawn_local(async move {
+            if let Ok(model_statuses) = ChatStore::get_model_statuses().await {
+                set_statuses.set(model_statuses);
+            }
+            set_checked.set(true);
+        })
+    };
+    refresh();
+
+    // Only poll while a model is warming up
+    if let Ok(handle) = set_interval_with_handle(
+        move || {
+            if !checked.get_untracked() || statuses.get_untracked().iter().any(warming_up) {
+                refresh();
+            }
+        },
+        POLL_INTERVAL,
+    ) {
+        on_cleanup(move || handle.clear());
+    }
+
+    view! {
+        {move || {
+            let statuses = statuses.get();
+            if let Some(status) = statuses.iter().find(|status| warming_up(status)) {
+                view! {
+                    <div class="p-3 text-center bg-amber-600">
+                        {format!("Warming up the {} model... {}%", status.id, status.progress)}
+                    </div>
+                }
+                .into_view()
+            } else if let Some(status) =
+                statuses.iter().find(|status| status.state == ModelState::Failed)
+            {
+                view! {
+                    <div class="p-3 text-center bg-red-700">
+                        {format!("The {} model failed to load, replies are unavailable", status.id)}
+                    </div>
+                }
+                .into_view()
+            } else {
+                ().into_view()
+            }
+        }}
+    }
+}
@@ -1,7 +1,12 @@
-use leptos::{component, use_context, view, IntoView, Resource, SignalGet};
+use leptos::{
+    component, create_action, create_signal, ev::SubmitEvent, html::Input,
+    leptos_dom::logging::console_error, use_context, view, IntoView, NodeRef, Resource, SignalGet,
+    SignalSet,
+};
 
-use models::{Conversation, Voice};
+use models::{Conversation, SearchResult, Voice};
 
+use super::auth::LogoutButton;
 use crate::store::ChatStore;
 
 #[component]
@@ -17,6 +22,92 @@ pub fn ConversationItem(conversation: Conversation, voice: Voice) -> impl IntoVi
     }
 }
 
+/// Split a search snippet into its parts, and whether each part matched
+///
+/// Arguments:
+/// - snippet: A snippet with the matching words between `<mark>` and `</mark>`
+fn snippet_parts(snippet: &str) -> Vec<(String, bool)> {
+    let mut parts = Vec::new();
+    let mut rest = snippet;
+
+    while let Some((before, after)) = rest.split_once("<mark>") {
+        let (marked, after) = after.split_once("</mark>").unwrap_or((after, ""));
+        parts.push((before.to_string(), false));
+        parts.push((marked.to_string(), true));
+        rest = after;
+    }
+    parts.push((rest.to_string(), false));
+
+    parts
+}
+
+#[component]
+pub fn SearchResultItem(result: SearchResult) -> impl IntoView {
+    view! {
+        // <!-- Search Result Item -->
+        <a href={format!("/conversations/{}", result.message.conversation_id)}>
+            <div class="p-5 border-b cursor-pointer overflow-hidden border-slate-500 hover:bg-slate-600">
+                <p class="font-bold text-ellipsis overflow-hidden">{result.conversation_name}</p>
+                <p class="text-sm">
+                    {snippet_parts(&result.snippet).into_iter().map(|(text, matched)| match matched {
+                        true => view! { <mark>{text}</mark> }.into_view(),
+                        false => text.into_view(),
+                    }).collect::<Vec<_>>()}
+                </p>
+            </div>
+        </a>
+    }
+}
+
+#[component]
+pub fn SearchBox() -> impl IntoView {
+    let search_element: NodeRef<Input> = NodeRef::new();
+    // None until the user searches
+    let (results, set_results) = create_signal(None::<Vec<SearchResult>>);
+
+    let search = create_action(move |terms: &String| {
+        let terms = terms.to_owned();
+        async move {
+            match ChatStore::search_messages(terms).await {
+                Ok(found) => set_results.set(Some(found)),
+                Err(_) => console_error("Could not search the messages"),
+            }
+        }
+    });
+
+    let on_submit = move |ev: SubmitEvent| {
+        // stop the page from reloading!
+        ev.prevent_default();
+
+        let terms = search_element.get().expect("search to exist").value();
+        if terms.trim().is_empty() {
+            set_results.set(None);
+            return;
+        }
+        search.dispatch(terms);
+    };
+
+    view! {
+        <form class="w-full" on:submit=on_submit>
+            <input class="w-full px-4 py-2 rounded-full bg-slate-600 text-white" type="search" placeholder="Search messages" node_ref=search_element />
+        </form>
+        {move || results.get().map(|results| view! {
+            // <!-- Search Results -->
+            <div class="fixed top-32 w-3/12 max-h-96 overflow-y-auto border-b bg-slate-800">
+                <p class="p-2 text-sm text-right cursor-pointer hover:underline" on:click=move |_| set_results.set(None)>
+                    "Clear"
+                </p>
+                {match results.is_empty() {
+                    true => view! { <p class="p-5">"No messages found"</p> }.into_view(),
+                    false => results.into_iter().map(|result| view! {
+                        <SearchResultItem result />
+                    }).collect::<Vec<_>>().into_view(),
+                }}
+            </div>
+        })}
+    }
+}
+
 #[component]
 pub fn SidebarDisplay() -> impl IntoView {
     let store = use_context::<Resource<(), ChatStore>>().expect("to have store set");
@@ -24,8 +115,10 @@ pub fn SidebarDisplay() -> impl IntoView {
     view! {
         // <!-- Sidebar -->
         // <!-- Title -->
-        <div class="fixed top-0 h-32 w-3/12 p-5 flex justify-center items-center border-b bg-slate-700">
+        <div class="fixed top-0 h-32 w-3/12 p-5 flex flex-col gap-3 justify-center items-center border-b bg-slate-700">
             <h2 class="text-2xl">{"Conversations"}</h2>
+            <LogoutButton />
+            <SearchBox />
         </div>
 
         // <!-- Conversation List -->
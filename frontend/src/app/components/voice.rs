@@ -57,7 +57,7 @@ pub fn VoiceListDisplay() -> impl IntoView {
             {move || match store.get() {
                 None => view! { <p>"Loading..."</p> }.into_view(),
                 Some(s) => {
-                    s.voices.into_values().map(|voice| {
+                    s.voices.into_values().filter(|voice| voice.deleted_at.is_none()).map(|voice| {
                         view! {
                             <VoiceItem voice />
                         }
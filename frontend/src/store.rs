@@ -1,24 +1,31 @@
+use futures::{stream, StreamExt};
 use gloo::{
-    net::{http::Request, Error},
+    net::{eventsource::futures::EventSource, http::Request, Error},
     storage::{LocalStorage, Storage},
 };
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::collections::HashMap;
-use uuid::Uuid;
 
-use models::{Conversation, JsonApiResponse, Message, Voice};
+use models::{
+    Author, Claim, ClaimReport, Conversation, Credentials, JsonApiResponse, Message, ModelStatus,
+    Reply, ReplyToken, SearchResult, User, Voice,
+};
 
 #[derive(PartialEq, Eq, Serialize, Default, Deserialize, Clone, Debug)]
 pub struct UserConfig {
     pub id: String,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ChatStore {
     pub voices: HashMap<String, Voice>,
     pub conversations: HashMap<String, Conversation>,
     pub messages: Vec<Message>,
-    pub user_config: UserConfig,
+    /// The anonymous user the browser made before there were accounts,
+    /// until their conversations are claimed
+    pub user_config: Option<UserConfig>,
+    /// The logged in user, None until they log in
+    pub user: Option<User>,
 }
 
 const LS_USER_ID_KEY: &str = "rusty_chat_user_config";
@@ -30,25 +37,111 @@ impl ChatStore {
             voices: HashMap::new(),
             conversations: HashMap::new(),
             messages: Vec::new(),
-            user_config: UserConfig::default(),
+            user_config: None,
+            user: None,
         }
     }
 
     /// Initialize the chat store
-    /// This will initialize the user config, then fetch voices and conversations
+    /// This will read the anonymous user config, then fetch the logged in user, voices and
+    /// their conversations
     pub async fn init(&mut self) {
-        self.user_config = Self::init_user_config();
+        self.user_config = Self::anonymous_user_config();
+        self.user = Self::get_current_user().await.unwrap_or(None);
         if let Ok(voices) = Self::get_voices().await {
             self.voices = voices;
         };
-        if let Ok(conversations) = Self::get_conversations(self.user_config.id.clone()).await {
-            self.conversations = conversations;
-        };
+        if self.user.is_some() {
+            // The first login claims the conversations of the anonymous user,
+            // the config is kept to try again if it fails
+            if let Some(user_config) = &self.user_config {
+                if Self::claim_conversations(user_config.id.clone())
+                    .await
+                    .is_ok()
+                {
+                    LocalStorage::delete(LS_USER_ID_KEY);
+                    self.user_config = None;
+                }
+            }
+            if let Ok(conversations) = Self::get_conversations().await {
+                self.conversations = conversations;
+            };
+        }
+    }
+
+    /// Fetch the logged in user from the API, None if nobody is logged in
+    pub async fn get_current_user() -> Result<Option<User>, Error> {
+        let resp = Request::get("/api/auth/me").send().await?;
+        if !resp.ok() {
+            return Ok(None);
+        }
+
+        let resp = resp.json::<JsonApiResponse<User>>().await?;
+        Ok(resp.data.and_then(|users| users.into_iter().next()))
+    }
+
+    /// Register a new user, which logs them in
+    pub async fn register(credentials: Credentials) -> Result<User, Error> {
+        Self::authenticate("/api/auth/register", credentials).await
+    }
+
+    /// Log a user in
+    pub async fn login(credentials: Credentials) -> Result<User, Error> {
+        Self::authenticate("/api/auth/login", credentials).await
+    }
+
+    /// Send credentials to the API, the session cookie it answers with logs the user in
+    ///
+    /// Arguments:
+    /// - url: The url to send them to
+    /// - credentials: The username and password of the user
+    async fn authenticate(url: &str, credentials: Credentials) -> Result<User, Error> {
+        let resp = Request::post(url).json(&credentials)?.send().await?;
+        if !resp.ok() {
+            let resp = resp.json::<JsonApiResponse<String>>().await?;
+            return Err(Error::GlooError(resp.errors.unwrap_or_default().join(", ")));
+        }
+
+        let resp = resp.json::<JsonApiResponse<User>>().await?;
+        resp.data
+            .and_then(|users| users.into_iter().next())
+            .ok_or_else(|| Error::GlooError("The API did not answer with the user".to_string()))
+    }
+
+    /// Log the user out
+    pub async fn logout() -> Result<(), Error> {
+        Request::post("/api/auth/logout").send().await?;
+
+        Ok(())
+    }
+
+    /// Move the conversations of the anonymous user to the logged in user
+    ///
+    /// Arguments:
+    /// - anonymous_id: The id the browser generated for the anonymous user
+    pub async fn claim_conversations(anonymous_id: String) -> Result<ClaimReport, Error> {
+        let resp = Request::post("/api/auth/claim")
+            .json(&Claim { anonymous_id })?
+            .send()
+            .await?;
+        if !resp.ok() {
+            let resp = resp.json::<JsonApiResponse<String>>().await?;
+            return Err(Error::GlooError(resp.errors.unwrap_or_default().join(", ")));
+        }
+
+        let resp = resp.json::<JsonApiResponse<ClaimReport>>().await?;
+        Ok(resp
+            .data
+            .and_then(|reports| reports.into_iter().next())
+            .unwrap_or_default())
     }
 
     /// Fetch voices from the API
+    ///
+    /// Deleted voices are fetched too, so their conversations can still be shown
     pub async fn get_voices() -> Result<HashMap<String, Voice>, Error> {
         let resp = Request::get("/api/voices")
+            .query([("include_deleted", "true")])
             .send()
             .await?
             .json::<JsonApiResponse<Voice>>()
@@ -62,19 +155,12 @@ impl ChatStore {
         Ok(voice_map)
     }
 
-    /// Fetch conversations from the API
-    pub async fn get_conversations(
-        user_id: String,
-    ) -> Result<HashMap<String, Conversation>, Error> {
-        let resp = Request::get("/api/conversations")
-            .query([("user_id", user_id)])
-            .send()
-            .await?
-            .json::<JsonApiResponse<Conversation>>()
-            .await?;
+    /// Fetch the conversations of the logged in user from the API
+    pub async fn get_conversations() -> Result<HashMap<String, Conversation>, Error> {
+        let conversations = Self::get_all_pages::<Conversation>("/api/conversations", None).await?;
 
         let mut conversation_map = HashMap::new();
-        for conversation in resp.data.unwrap() {
+        for conversation in conversations {
             conversation_map.insert(conversation.id.clone(), conversation);
         }
 
@@ -87,7 +173,7 @@ impl ChatStore {
         voice_id: String,
     ) -> Result<Conversation, Error> {
         let conversation = Conversation::new(user_id, name, voice_id);
-        let resp = Request::post("/api/conversations")
+        Request::post("/api/conversations")
             .json(&conversation)?
             .send()
             .await?
@@ -98,31 +184,128 @@ impl ChatStore {
     }
 
     pub async fn get_messages(conversation_id: String) -> Result<Vec<Message>, Error> {
-        let resp = Request::get("/api/messages")
-            .query([("conversation_id", conversation_id)])
+        Self::get_all_pages("/api/messages", Some(("conversation_id", conversation_id))).await
+    }
+
+    /// Fetch every page of a paginated list from the API, oldest first
+    ///
+    /// Arguments:
+    /// - url: The url of the list
+    /// - filter: The query parameter that selects the list, if it needs one
+    async fn get_all_pages<T: DeserializeOwned>(
+        url: &str,
+        filter: Option<(&str, String)>,
+    ) -> Result<Vec<T>, Error> {
+        let mut items = Vec::new();
+        let mut after: Option<String> = None;
+
+        loop {
+            let mut query: Vec<_> = filter.iter().cloned().collect();
+            if let Some(cursor) = after {
+                query.push(("after", cursor));
+            }
+            let resp = Request::get(url)
+                .query(query)
+                .send()
+                .await?
+                .json::<JsonApiResponse<T>>()
+                .await?;
+
+            items.extend(resp.data.unwrap_or_default());
+            after = resp.meta.and_then(|meta| meta.next_cursor);
+            if after.is_none() {
+                return Ok(items);
+            }
+        }
+    }
+
+    /// Search the messages of the logged in user's conversations
+    ///
+    /// Arguments:
+    /// - terms: The words to look for
+    pub async fn search_messages(terms: String) -> Result<Vec<SearchResult>, Error> {
+        let resp = Request::get("/api/search")
+            .query([("q", terms)])
+            .send()
+            .await?
+            .json::<JsonApiResponse<SearchResult>>()
+            .await?;
+
+        Ok(resp.data.unwrap_or_default())
+    }
+
+    /// Send a new user message to a conversation
+    pub async fn new_message(conversation_id: String, content: String) -> Result<Message, Error> {
+        let message = Message::new(conversation_id, Author::User, content);
+        Request::post("/api/messages")
+            .json(&message)?
             .send()
             .await?
             .json::<JsonApiResponse<Message>>()
             .await?;
 
-        Ok(match resp.data {
-            Some(messages) => messages,
-            None => Vec::new(),
-        })
+        Ok(message)
     }
 
-    fn init_user_config() -> UserConfig {
-        // Get user id, if it doesn't exist, create a new user id and store it
-        let default_user_config = UserConfig {
-            id: Uuid::new_v4().to_string(),
-        };
+    /// Ask the voice of a conversation to reply, receiving the reply as it is generated
+    ///
+    /// Arguments:
+    /// - conversation_id: The id of the conversation to reply to
+    /// - on_token: Called with each piece of the reply as it arrives
+    pub async fn stream_reply(
+        conversation_id: String,
+        mut on_token: impl FnMut(String),
+    ) -> Result<Message, Error> {
+        // The EventSource is closed when it is dropped,
+        // otherwise the browser would reconnect and ask for another reply
+        let mut source = EventSource::new(&format!(
+            "/api/conversations/{}/reply/stream",
+            conversation_id
+        ))
+        .map_err(Error::JsError)?;
+        let tokens = source.subscribe("token").map_err(Error::JsError)?;
+        let done = source.subscribe("done").map_err(Error::JsError)?;
+        let failure = source.subscribe("failure").map_err(Error::JsError)?;
+        let mut events = stream::select(tokens, stream::select(done, failure));
 
-        match LocalStorage::get::<UserConfig>(LS_USER_ID_KEY) {
-            Ok(uc) => uc,
-            Err(_) => {
-                let _ = LocalStorage::set(LS_USER_ID_KEY, default_user_config.clone());
-                default_user_config
+        while let Some(event) = events.next().await {
+            let (event_type, event) = event.map_err(|err| Error::GlooError(err.to_string()))?;
+            let data = event.data().as_string().unwrap_or_default();
+            match event_type.as_str() {
+                "token" => on_token(serde_json::from_str::<ReplyToken>(&data)?.content),
+                "done" => return Ok(serde_json::from_str::<Reply>(&data)?.message),
+                _ => {
+                    let resp = serde_json::from_str::<JsonApiResponse<String>>(&data)?;
+                    return Err(Error::GlooError(resp.errors.unwrap_or_default().join(", ")));
+                }
             }
         }
+
+        Err(Error::GlooError("The reply stream ended early".to_string()))
+    }
+
+    /// Stop the reply that is being generated, what was generated so far is kept
+    pub async fn cancel_reply(conversation_id: String) -> Result<(), Error> {
+        Request::delete(&format!("/api/conversations/{}/reply", conversation_id))
+            .send()
+            .await?;
+
+        Ok(())
+    }
+
+    /// Fetch the loading status of the models from the API
+    pub async fn get_model_statuses() -> Result<Vec<ModelStatus>, Error> {
+        let resp = Request::get("/api/models/status")
+            .send()
+            .await?
+            .json::<JsonApiResponse<ModelStatus>>()
+            .await?;
+
+        Ok(resp.data.unwrap_or_default())
+    }
+
+    /// The anonymous user the browser made before there were accounts, if it made one
+    fn anonymous_user_config() -> Option<UserConfig> {
+        LocalStorage::get::<UserConfig>(LS_USER_ID_KEY).ok()
     }
 }
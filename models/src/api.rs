@@ -9,6 +9,16 @@ pub struct JsonApiResponse<T> {
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub errors: Option<Vec<T>>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub meta: Option<Meta>,
+}
+
+/// Information about a response beside its data
+#[derive(Debug, Default, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Meta {
+    /// The cursor of the next page of a paginated list, None on the last page
+    pub next_cursor: Option<String>,
 }
 
 /// Factory methods to create a success JSON API Response and a failure JSON API Response
@@ -23,6 +33,7 @@ impl<T> JsonApiResponse<T> {
             data: Some(data),
             message: message.unwrap_or("OK".to_string()),
             errors: None,
+            meta: None,
         }
     }
 
@@ -36,6 +47,16 @@ impl<T> JsonApiResponse<T> {
             data: None,
             message: message.unwrap_or("NOT OK".to_string()),
             errors: Some(errors),
+            meta: None,
         }
     }
+
+    /// Add a meta section to the response
+    ///
+    /// Arguments:
+    /// - meta: The information to include beside the data
+    pub fn with_meta(mut self, meta: Meta) -> Self {
+        self.meta = Some(meta);
+        self
+    }
 }
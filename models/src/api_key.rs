@@ -0,0 +1,89 @@
+use std::str::FromStr;
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use strum::Display;
+use uuid::Uuid;
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Display, Serialize, Deserialize)]
+#[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+/// What an API key is allowed to do
+pub enum Scope {
+    /// Reads conversations, messages and voices, without changing anything
+    Read,
+
+    /// Also chats, everything its user can do except managing API keys
+    Chat,
+}
+
+impl FromStr for Scope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "read" => Ok(Scope::Read),
+            "chat" => Ok(Scope::Chat),
+            _ => Err(format!("Invalid scope: {}", s)),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+/// A key that scripts use instead of logging in, acting as its user
+pub struct ApiKey {
+    /// ID of the API key
+    pub id: String,
+
+    /// ID of the user the key acts as. Reference to User.id
+    pub user_id: String,
+
+    /// What the key is for, to tell keys apart
+    pub name: String,
+
+    /// What the key is allowed to do
+    pub scope: Scope,
+
+    /// Unix Timestamp of when the key was created
+    pub created_at: i64,
+
+    /// Unix Timestamp of when the key was last used
+    pub last_used_at: Option<i64>,
+}
+
+impl ApiKey {
+    /// Create a new ApiKey that auto-generates the ID and created_at timestamp
+    pub fn new(user_id: String, name: String, scope: Scope) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            user_id,
+            name,
+            scope,
+            created_at: Utc::now().timestamp(),
+            last_used_at: None,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+/// Asks for a new API key for the logged in user
+pub struct NewApiKey {
+    /// What the key is for
+    pub name: String,
+
+    /// What the key is allowed to do
+    pub scope: Scope,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+/// A new API key, along with the key itself
+///
+/// The key is only ever shown here, only its hash is stored.
+/// Not Debug, so the key can't end up in a log
+pub struct CreatedApiKey {
+    /// The stored API key
+    pub api_key: ApiKey,
+
+    /// The key to send in the `Authorization: Bearer` header
+    pub token: String,
+}
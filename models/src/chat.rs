@@ -0,0 +1,34 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{GenerationSettings, Message, Reply, ReplyToken};
+
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+/// A frame of the WebSocket chat protocol, sent as JSON text
+///
+/// Every frame carries its kind in a `type` field, e.g.
+/// `{ "type": "user_message", "content": "Hello" }`
+pub enum ChatFrame {
+    /// Sent by the client: a new user message, the voice replies to it.
+    /// The settings override the voice's generation settings for this reply
+    UserMessage {
+        content: String,
+        #[serde(default)]
+        settings: GenerationSettings,
+    },
+
+    /// Sent by the client: stop generating the current reply
+    Cancel,
+
+    /// Sent by the server: the user message was saved
+    Message(Message),
+
+    /// Sent by the server: a piece of the reply, as it is generated
+    Token(ReplyToken),
+
+    /// Sent by the server: the reply is done and saved
+    Done(Reply),
+
+    /// Sent by the server: the last frame could not be handled
+    Error { errors: Vec<String> },
+}
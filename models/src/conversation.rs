@@ -8,7 +8,9 @@ pub struct Conversation {
     /// ID of the conversation
     pub id: String,
 
-    /// ID of the user involved in the conversation
+    /// ID of the user involved in the conversation,
+    /// the API sets it to the logged in user
+    #[serde(default)]
     pub user_id: String,
 
     /// A name for the conversation
@@ -0,0 +1,172 @@
+use serde::{Deserialize, Serialize};
+
+/// The most stop sequences a voice or request can set
+const MAX_STOP_SEQUENCES: usize = 8;
+
+/// The most tokens a reply can ask for, so one request can't keep a worker busy for long.
+/// Replies are also limited by the model's context, which has to fit the prompt too
+const MAX_TOKENS: i64 = 4096;
+
+#[derive(PartialEq, Debug, Clone, Default, Serialize, Deserialize)]
+/// Settings that control how a reply is sampled from the model
+///
+/// Every setting is optional, unset settings fall back to the model defaults
+pub struct GenerationSettings {
+    /// Randomness of the sampling, between 0 and 2. Higher is more random
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+
+    /// Only the top K most likely tokens are sampled from, at least 1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<i64>,
+
+    /// Only the most likely tokens up to this cumulative probability are sampled from,
+    /// above 0 and at most 1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+
+    /// Penalty for repeating tokens, between 0 and 2. Higher repeats less
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repeat_penalty: Option<f32>,
+
+    /// The maximum number of tokens to generate for a reply, between 1 and 4096
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<i64>,
+
+    /// Strings that end the reply when generated, on top of the chat template's
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_sequences: Option<Vec<String>>,
+}
+
+impl GenerationSettings {
+    /// Check that every setting is within its range
+    ///
+    /// Returns a message for every setting that is not
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+
+        if let Some(temperature) = self.temperature {
+            if !(0.0..=2.0).contains(&temperature) {
+                errors.push("temperature must be between 0 and 2".to_string());
+            }
+        }
+        if let Some(top_k) = self.top_k {
+            if top_k < 1 {
+                errors.push("top_k must be at least 1".to_string());
+            }
+        }
+        if let Some(top_p) = self.top_p {
+            if !(top_p > 0.0 && top_p <= 1.0) {
+                errors.push("top_p must be above 0 and at most 1".to_string());
+            }
+        }
+        if let Some(repeat_penalty) = self.repeat_penalty {
+            if !(0.0..=2.0).contains(&repeat_penalty) {
+                errors.push("repeat_penalty must be between 0 and 2".to_string());
+            }
+        }
+        if let Some(max_tokens) = self.max_tokens {
+            if !(1..=MAX_TOKENS).contains(&max_tokens) {
+                errors.push(format!("max_tokens must be between 1 and {}", MAX_TOKENS));
+            }
+        }
+        if let Some(stop_sequences) = &self.stop_sequences {
+            if stop_sequences.len() > MAX_STOP_SEQUENCES {
+                errors.push(format!(
+                    "stop_sequences can have at most {} entries",
+                    MAX_STOP_SEQUENCES
+                ));
+            }
+            if stop_sequences.iter().any(|stop| stop.is_empty()) {
+                errors.push("stop_sequences can not contain empty strings".to_string());
+            }
+        }
+
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(errors),
+        }
+    }
+
+    /// Combine these settings with overrides, the overrides win where they are set
+    ///
+    /// Arguments:
+    /// - overrides: The settings that take precedence
+    pub fn with_overrides(&self, overrides: &GenerationSettings) -> GenerationSettings {
+        GenerationSettings {
+            temperature: overrides.temperature.or(self.temperature),
+            top_k: overrides.top_k.or(self.top_k),
+            top_p: overrides.top_p.or(self.top_p),
+            repeat_penalty: overrides.repeat_penalty.or(self.repeat_penalty),
+            max_tokens: overrides.max_tokens.or(self.max_tokens),
+            stop_sequences: overrides
+                .stop_sequences
+                .clone()
+                .or(self.stop_sequences.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(GenerationSettings::default().validate(), Ok(()));
+
+        let valid = GenerationSettings {
+            temperature: Some(2.0),
+            top_k: Some(1),
+            top_p: Some(1.0),
+            repeat_penalty: Some(0.0),
+            max_tokens: Some(MAX_TOKENS),
+            stop_sequences: Some(vec!["User:".to_string()]),
+        };
+        assert_eq!(valid.validate(), Ok(()));
+
+        let invalid = GenerationSettings {
+            temperature: Some(2.1),
+            top_k: Some(0),
+            top_p: Some(0.0),
+            repeat_penalty: Some(-0.1),
+            max_tokens: Some(MAX_TOKENS + 1),
+            stop_sequences: Some(vec![String::new(); MAX_STOP_SEQUENCES + 1]),
+        };
+        assert_eq!(
+            invalid.validate(),
+            Err(vec![
+                "temperature must be between 0 and 2".to_string(),
+                "top_k must be at least 1".to_string(),
+                "top_p must be above 0 and at most 1".to_string(),
+                "repeat_penalty must be between 0 and 2".to_string(),
+                "max_tokens must be between 1 and 4096".to_string(),
+                "stop_sequences can have at most 8 entries".to_string(),
+                "stop_sequences can not contain empty strings".to_string(),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_validate_max_tokens() {
+        for (max_tokens, valid) in [
+            (0, false),
+            (1, true),
+            (256, true),
+            (MAX_TOKENS, true),
+            (MAX_TOKENS + 1, false),
+            (i64::MAX, false),
+        ] {
+            let settings = GenerationSettings {
+                max_tokens: Some(max_tokens),
+                ..Default::default()
+            };
+            assert_eq!(
+                settings.validate().is_ok(),
+                valid,
+                "max_tokens {}",
+                max_tokens
+            );
+        }
+    }
+}
@@ -1,10 +1,36 @@
 mod api;
+mod api_key;
+mod chat;
 mod conversation;
+mod generation;
 mod message;
+mod model_status;
+mod purge;
+mod reply;
+mod search;
+mod user;
 mod voice;
 
 pub use api::JsonApiResponse;
+pub use api::Meta;
+pub use api_key::ApiKey;
+pub use api_key::CreatedApiKey;
+pub use api_key::NewApiKey;
+pub use api_key::Scope;
+pub use chat::ChatFrame;
 pub use conversation::Conversation;
+pub use generation::GenerationSettings;
 pub use message::Author;
 pub use message::Message;
+pub use model_status::ModelState;
+pub use model_status::ModelStatus;
+pub use purge::PurgeReport;
+pub use reply::Reply;
+pub use reply::ReplyToken;
+pub use search::SearchResult;
+pub use user::Claim;
+pub use user::ClaimReport;
+pub use user::Credentials;
+pub use user::Role;
+pub use user::User;
 pub use voice::Voice;
@@ -7,7 +7,6 @@ use uuid::Uuid;
 
 #[derive(PartialEq, Eq, Debug, Clone, Display, Serialize, Deserialize)]
 #[strum(serialize_all = "lowercase")]
-
 pub enum Author {
     User,
     Voice,
@@ -45,6 +44,10 @@ pub struct Message {
 
     /// Unix Timestamp of when the message was deleted
     pub deleted_at: Option<i64>,
+
+    /// Whether the reply was cancelled before it was finished
+    #[serde(default)]
+    pub truncated: bool,
 }
 
 impl Message {
@@ -57,6 +60,7 @@ impl Message {
             content,
             created_at: Utc::now().timestamp(),
             deleted_at: None,
+            truncated: false,
         }
     }
 }
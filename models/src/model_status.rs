@@ -0,0 +1,37 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+/// Where a model is in its life cycle
+pub enum ModelState {
+    /// The model is not in memory, it is loaded when a voice replies with it
+    NotLoaded,
+
+    /// The model is being loaded
+    Loading,
+
+    /// The model is loaded and can generate replies
+    Ready,
+
+    /// Loading the model failed, it is tried again when a voice replies with it
+    Failed,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+/// The loading status of a model
+pub struct ModelStatus {
+    /// ID of the model, as used by a voice's model_id
+    pub id: String,
+
+    /// Whether voices without a model_id use this model
+    pub default: bool,
+
+    /// Where the model is in its life cycle
+    pub state: ModelState,
+
+    /// How much of the model is loaded, in percent
+    pub progress: u8,
+
+    /// Why loading the model failed
+    pub error: Option<String>,
+}
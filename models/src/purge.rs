@@ -0,0 +1,11 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(PartialEq, Eq, Debug, Clone, Default, Serialize, Deserialize)]
+/// The rows a purge of deleted data removed for good
+pub struct PurgeReport {
+    /// The number of conversations removed
+    pub conversations: u64,
+
+    /// The number of messages removed
+    pub messages: u64,
+}
@@ -0,0 +1,24 @@
+use serde::{Deserialize, Serialize};
+
+use crate::Message;
+
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+/// A reply generated by the voice of a conversation
+pub struct Reply {
+    /// The saved reply message
+    pub message: Message,
+
+    /// IDs of the messages that were left out of the prompt to fit the context window
+    pub excluded_message_ids: Vec<String>,
+
+    /// Whether the generation was cancelled before the reply was complete
+    #[serde(default)]
+    pub truncated: bool,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+/// A piece of a reply, streamed while the voice is generating it
+pub struct ReplyToken {
+    /// The generated text
+    pub content: String,
+}
@@ -0,0 +1,17 @@
+use serde::{Deserialize, Serialize};
+
+use crate::Message;
+
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+/// A message that matched a search
+pub struct SearchResult {
+    /// The matching message
+    pub message: Message,
+
+    /// The name of the conversation the message is in
+    pub conversation_name: String,
+
+    /// The part of the message that matched,
+    /// with the matching words between `<mark>` and `</mark>`
+    pub snippet: String,
+}
@@ -0,0 +1,88 @@
+use std::str::FromStr;
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use strum::Display;
+use uuid::Uuid;
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default, Display, Serialize, Deserialize)]
+#[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+/// What a user is allowed to do
+pub enum Role {
+    /// Chats with the voices
+    #[default]
+    User,
+
+    /// Also manages the voices, users and stored data
+    Admin,
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "user" => Ok(Role::User),
+            "admin" => Ok(Role::Admin),
+            _ => Err(format!("Invalid role: {}", s)),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize, Default)]
+/// Represents a registered user
+pub struct User {
+    /// ID of the user
+    pub id: String,
+
+    /// The name the user logs in with, unique
+    pub username: String,
+
+    /// Unix Timestamp of when the user registered
+    pub created_at: i64,
+
+    /// What the user is allowed to do
+    #[serde(default)]
+    pub role: Role,
+}
+
+impl User {
+    /// Create a new User that auto-generates the ID and created_at timestamp
+    pub fn new(username: String) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            username,
+            created_at: Utc::now().timestamp(),
+            role: Role::User,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+/// What a user registers and logs in with
+///
+/// Not Debug, so the password can't end up in a log
+pub struct Credentials {
+    /// The name of the user
+    pub username: String,
+
+    /// The password of the user, in plain text
+    pub password: String,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+/// Asks for the conversations of an anonymous user to be moved to the logged in user
+///
+/// Before there were accounts, the browser generated a user id and kept it in LocalStorage
+pub struct Claim {
+    /// The id the browser generated
+    pub anonymous_id: String,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Default, Serialize, Deserialize)]
+/// What a claim moved to the logged in user
+pub struct ClaimReport {
+    /// The number of conversations moved
+    pub conversations: u64,
+}
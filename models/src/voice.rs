@@ -2,7 +2,9 @@ use chrono::Utc;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+use crate::GenerationSettings;
+
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
 /// A voice is a description of the responder in the conversation
 pub struct Voice {
     /// ID of the voice
@@ -17,6 +19,19 @@ pub struct Voice {
     /// The LLM prefix description of the voice, used in the prompt
     pub prefix: String,
 
+    /// The maximum number of prompt tokens for this voice.
+    /// Older messages are left out of the prompt to fit. None uses the model limit
+    #[serde(default)]
+    pub context_budget: Option<i64>,
+
+    /// How replies of the voice are sampled, can be overridden per reply
+    #[serde(default)]
+    pub generation: GenerationSettings,
+
+    /// The id of the model that generates the replies of the voice. None uses the default model
+    #[serde(default)]
+    pub model_id: Option<String>,
+
     /// Unix Timestamp of when the voice was created
     pub created_at: i64,
 
@@ -32,6 +47,9 @@ impl Voice {
             name,
             description,
             prefix,
+            context_budget: None,
+            generation: GenerationSettings::default(),
+            model_id: None,
             created_at: Utc::now().timestamp(),
             deleted_at: None,
         }